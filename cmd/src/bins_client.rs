@@ -1,6 +1,6 @@
 use clap::{Arg, ArgMatches, Command, Parser};
 use cmd::client_cmds::{app_commands, match_storage_cmds, print_result, repl};
-use lab::lab2;
+use lab::lab3;
 use log::LevelFilter;
 use tribbler::{
     config::{Config, DEFAULT_CONFIG_LOCATION},
@@ -28,8 +28,8 @@ async fn main() -> TribResult<()> {
     let args = Options::parse();
     env_logger::builder().filter_level(args.log).init();
     let cfg = Config::read(Some(&args.config))?;
-    let addrs = cfg.backs;
-    let bc = lab2::new_bin_client(addrs).await?;
+    let bc =
+        lab3::new_bin_client_with_placement(cfg.backs, cfg.locks, cfg.replicas, cfg.vnodes).await?;
     let app = Command::new("bin-client")
         .subcommands(app_commands())
         .subcommands(bin_cmd());
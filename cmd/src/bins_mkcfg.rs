@@ -22,6 +22,21 @@ struct Options {
     /// number of keepers
     #[clap(short, long, default_value = "1")]
     keeps: usize,
+    /// number of lock servers
+    #[clap(long, default_value = "1")]
+    locks: usize,
+    /// number of backends each bin is kept on
+    #[clap(long, default_value = "2")]
+    replicas: usize,
+    /// number of points each backend takes on the bin placement ring
+    #[clap(long, default_value = "64")]
+    vnodes: usize,
+    /// number of keys per second a migration copies, 0 for no limit
+    #[clap(long, default_value = "0")]
+    migration_rate: u64,
+    /// number of batches of keys a migration copies at once
+    #[clap(long, default_value = "4")]
+    migration_concurrency: usize,
     /// location to write the config file. Use `-` for stdout
     #[clap(long, default_value = DEFAULT_CONFIG_LOCATION)]
     file: String,
@@ -41,6 +56,18 @@ fn main() -> TribResult<()> {
         eprintln!("too many keepers: {}. Must be <= 10", args.keeps);
         process::exit(1)
     }
+    if args.replicas == 0 {
+        eprintln!("replicas must be at least 1");
+        process::exit(1)
+    }
+    if args.vnodes == 0 {
+        eprintln!("vnodes must be at least 1");
+        process::exit(1)
+    }
+    if args.migration_concurrency == 0 {
+        eprintln!("migration concurrency must be at least 1");
+        process::exit(1)
+    }
 
     let mut p = 3000;
     if !args.fix {
@@ -49,6 +76,7 @@ fn main() -> TribResult<()> {
 
     let mut backs = vec![];
     let mut keepers = vec![];
+    let mut locks = vec![];
     for i in 0..args.backs {
         backs.push(format!("{}:{}", args.ip[i % args.ip.len()], p));
         p += 1;
@@ -59,7 +87,20 @@ fn main() -> TribResult<()> {
         p += 1;
     }
 
-    let cfg = config::Config { backs, keepers };
+    for i in 0..args.locks {
+        locks.push(format!("{}:{}", args.ip[i % args.ip.len()], p));
+        p += 1;
+    }
+
+    let cfg = config::Config {
+        backs,
+        keepers,
+        locks,
+        replicas: args.replicas,
+        vnodes: args.vnodes,
+        migration_rate: args.migration_rate,
+        migration_concurrency: args.migration_concurrency,
+    };
 
     cfg.write(Some(&args.file))
 }
@@ -7,7 +7,7 @@ use std::{
     time::Duration,
 };
 
-use lab::{lab1, lab2};
+use lab::{lab1, lab2, lab3};
 use log::{error, info, warn, LevelFilter};
 use tokio::join;
 use tribbler::{addr, config::Config, err::TribResult, storage::MemStorage};
@@ -16,6 +16,7 @@ use tribbler::{addr, config::Config, err::TribResult, storage::MemStorage};
 pub enum ProcessType {
     Back,
     Keep,
+    Lock,
 }
 
 pub async fn main(
@@ -29,7 +30,13 @@ pub async fn main(
         .default_format()
         .filter_level(log_level)
         .init();
-    let config = Arc::new(Config::read(Some(&cfg))?);
+    let mut config = Config::read(Some(&cfg))?;
+    if let ProcessType::Lock = t {
+        // configs without lock servers keep using the ones of `config.env`,
+        // the same ones the bin clients fall back to
+        config.locks = lab3::lock_servers_or_default(config.locks);
+    }
+    let config = Arc::new(config);
 
     // println!("{:?}", config);
     let (tx, rdy) = mpsc::channel();
@@ -38,6 +45,7 @@ pub async fn main(
     let it = match t {
         ProcessType::Back => &config.backs,
         ProcessType::Keep => &config.keepers,
+        ProcessType::Lock => &config.locks,
     };
     for (i, srv) in it.iter().enumerate() {
         if addr::check(srv)? {
@@ -52,6 +60,7 @@ pub async fn main(
     let proc_name = match t {
         ProcessType::Back => "backend",
         ProcessType::Keep => "keeper",
+        ProcessType::Lock => "lock server",
     };
     if handles.is_empty() {
         warn!("no {}s found for this host", proc_name);
@@ -95,5 +104,10 @@ async fn run_srv(t: ProcessType, idx: usize, config: Arc<Config>, tx: Option<Sen
             info!("starting keeper on {}", cfg.addr());
             lab2::serve_keeper(cfg).await;
         }
+        ProcessType::Lock => {
+            let addr = config.locks[idx].to_string();
+            info!("starting lock server on {}", addr);
+            lab3::serve_lock_server(addr, config.locks.clone(), tx, None).await;
+        }
     };
 }
@@ -0,0 +1,99 @@
+use clap::{Parser, Subcommand};
+use lab::lab3::KeeperClient;
+use tribbler::config::{Config, DEFAULT_CONFIG_LOCATION};
+use tribbler::err::{TribResult, TribblerError};
+
+/// inspects and steers the keepers of a given bin config file
+#[derive(Parser, Debug)]
+#[clap(name = "keeper-ctl")]
+struct Args {
+    /// bin configuration file
+    #[clap(short, long, default_value = DEFAULT_CONFIG_LOCATION)]
+    config: String,
+    /// index of the keeper to talk to, every keeper when not set
+    #[clap(short, long)]
+    keeper: Option<usize>,
+
+    #[clap(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand, Debug)]
+enum Action {
+    /// prints the term, backend liveness and migration the leader sees
+    Status,
+    /// keeps the keepers from starting migrations
+    Pause,
+    /// lets the keepers start migrations again
+    Resume,
+    /// makes the leader scan the backends right away
+    Rescan,
+}
+
+#[tokio::main]
+async fn main() -> TribResult<()> {
+    let args = Args::parse();
+    let cfg = Config::read(Some(&args.config))?;
+    if let Some(i) = args.keeper {
+        if i >= cfg.keepers.len() {
+            return Err(TribblerError::Unknown(format!(
+                "no keeper {} among {}",
+                i,
+                cfg.keepers.len()
+            ))
+            .into());
+        }
+    }
+    let keepers: Vec<usize> = match args.keeper {
+        Some(i) => vec![i],
+        None => (0..cfg.keepers.len()).collect(),
+    };
+    for i in keepers {
+        let client = KeeperClient::new(&cfg.keepers[i], None);
+        let res = match args.action {
+            Action::Status => client.status().await.map(|status| {
+                let leader = match status.leader {
+                    Some(leader) => leader.to_string(),
+                    None => "none".to_string(),
+                };
+                let migration = match status.migration {
+                    Some(log) => match log.steps.get(log.next) {
+                        Some(step) => format!(
+                            "step {}/{} (backends {:?} {}), {}% done",
+                            log.next + 1,
+                            log.steps.len(),
+                            step.back_ids,
+                            if step.leave { "left" } else { "joined" },
+                            log.percent()
+                        ),
+                        None => format!("{} steps done", log.steps.len()),
+                    },
+                    None => "none".to_string(),
+                };
+                format!(
+                    "leader {}, term {}, backs {:?}, migration {}, paused {}, last clock {}",
+                    leader, status.term, status.backs, migration, status.paused, status.last_clock
+                )
+            }),
+            Action::Pause => client.pause_migration().await.map(|_| "paused".to_string()),
+            Action::Resume => client
+                .resume_migration()
+                .await
+                .map(|_| "resumed".to_string()),
+            Action::Rescan => client
+                .force_rescan()
+                .await
+                .map(|_| "rescanning".to_string()),
+        };
+        match res {
+            Ok(line) => println!("keeper {} ({}): {}", i, cfg.keepers[i], line),
+            Err(err) => match err.downcast_ref::<TribblerError>() {
+                Some(TribblerError::NotLeader(_)) => {
+                    println!("keeper {} ({}): {}", i, cfg.keepers[i], err)
+                }
+                _ => println!("keeper {} ({}): unreachable: {}", i, cfg.keepers[i], err),
+            },
+        }
+    }
+    Ok(())
+}
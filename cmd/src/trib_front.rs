@@ -3,7 +3,7 @@ use std::str::FromStr;
 use actix_files::Files;
 use actix_web::{web, App, HttpServer};
 use clap::Parser;
-use lab::lab2;
+use lab::{lab2, lab3};
 use log::{info, warn, LevelFilter};
 use tribbler::config::Config;
 use tribbler::config::DEFAULT_CONFIG_LOCATION;
@@ -70,7 +70,9 @@ async fn main() -> TribResult<()> {
         ServerType::Ref => Box::new(RefServer::new()),
         ServerType::Lab => {
             let cfg = Config::read(Some(&args.config))?;
-            let bc = lab2::new_bin_client(cfg.backs).await?;
+            let bc =
+                lab3::new_bin_client_with_placement(cfg.backs, cfg.locks, cfg.replicas, cfg.vnodes)
+                    .await?;
             lab2::new_front(bc).await?
         }
     };
@@ -1,6 +1,7 @@
 use super::lab1;
 use super::lab2;
 use super::lab3;
+use super::test_helper;
 use rand::Rng;
 use std::{
     cmp,
@@ -13,7 +14,9 @@ use std::{
     time::Duration,
 };
 use tokio::sync::mpsc::Sender as MpscSender;
-use tribbler::config::KeeperConfig;
+use tribbler::config::{
+    KeeperConfig, DEFAULT_MIGRATION_CONCURRENCY, DEFAULT_REPLICAS, DEFAULT_VNODES,
+};
 #[allow(unused_imports)]
 use tribbler::{
     self,
@@ -35,6 +38,8 @@ async fn setup(
     initial_back_indices: Vec<usize>,
     keepers: Vec<String>,
     initial_keeper_indices: Vec<usize>,
+    locks: Vec<String>,
+    replicas: usize,
 ) -> (Vec<Option<MpscSender<()>>>, Vec<Option<MpscSender<()>>>) {
     let mut shutdown_back_send_chans = vec![];
     let back_hashset: HashSet<usize> = initial_back_indices.into_iter().collect();
@@ -55,7 +60,8 @@ async fn setup(
             shutdown_keeper_send_chans.push(None);
             continue;
         }
-        let shut_tx = setup_single_keeper(i, keepers.clone(), backs.clone()).await;
+        let shut_tx =
+            setup_single_keeper(i, keepers.clone(), backs.clone(), locks.clone(), replicas).await;
         shutdown_keeper_send_chans.push(Some(shut_tx.clone()));
     }
     tokio::time::sleep(Duration::from_secs(1)).await;
@@ -66,11 +72,18 @@ async fn setup_single_keeper(
     i: usize,
     keeper_addrs: Vec<String>,
     backs: Vec<String>,
+    locks: Vec<String>,
+    replicas: usize,
 ) -> MpscSender<()> {
     let (shut_tx_keeper, shut_rx_keeper) = tokio::sync::mpsc::channel(1);
     let kfg = KeeperConfig {
         backs: backs.clone(),
         addrs: keeper_addrs.clone(),
+        locks,
+        replicas,
+        vnodes: DEFAULT_VNODES,
+        migration_rate: 0,
+        migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
         this: i,
         id: rand::thread_rng().gen_range(0..300),
         ready: None,
@@ -98,11 +111,12 @@ fn generate_addresses(
     back_len: usize,
     keeper_len: usize,
     prefix_id: usize,
-) -> (Vec<String>, Vec<String>) {
+) -> (Vec<String>, Vec<String>, Vec<String>) {
     // prefix_id ranges from 01 to 65
     // 01000 - 65000
     let back_port_num_base = (prefix_id * 1000) as u32;
     let keeper_port_num_base = (prefix_id * 1000 + 700) as u32;
+    let lock_port_num_base = (prefix_id * 1000 + 900) as u32;
     let mut back_addrs = vec![];
     let mut prefix = "127.0.0.1:";
     for i in 0..back_len {
@@ -117,7 +131,8 @@ fn generate_addresses(
         let full_str = format!("{}{:05}", prefix, keeper_port_num_base + u32_i);
         keeper_addrs.push(full_str.to_string());
     }
-    return (back_addrs, keeper_addrs);
+    let lock_addrs = vec![format!("{}{:05}", prefix, lock_port_num_base)];
+    return (back_addrs, keeper_addrs, lock_addrs);
 }
 
 pub struct BigFuckingTester {
@@ -125,6 +140,9 @@ pub struct BigFuckingTester {
     pub keeper_shutdown_send_chan: Vec<Option<MpscSender<()>>>,
     pub back_addresses: Vec<String>,
     pub back_shutdown_send_chan: Vec<Option<MpscSender<()>>>,
+    pub lock_addresses: Vec<String>,
+    pub lock_shutdown_send_chan: Option<MpscSender<()>>,
+    pub replicas: usize,
 }
 
 impl BigFuckingTester {
@@ -135,12 +153,35 @@ impl BigFuckingTester {
         initial_back_live_indices: Vec<usize>,
         keeper_len: usize,
         initial_keeper_live_indices: Vec<usize>,
+    ) -> Self {
+        Self::new_with_replicas(
+            testing_sequence_id,
+            back_len,
+            initial_back_live_indices,
+            keeper_len,
+            initial_keeper_live_indices,
+            DEFAULT_REPLICAS,
+        )
+        .await
+    }
+
+    /// same as [BigFuckingTester::new], with keepers that keep each bin on
+    /// `replicas` backends.
+    pub async fn new_with_replicas(
+        testing_sequence_id: usize,
+        back_len: usize,
+        initial_back_live_indices: Vec<usize>,
+        keeper_len: usize,
+        initial_keeper_live_indices: Vec<usize>,
+        replicas: usize,
     ) -> Self {
         if testing_sequence_id < 1 || testing_sequence_id > 65 {
             panic!("Prefix id must ranges from 1 to 65");
         }
-        let (back_addresses, keeper_addresses) =
+        let (back_addresses, keeper_addresses, lock_addresses) =
             generate_addresses(back_len, keeper_len, testing_sequence_id);
+        // keepers and the clients of the test lock through this server
+        let lock_shut_tx = test_helper::spawn_lock_server(&lock_addresses[0], &lock_addresses);
         // println!("Backend addresses: {:?}", back_addresses.clone());
         // println!("Keeper addresses: {:?}", keeper_addresses.clone());
         let (back_shut_vec, keeper_shut_vec) = setup(
@@ -148,6 +189,8 @@ impl BigFuckingTester {
             initial_back_live_indices.clone(),
             keeper_addresses.clone(),
             initial_keeper_live_indices.clone(),
+            lock_addresses.clone(),
+            replicas,
         )
         .await;
         Self {
@@ -155,6 +198,9 @@ impl BigFuckingTester {
             back_shutdown_send_chan: back_shut_vec,
             keeper_addresses: keeper_addresses,
             keeper_shutdown_send_chan: keeper_shut_vec,
+            lock_addresses,
+            lock_shutdown_send_chan: Some(lock_shut_tx),
+            replicas,
         }
     }
 }
@@ -185,6 +231,8 @@ impl BigFuckingTesterTrait for BigFuckingTester {
             join_index,
             self.keeper_addresses.clone(),
             self.back_addresses.clone(),
+            self.lock_addresses.clone(),
+            self.replicas,
         )
         .await;
         self.keeper_shutdown_send_chan[join_index] = Some(shut_tx);
@@ -217,6 +265,9 @@ impl BigFuckingTesterTrait for BigFuckingTester {
             }
             self.back_node_leave(i).await;
         }
+        if let Some(lock_shut_tx) = self.lock_shutdown_send_chan.take() {
+            let _ = lock_shut_tx.send(()).await;
+        }
     }
 }
 
@@ -0,0 +1,612 @@
+/// Add your message and service definitions below this line
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Heartbeat {
+    #[prost(bool, tag = "1")]
+    pub value: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HeartbeatResponse {
+    #[prost(bool, tag = "1")]
+    pub value: bool,
+}
+/// a candidate asks the other keepers for their vote in a new term
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VoteRequest {
+    #[prost(uint64, tag = "1")]
+    pub term: u64,
+    #[prost(uint64, tag = "2")]
+    pub candidate: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VoteResponse {
+    #[prost(uint64, tag = "1")]
+    pub term: u64,
+    #[prost(bool, tag = "2")]
+    pub granted: bool,
+}
+/// the leader of a term renews its lease on the other keepers
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AppendEntriesRequest {
+    #[prost(uint64, tag = "1")]
+    pub term: u64,
+    #[prost(uint64, tag = "2")]
+    pub leader: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AppendEntriesResponse {
+    #[prost(uint64, tag = "1")]
+    pub term: u64,
+    #[prost(bool, tag = "2")]
+    pub success: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StatusRequest {}
+/// the backends that left together, or the one that joined
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MigrationStep {
+    #[prost(uint64, repeated, tag = "1")]
+    pub back_ids: ::prost::alloc::vec::Vec<u64>,
+    #[prost(bool, tag = "2")]
+    pub leave: bool,
+}
+/// the keys of a source up to and including this one are copied
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MigrationCursor {
+    #[prost(uint64, tag = "1")]
+    pub back_id: u64,
+    #[prost(bool, tag = "2")]
+    pub is_list: bool,
+    #[prost(string, tag = "3")]
+    pub key: ::prost::alloc::string::String,
+}
+/// a migration in progress, as recorded in the keeper store
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MigrationLog {
+    #[prost(bool, repeated, tag = "1")]
+    pub from: ::prost::alloc::vec::Vec<bool>,
+    #[prost(bool, repeated, tag = "2")]
+    pub to: ::prost::alloc::vec::Vec<bool>,
+    #[prost(message, repeated, tag = "3")]
+    pub steps: ::prost::alloc::vec::Vec<MigrationStep>,
+    /// the step in progress
+    #[prost(uint64, tag = "4")]
+    pub next: u64,
+    #[prost(uint64, tag = "5")]
+    pub term: u64,
+    /// how far the step in progress got, out of the keys on its sources
+    #[prost(message, repeated, tag = "6")]
+    pub cursors: ::prost::alloc::vec::Vec<MigrationCursor>,
+    #[prost(uint64, tag = "7")]
+    pub done: u64,
+    #[prost(uint64, tag = "8")]
+    pub total: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KeeperStatus {
+    #[prost(uint64, tag = "1")]
+    pub this: u64,
+    /// whether this keeper holds the lease of a leader
+    #[prost(bool, tag = "2")]
+    pub has_leader: bool,
+    #[prost(uint64, tag = "3")]
+    pub leader: u64,
+    #[prost(uint64, tag = "4")]
+    pub term: u64,
+    /// liveness of the backends in the last scan of this keeper
+    #[prost(bool, repeated, tag = "5")]
+    pub backs: ::prost::alloc::vec::Vec<bool>,
+    /// unset when no migration is in progress
+    #[prost(message, optional, tag = "6")]
+    pub migration: ::core::option::Option<MigrationLog>,
+    #[prost(bool, tag = "7")]
+    pub paused: bool,
+    /// the clock this keeper broadcast last, and when in unix milliseconds.
+    /// 0 when it never did
+    #[prost(uint64, tag = "8")]
+    pub last_clock: u64,
+    #[prost(uint64, tag = "9")]
+    pub last_broadcast: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ControlRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ControlResponse {
+    #[prost(bool, tag = "1")]
+    pub value: bool,
+}
+#[doc = r" Generated client implementations."]
+pub mod keeper_service_client {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    #[derive(Debug, Clone)]
+    pub struct KeeperServiceClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl KeeperServiceClient<tonic::transport::Channel> {
+        #[doc = r" Attempt to create a new client by connecting to a given endpoint."]
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: std::convert::TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> KeeperServiceClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::ResponseBody: Body + Send + 'static,
+        T::Error: Into<StdError>,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> KeeperServiceClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<http::Request<tonic::body::BoxBody>>>::Error:
+                Into<StdError> + Send + Sync,
+        {
+            KeeperServiceClient::new(InterceptedService::new(inner, interceptor))
+        }
+        #[doc = r" Compress requests with `gzip`."]
+        #[doc = r""]
+        #[doc = r" This requires the server to support it otherwise it might respond with an"]
+        #[doc = r" error."]
+        pub fn send_gzip(mut self) -> Self {
+            self.inner = self.inner.send_gzip();
+            self
+        }
+        #[doc = r" Enable decompressing responses with `gzip`."]
+        pub fn accept_gzip(mut self) -> Self {
+            self.inner = self.inner.accept_gzip();
+            self
+        }
+        pub async fn ping(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Heartbeat>,
+        ) -> Result<tonic::Response<super::HeartbeatResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/keeper.KeeperService/ping");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn request_vote(
+            &mut self,
+            request: impl tonic::IntoRequest<super::VoteRequest>,
+        ) -> Result<tonic::Response<super::VoteResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/keeper.KeeperService/requestVote");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn append_entries(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AppendEntriesRequest>,
+        ) -> Result<tonic::Response<super::AppendEntriesResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/keeper.KeeperService/appendEntries");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn get_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::StatusRequest>,
+        ) -> Result<tonic::Response<super::KeeperStatus>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/keeper.KeeperService/getStatus");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn pause_migration(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ControlRequest>,
+        ) -> Result<tonic::Response<super::ControlResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/keeper.KeeperService/pauseMigration");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn resume_migration(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ControlRequest>,
+        ) -> Result<tonic::Response<super::ControlResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path =
+                http::uri::PathAndQuery::from_static("/keeper.KeeperService/resumeMigration");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " scans the backends right away and compares them with the recorded"]
+        #[doc = " status, instead of waiting for the next round"]
+        pub async fn force_rescan(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ControlRequest>,
+        ) -> Result<tonic::Response<super::ControlResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/keeper.KeeperService/forceRescan");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+    }
+}
+#[doc = r" Generated server implementations."]
+pub mod keeper_service_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    #[doc = "Generated trait containing gRPC methods that should be implemented for use with KeeperServiceServer."]
+    #[async_trait]
+    pub trait KeeperService: Send + Sync + 'static {
+        async fn ping(
+            &self,
+            request: tonic::Request<super::Heartbeat>,
+        ) -> Result<tonic::Response<super::HeartbeatResponse>, tonic::Status>;
+        async fn request_vote(
+            &self,
+            request: tonic::Request<super::VoteRequest>,
+        ) -> Result<tonic::Response<super::VoteResponse>, tonic::Status>;
+        async fn append_entries(
+            &self,
+            request: tonic::Request<super::AppendEntriesRequest>,
+        ) -> Result<tonic::Response<super::AppendEntriesResponse>, tonic::Status>;
+        async fn get_status(
+            &self,
+            request: tonic::Request<super::StatusRequest>,
+        ) -> Result<tonic::Response<super::KeeperStatus>, tonic::Status>;
+        async fn pause_migration(
+            &self,
+            request: tonic::Request<super::ControlRequest>,
+        ) -> Result<tonic::Response<super::ControlResponse>, tonic::Status>;
+        async fn resume_migration(
+            &self,
+            request: tonic::Request<super::ControlRequest>,
+        ) -> Result<tonic::Response<super::ControlResponse>, tonic::Status>;
+        #[doc = " scans the backends right away and compares them with the recorded"]
+        #[doc = " status, instead of waiting for the next round"]
+        async fn force_rescan(
+            &self,
+            request: tonic::Request<super::ControlRequest>,
+        ) -> Result<tonic::Response<super::ControlResponse>, tonic::Status>;
+    }
+    #[derive(Debug)]
+    pub struct KeeperServiceServer<T: KeeperService> {
+        inner: _Inner<T>,
+        accept_compression_encodings: (),
+        send_compression_encodings: (),
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: KeeperService> KeeperServiceServer<T> {
+        pub fn new(inner: T) -> Self {
+            let inner = Arc::new(inner);
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+            }
+        }
+        pub fn with_interceptor<F>(inner: T, interceptor: F) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for KeeperServiceServer<T>
+    where
+        T: KeeperService,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = Never;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/keeper.KeeperService/ping" => {
+                    #[allow(non_camel_case_types)]
+                    struct pingSvc<T: KeeperService>(pub Arc<T>);
+                    impl<T: KeeperService> tonic::server::UnaryService<super::Heartbeat> for pingSvc<T> {
+                        type Response = super::HeartbeatResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::Heartbeat>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).ping(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = pingSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/keeper.KeeperService/requestVote" => {
+                    #[allow(non_camel_case_types)]
+                    struct requestVoteSvc<T: KeeperService>(pub Arc<T>);
+                    impl<T: KeeperService> tonic::server::UnaryService<super::VoteRequest> for requestVoteSvc<T> {
+                        type Response = super::VoteResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::VoteRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).request_vote(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = requestVoteSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/keeper.KeeperService/appendEntries" => {
+                    #[allow(non_camel_case_types)]
+                    struct appendEntriesSvc<T: KeeperService>(pub Arc<T>);
+                    impl<T: KeeperService> tonic::server::UnaryService<super::AppendEntriesRequest>
+                        for appendEntriesSvc<T>
+                    {
+                        type Response = super::AppendEntriesResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AppendEntriesRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).append_entries(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = appendEntriesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/keeper.KeeperService/getStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct getStatusSvc<T: KeeperService>(pub Arc<T>);
+                    impl<T: KeeperService> tonic::server::UnaryService<super::StatusRequest> for getStatusSvc<T> {
+                        type Response = super::KeeperStatus;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::StatusRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_status(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = getStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/keeper.KeeperService/pauseMigration" => {
+                    #[allow(non_camel_case_types)]
+                    struct pauseMigrationSvc<T: KeeperService>(pub Arc<T>);
+                    impl<T: KeeperService> tonic::server::UnaryService<super::ControlRequest> for pauseMigrationSvc<T> {
+                        type Response = super::ControlResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ControlRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).pause_migration(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = pauseMigrationSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/keeper.KeeperService/resumeMigration" => {
+                    #[allow(non_camel_case_types)]
+                    struct resumeMigrationSvc<T: KeeperService>(pub Arc<T>);
+                    impl<T: KeeperService> tonic::server::UnaryService<super::ControlRequest>
+                        for resumeMigrationSvc<T>
+                    {
+                        type Response = super::ControlResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ControlRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).resume_migration(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = resumeMigrationSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/keeper.KeeperService/forceRescan" => {
+                    #[allow(non_camel_case_types)]
+                    struct forceRescanSvc<T: KeeperService>(pub Arc<T>);
+                    impl<T: KeeperService> tonic::server::UnaryService<super::ControlRequest> for forceRescanSvc<T> {
+                        type Response = super::ControlResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ControlRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).force_rescan(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = forceRescanSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => Box::pin(async move {
+                    Ok(http::Response::builder()
+                        .status(200)
+                        .header("grpc-status", "12")
+                        .header("content-type", "application/grpc")
+                        .body(empty_body())
+                        .unwrap())
+                }),
+            }
+        }
+    }
+    impl<T: KeeperService> Clone for KeeperServiceServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+            }
+        }
+    }
+    impl<T: KeeperService> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone())
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: KeeperService> tonic::transport::NamedService for KeeperServiceServer<T> {
+        const NAME: &'static str = "keeper.KeeperService";
+    }
+}
@@ -0,0 +1,236 @@
+use super::bin_client::{update_channel_cache, BinStorageClient};
+use super::client::StorageClient;
+use super::constants::{ANTI_ENTROPY_BUCKETS, LIST_LOG_PREFIX, STR_LOG_PREFIX, VALIDATION_BIT_KEY};
+use super::hash_ring::{in_arc, stable_hash};
+use std::collections::HashMap;
+use tribbler::err::{TribResult, TribblerError};
+use tribbler::rpc;
+use tribbler::storage::{KeyString, Pattern, Storage};
+
+/// the digest of a key and its value. It must not depend on the build, two
+/// backends running different builds still compare their digests.
+fn digest_of<T: serde::Serialize + ?Sized>(raw_key: &str, value: &T) -> TribResult<u64> {
+    Ok(stable_hash(&serde_json::to_string(&(raw_key, value))?))
+}
+
+/// the tree node of the leaf `raw_key` falls in.
+fn leaf_of(raw_key: &str, buckets: u32) -> u32 {
+    buckets + (stable_hash(raw_key) % buckets as u64) as u32
+}
+
+/// the bin of a raw key holding a string or list value, e.g. `alice` for
+/// `alice::LIST::tribs`. Histories are left out, replicas compact them on
+/// their own so they differ even when the values agree.
+fn value_key_bin(raw_key: &str) -> Option<&str> {
+    let (bin, key) = raw_key.split_once("::")?;
+    if key.starts_with(STR_LOG_PREFIX) || key.starts_with(LIST_LOG_PREFIX) {
+        Some(bin)
+    } else {
+        None
+    }
+}
+
+/// the digest of every string and list value `store` holds for the bins
+/// covered by `request`, along with its raw key. Lists are digested in
+/// their stored order, replicas holding the same elements in another order
+/// disagree.
+pub(crate) async fn key_digests(
+    store: &dyn Storage,
+    request: &rpc::DigestRequest,
+) -> TribResult<Vec<(String, u64)>> {
+    if !request.buckets.is_power_of_two() {
+        return Err(Box::new(TribblerError::Unknown(format!(
+            "bad digest request over {} buckets",
+            request.buckets
+        ))));
+    }
+    let covered = |raw_key: &str| match value_key_bin(raw_key) {
+        Some(bin) => {
+            let hash = stable_hash(bin);
+            request
+                .ranges
+                .iter()
+                .any(|range| in_arc(hash, range.start, range.end))
+        }
+        None => false,
+    };
+    let everything = Pattern {
+        prefix: "".to_string(),
+        suffix: "".to_string(),
+    };
+    let mut digests = vec![];
+    for raw_key in store.keys(&everything).await?.0 {
+        if !covered(&raw_key) {
+            continue;
+        }
+        if let Some(value) = store.get(&raw_key).await? {
+            let digest = digest_of(&raw_key, &value)?;
+            digests.push((raw_key, digest));
+        }
+    }
+    for raw_key in store.list_keys(&everything).await?.0 {
+        if !covered(&raw_key) {
+            continue;
+        }
+        let list = store.list_get(&raw_key).await?.0;
+        let digest = digest_of(&raw_key, &list)?;
+        digests.push((raw_key, digest));
+    }
+    Ok(digests)
+}
+
+/// the Merkle tree over `digests` with `buckets` leaves, node n at index n.
+/// A leaf combines the digests of its keys regardless of their order, an
+/// inner node hashes its two children.
+pub(crate) fn digest_tree(digests: &[(String, u64)], buckets: u32) -> Vec<u64> {
+    let mut tree = vec![0; 2 * buckets as usize];
+    for (raw_key, digest) in digests.iter() {
+        tree[leaf_of(raw_key, buckets) as usize] ^= digest;
+    }
+    for node in (1..buckets as usize).rev() {
+        tree[node] = stable_hash(&format!("{}:{}", tree[2 * node], tree[2 * node + 1]));
+    }
+    tree
+}
+
+/// the raw keys among `digests` that fall in one of the leaves `nodes`.
+pub(crate) fn keys_in_leaves(
+    digests: Vec<(String, u64)>,
+    buckets: u32,
+    nodes: &[u32],
+) -> Vec<String> {
+    digests
+        .into_iter()
+        .map(|(raw_key, _)| raw_key)
+        .filter(|raw_key| nodes.contains(&leaf_of(raw_key, buckets)))
+        .collect()
+}
+
+impl BinStorageClient {
+    /// compares the digest trees of the replicas of every ring arc, and
+    /// repairs the keys under the leaves they disagree on. Replicas that
+    /// agree cost a single digest call, and a backend scans its keys once
+    /// per call, so keepers run it continuously.
+    /// Returns the number of replicas repaired.
+    pub async fn anti_entropy(&self) -> TribResult<u64> {
+        let back_status = self.scanned_backs_status().await;
+        // backends still migrating are not compared
+        let mut valid = back_status.clone();
+        for (index, back) in self.backs.iter().enumerate() {
+            if !back_status[index] {
+                continue;
+            }
+            valid[index] =
+                match update_channel_cache(self.channel_cache.clone(), back.clone()).await {
+                    Ok(chan) => matches!(
+                        StorageClient::new(back, Some(chan))
+                            .get(VALIDATION_BIT_KEY)
+                            .await,
+                        Ok(Some(_))
+                    ),
+                    Err(_) => false,
+                };
+        }
+        // each primary is compared with its other replicas over the arcs
+        // they share
+        let mut pairs: Vec<(usize, usize, Vec<rpc::HashRange>)> = vec![];
+        for (start, end, replicas) in self.ring.arcs(&back_status, self.replicas) {
+            let replicas = replicas
+                .into_iter()
+                .filter(|replica| valid[*replica])
+                .collect::<Vec<usize>>();
+            for other in replicas.iter().skip(1) {
+                let range = rpc::HashRange { start, end };
+                match pairs
+                    .iter_mut()
+                    .find(|(primary, replica, _)| *primary == replicas[0] && replica == other)
+                {
+                    Some((_, _, ranges)) => ranges.push(range),
+                    None => pairs.push((replicas[0], *other, vec![range])),
+                }
+            }
+        }
+        let mut repaired = 0;
+        let mut first_err = None;
+        for (primary, replica, ranges) in pairs {
+            let raw_keys = match self
+                .differing_keys(&self.backs[primary], &self.backs[replica], ranges)
+                .await
+            {
+                Ok(raw_keys) => raw_keys,
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                    continue;
+                }
+            };
+            for raw_key in raw_keys {
+                // raw keys look like {bin}::LIST::{key}
+                let (bin, wrapped_key) = match raw_key.split_once("::") {
+                    Some(split) => split,
+                    None => continue,
+                };
+                match self.replicator(bin).await.repair_key(wrapped_key).await {
+                    Ok(count) => repaired += count,
+                    Err(err) => {
+                        first_err.get_or_insert(err);
+                    }
+                }
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(repaired),
+        }
+    }
+
+    // Fetches the whole digest trees of `primary` and `replica` over
+    // `ranges` in one call each, walks down them only into the subtrees that
+    // differ, and returns the keys under the leaves that still differ.
+    async fn differing_keys(
+        &self,
+        primary: &str,
+        replica: &str,
+        ranges: Vec<rpc::HashRange>,
+    ) -> TribResult<Vec<String>> {
+        let mut clients = vec![];
+        for addr in [primary, replica] {
+            let chan = update_channel_cache(self.channel_cache.clone(), addr.to_string()).await?;
+            clients.push(StorageClient::new(addr, Some(chan)));
+        }
+        let mut request = rpc::DigestRequest {
+            ranges,
+            buckets: ANTI_ENTROPY_BUCKETS,
+            nodes: (1..2 * ANTI_ENTROPY_BUCKETS).collect(),
+        };
+        let ours = clients[0].digest(&request).await?;
+        let theirs = clients[1].digest(&request).await?;
+        let differ = request
+            .nodes
+            .iter()
+            .zip(ours.iter().zip(theirs.iter()))
+            .map(|(node, (a, b))| (*node, a != b))
+            .collect::<HashMap<u32, bool>>();
+        let mut leaves = vec![];
+        let mut nodes = vec![1];
+        while let Some(node) = nodes.pop() {
+            if !differ.get(&node).copied().unwrap_or(false) {
+                continue;
+            }
+            if node >= ANTI_ENTROPY_BUCKETS {
+                leaves.push(node);
+            } else {
+                nodes.push(2 * node);
+                nodes.push(2 * node + 1);
+            }
+        }
+        if leaves.is_empty() {
+            return Ok(vec![]);
+        }
+        request.nodes = leaves;
+        let mut raw_keys = clients[0].digest_keys(&request).await?;
+        raw_keys.append(&mut clients[1].digest_keys(&request).await?);
+        raw_keys.sort();
+        raw_keys.dedup();
+        Ok(raw_keys)
+    }
+}
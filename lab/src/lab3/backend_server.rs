@@ -1,3 +1,4 @@
+use super::anti_entropy::{digest_tree, key_digests, keys_in_leaves};
 use async_trait::async_trait;
 use tonic::{Request, Response, Status};
 use tribbler::rpc;
@@ -230,4 +231,107 @@ impl TribStorage for BackendServer {
         let msg_body = rpc::Clock { timestamp: ret_ts };
         Ok(Response::new(msg_body))
     }
+
+    async fn digest(
+        &self,
+        request: tonic::Request<rpc::DigestRequest>,
+    ) -> Result<tonic::Response<rpc::DigestResponse>, tonic::Status> {
+        let request_inner = request.into_inner();
+        let digests = match key_digests(self.store.as_ref(), &request_inner).await {
+            Ok(res) => res,
+            Err(_) => {
+                return Err(Status::unavailable("digest error"));
+            }
+        };
+        let tree = digest_tree(&digests, request_inner.buckets);
+        let mut nodes = vec![];
+        for node in request_inner.nodes {
+            match tree.get(node as usize) {
+                Some(digest) if node > 0 => nodes.push(*digest),
+                _ => {
+                    return Err(Status::unavailable("digest error"));
+                }
+            }
+        }
+        let msg_body = rpc::DigestResponse { digests: nodes };
+        Ok(Response::new(msg_body))
+    }
+
+    async fn digest_keys(
+        &self,
+        request: tonic::Request<rpc::DigestRequest>,
+    ) -> Result<tonic::Response<rpc::StringList>, tonic::Status> {
+        let request_inner = request.into_inner();
+        let digests = match key_digests(self.store.as_ref(), &request_inner).await {
+            Ok(res) => res,
+            Err(_) => {
+                return Err(Status::unavailable("digest keys error"));
+            }
+        };
+        let vec = keys_in_leaves(digests, request_inner.buckets, &request_inner.nodes);
+        let msg_body = rpc::StringList { list: vec };
+        Ok(Response::new(msg_body))
+    }
+
+    async fn get_batch(
+        &self,
+        request: tonic::Request<rpc::BatchKeys>,
+    ) -> Result<tonic::Response<rpc::Batch>, tonic::Status> {
+        let request_inner = request.into_inner();
+        let mut values = vec![];
+        for key in request_inner.keys {
+            let value = match self.store.get(&key).await {
+                Ok(res) => res.unwrap_or_default(),
+                Err(_) => {
+                    return Err(Status::unavailable("get batch error"));
+                }
+            };
+            values.push(rpc::KeyValue { key, value });
+        }
+        let mut lists = vec![];
+        for key in request_inner.list_keys {
+            let list = match self.store.list_get(&key).await {
+                Ok(res) => res.0,
+                Err(_) => {
+                    return Err(Status::unavailable("get batch error"));
+                }
+            };
+            lists.push(rpc::KeyValueList { key, list });
+        }
+        let msg_body = rpc::Batch { values, lists };
+        Ok(Response::new(msg_body))
+    }
+
+    async fn set_batch(
+        &self,
+        request: tonic::Request<rpc::Batch>,
+    ) -> Result<tonic::Response<rpc::Bool>, tonic::Status> {
+        let request_inner = request.into_inner();
+        for kv in request_inner.values {
+            let res = self
+                .store
+                .set(&storage::KeyValue {
+                    key: kv.key,
+                    value: kv.value,
+                })
+                .await;
+            if res.is_err() {
+                return Err(Status::unavailable("set batch error"));
+            }
+        }
+        for kl in request_inner.lists {
+            let res = self
+                .store
+                .list_set(&storage::KeyValueList {
+                    key: kl.key,
+                    list: kl.list,
+                })
+                .await;
+            if res.is_err() {
+                return Err(Status::unavailable("set batch error"));
+            }
+        }
+        let msg_body = rpc::Bool { value: true };
+        Ok(Response::new(msg_body))
+    }
 }
@@ -1,24 +1,29 @@
 use crate::lockserver::PingRequest;
 
 use super::super::lockserver::lock_service_client::LockServiceClient;
-use super::bin_replicator_adapter::BinReplicatorAdapter;
+use super::bin_replicator_adapter::{BinReplicatorAdapter, Consistency, WriteReport};
 use super::client::StorageClient;
 use super::constants::{
     DEFAULT_LOCK_SERVERS_STARTING_PORT, DEFAULT_NUM_LOCK_SERVERS, LIST_LOG_KEYWORD,
-    LOCK_SERVERS_STARTING_PORT_KEY, NUM_LOCK_SERVERS_KEY, SCAN_INTERVAL_CONSTANT, STR_LOG_KEYWORD,
-    TRANS_LOG_LIST_PREFIX, TRANS_LOG_STR_PREFIX,
+    LIST_LOG_PREFIX, LOCK_SERVERS_STARTING_PORT_KEY, NUM_LOCK_SERVERS_KEY, SCAN_INTERVAL_CONSTANT,
+    STR_LOG_KEYWORD, STR_LOG_PREFIX, TRANS_LOG_LIST_PREFIX, TRANS_LOG_STR_PREFIX,
+    TXN_ABORT_DECISION, TXN_COMMIT_DECISION, TXN_DECISION_PREFIX, TXN_INDEX_BIN, TXN_OPEN_KEY,
+    TXN_PREPARE_PREFIX, TXN_RECOVERY_TIMESTAMP,
 };
+use super::hash_ring::HashRing;
 use super::lock_client::{self, LockClient};
+use super::txn::TxnMode;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
 use tonic::transport::{Channel, Endpoint};
-use tribbler::err::TribResult;
-use tribbler::storage::{BinStorage, KeyString, KeyValue, Storage};
+use tribbler::config::{DEFAULT_REPLICAS, DEFAULT_VNODES};
+use tribbler::err::{TribResult, TribblerError};
+use tribbler::storage::{BinStorage, KeyString, KeyValue, KeyValueList, Storage};
 extern crate dotenv;
 use dotenv::dotenv;
 use std::env;
@@ -30,12 +35,108 @@ pub struct TxnLog {
     old_value: Vec<String>,
 }
 
+/// Left in every bin a transaction wrote once that bin is prepared to
+/// commit. It names the coordinator, whose bin holds the decision record,
+/// and the keys of the bin the transaction wrote.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrepareRecord {
+    transaction_id: String,
+    transaction_key: String,
+    keys: Vec<String>,
+}
+
+/// An entry of the index of open transactions kept in [TXN_INDEX_BIN]: a
+/// transaction that may have left undo logs and prepare records behind,
+/// and the keys it writes in each bin.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct OpenTxn {
+    transaction_id: String,
+    transaction_key: String,
+    write_keys: BTreeMap<String, Vec<String>>,
+}
+
+impl OpenTxn {
+    fn new(
+        transaction_id: &str,
+        trans_key: &str,
+        write_keys_map: &HashMap<String, Vec<String>>,
+    ) -> Self {
+        // sorted, so the same transaction always makes the same entry
+        let write_keys = write_keys_map
+            .iter()
+            .map(|(bin, keys)| {
+                let mut keys = keys.clone();
+                keys.sort();
+                (bin.to_string(), keys)
+            })
+            .collect();
+        Self {
+            transaction_id: transaction_id.to_string(),
+            transaction_key: trans_key.to_string(),
+            write_keys,
+        }
+    }
+
+    fn index_entry(&self) -> TribResult<KeyValue> {
+        Ok(KeyValue {
+            key: TXN_OPEN_KEY.to_string(),
+            value: serde_json::to_string(self)?,
+        })
+    }
+}
+
+fn decision_key(trans_key: &str) -> String {
+    format!("{}{}", TXN_DECISION_PREFIX, trans_key)
+}
+
+fn prepare_key(transaction_id: &str, trans_key: &str) -> String {
+    format!("{}{}::{}", TXN_PREPARE_PREFIX, transaction_id, trans_key)
+}
+
+fn trans_log_key(raw_key: &str) -> (String, String, bool) {
+    let (prefix, key) = split_string(raw_key.to_string());
+    if prefix == LIST_LOG_KEYWORD {
+        (format!("{}{}", TRANS_LOG_LIST_PREFIX, key), key, true)
+    } else {
+        (format!("{}{}", TRANS_LOG_STR_PREFIX, key), key, false)
+    }
+}
+
+/// Where a [TxnClient] is in its current transaction. A transaction key can
+/// only be committed or aborted while it is active.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxnState {
+    Idle,
+    Active(String),
+    Committed(String),
+    Aborted(String),
+}
+
 pub struct TxnClient {
     transaction_id: String,
     transaction_num: RwLock<u64>,
-    lock_client: Arc<LockClient>,
+    // start time of the current transaction, kept across retries after a
+    // lock conflict so the transaction ages and eventually wins wait-die
+    pub(crate) start_ts: RwLock<u64>,
+    pub(crate) lock_client: Arc<LockClient>,
     channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
-    bin_storage: Arc<BinStorageClient>,
+    pub(crate) bin_storage: Arc<BinStorageClient>,
+    pub(crate) state: Arc<RwLock<TxnState>>,
+    pub(crate) mode: TxnMode,
+}
+
+/// the lock keys of the read and write keys of a transaction.
+fn lock_keys(
+    read_keys_map: &HashMap<String, Vec<String>>,
+    write_keys_map: &HashMap<String, Vec<String>>,
+) -> (Vec<String>, Vec<String>) {
+    let flatten = |keys_map: &HashMap<String, Vec<String>>| {
+        keys_map
+            .iter()
+            .flat_map(|(bin, keys)| keys.iter().map(move |key| format!("{}::{}", bin, key)))
+            .collect::<Vec<String>>()
+    };
+    (flatten(read_keys_map), flatten(write_keys_map))
 }
 
 pub fn split_string(s: String) -> (String, String) {
@@ -53,39 +154,95 @@ impl TxnClient {
         channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
         bin_storage: Arc<BinStorageClient>,
     ) -> Self {
+        // transaction keys start from the clock so that a restarted client
+        // reusing its id never reuses the commit marker of an earlier run
+        let first_num = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_micros() as u64;
         Self {
             transaction_id,
-            transaction_num: RwLock::<u64>::new(0),
+            transaction_num: RwLock::<u64>::new(first_num),
+            start_ts: RwLock::<u64>::new(0),
             lock_client,
             channel_cache,
             bin_storage,
+            state: Arc::new(RwLock::new(TxnState::Idle)),
+            mode: TxnMode::Locking,
         }
     }
 
+    pub async fn state(&self) -> TxnState {
+        self.state.read().await.clone()
+    }
+
+    /// the wait-die timestamp of the current transaction, set when it first
+    /// asks for locks.
+    pub(crate) async fn timestamp(&self) -> u64 {
+        let mut start_ts = self.start_ts.write().await;
+        if *start_ts == 0 {
+            *start_ts = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_micros() as u64;
+        }
+        *start_ts
+    }
+
+    async fn ensure_active(&self, trans_key: &str) -> TribResult<()> {
+        let state = self.state.read().await;
+        if *state != TxnState::Active(trans_key.to_string()) {
+            return Err(Box::new(TribblerError::InvalidTxnState(format!(
+                "transaction {} is not active, client is {:?}",
+                trans_key, *state
+            ))));
+        }
+        Ok(())
+    }
+
     pub async fn transaction_start(
         &mut self,
         read_keys_map: HashMap<String, Vec<String>>,
         write_keys_map: HashMap<String, Vec<String>>,
     ) -> TribResult<String> {
-        let mut write_keys = vec![];
-        let mut read_keys = vec![];
-        for (bin, key_list) in write_keys_map.iter() {
-            for key in key_list.iter() {
-                write_keys.push(format!("{}::{}", bin, key));
-            }
-        }
-        for (bin, key_list) in read_keys_map.iter() {
-            for key in key_list.iter() {
-                read_keys.push(format!("{}::{}", bin, key))
-            }
+        if let TxnState::Active(trans_key) = &*self.state.read().await {
+            return Err(Box::new(TribblerError::InvalidTxnState(format!(
+                "transaction {} is still active",
+                trans_key
+            ))));
         }
+        let (read_keys, write_keys) = lock_keys(&read_keys_map, &write_keys_map);
+        let timestamp = self.timestamp().await;
         self.lock_client
-            .acquire_locks(read_keys, write_keys)
+            .acquire_locks_with_timestamp(read_keys, write_keys, timestamp)
             .await?;
         let mut trans_num = self.transaction_num.write().await;
         let trans_key = trans_num.to_string();
         (*trans_num) = (*trans_num) + 1;
         drop(trans_num);
+        *self.state.write().await = TxnState::Active(trans_key.to_string());
+        // the locks are held from here on, a failure must give them back or
+        // the heartbeat keeps renewing them forever
+        if let Err(err) = self.write_undo_logs(&trans_key, &write_keys_map).await {
+            return self
+                .abort_after(err, trans_key, read_keys_map, write_keys_map)
+                .await;
+        }
+        Ok(trans_key)
+    }
+
+    /// saves the current value of every key in `write_keys_map` in an undo
+    /// log owned by the transaction `trans_key`. The transaction is added to
+    /// the index of open transactions first, so recovery finds every log.
+    async fn write_undo_logs(
+        &self,
+        trans_key: &str,
+        write_keys_map: &HashMap<String, Vec<String>>,
+    ) -> TribResult<()> {
+        if !write_keys_map.is_empty() {
+            let open = OpenTxn::new(&self.transaction_id, trans_key, write_keys_map);
+            self.bin_storage.open_txn(&open).await?;
+        }
         for (bin, key_list) in write_keys_map.iter() {
             let client = self.bin_storage.bin_with_locks(bin).await?;
             for raw_key in key_list.iter() {
@@ -126,39 +283,215 @@ impl TxnClient {
                 }
             }
         }
-        Ok(trans_key)
+        Ok(())
+    }
+
+    /// aborts the active transaction `trans_key` after `err` made it fail,
+    /// and returns `err`. An abort that fails as well is only logged, what it
+    /// left behind is rolled back by the next recovery.
+    pub(crate) async fn abort_after<T>(
+        &self,
+        err: Box<dyn Error + Send + Sync>,
+        trans_key: String,
+        read_keys_map: HashMap<String, Vec<String>>,
+        write_keys_map: HashMap<String, Vec<String>>,
+    ) -> TribResult<T> {
+        let aborted = trans_key.to_string();
+        if let Err(abort_err) = self
+            .transaction_abort(trans_key, read_keys_map, write_keys_map)
+            .await
+        {
+            log::warn!("abort of transaction {} failed: {}", aborted, abort_err);
+        }
+        Err(err)
     }
 
+    /// commits the active transaction `trans_key` with two-phase commit.
+    /// Every bin it wrote is prepared first, then the commit decision is
+    /// recorded in the bin of this client. Once it stands the transaction is
+    /// committed: the undo logs and prepare records are cleared, then the
+    /// decision is deleted, and whatever is left behind by a crash is cleared
+    /// by the next recovery. When a bin cannot be prepared, the decision
+    /// cannot be recorded, or recovery recorded an abort first, the
+    /// transaction is aborted instead.
     pub async fn transaction_end(
         &self,
         trans_key: String,
         read_keys_map: HashMap<String, Vec<String>>,
         write_keys_map: HashMap<String, Vec<String>>,
     ) -> TribResult<()> {
-        let mut write_keys = vec![];
-        let mut read_keys = vec![];
-        for (bin, key_list) in write_keys_map.iter() {
-            for key in key_list.iter() {
-                write_keys.push(format!("{}::{}", bin, key));
-            }
+        self.ensure_active(&trans_key).await?;
+        let mut res = self.prepare(&trans_key, &write_keys_map).await;
+        if res.is_ok() {
+            // recovery may have presumed an abort first, the decision read
+            // back is the one that stands
+            res = match self
+                .bin_storage
+                .decide_txn(&self.transaction_id, &trans_key, TXN_COMMIT_DECISION)
+                .await
+            {
+                Ok(decision) if decision == TXN_COMMIT_DECISION => {
+                    self.ensure_still_prepared(&trans_key, &write_keys_map)
+                        .await
+                }
+                Ok(_) => Err(Box::new(TribblerError::TxnAbortedByRecovery(
+                    trans_key.to_string(),
+                ))),
+                Err(err) => Err(err),
+            };
         }
-        for (bin, key_list) in read_keys_map.iter() {
-            for key in key_list.iter() {
-                read_keys.push(format!("{}::{}", bin, key))
-            }
+        if let Err(err) = res {
+            return self
+                .abort_after(err, trans_key, read_keys_map, write_keys_map)
+                .await;
         }
-        let client = self.bin_storage.bin(&self.transaction_id).await?;
-        client
-            .set(&KeyValue {
-                key: trans_key.to_string(),
-                value: "True".to_string(),
-            })
-            .await?;
+        *self.state.write().await = TxnState::Committed(trans_key.to_string());
+        // the decision is durable, a transaction left unfinished is finished
+        // by recovery
+        let _ = self
+            .bin_storage
+            .finish_txn(&OpenTxn::new(
+                &self.transaction_id,
+                &trans_key,
+                &write_keys_map,
+            ))
+            .await;
+        *self.start_ts.write().await = 0;
+        let (read_keys, write_keys) = lock_keys(&read_keys_map, &write_keys_map);
         self.lock_client
             .release_locks(read_keys, write_keys)
             .await?;
         Ok(())
     }
+
+    /// checks, once the commit decision stands, that the prepare records of
+    /// `trans_key` were not cleared. Recovery clears them only after rolling
+    /// back every bin under an abort it recorded, and deletes that abort once
+    /// they are all gone, so a commit recorded afterwards comes too late: it
+    /// is deleted again and the transaction counts as aborted by recovery.
+    async fn ensure_still_prepared(
+        &self,
+        trans_key: &str,
+        write_keys_map: &HashMap<String, Vec<String>>,
+    ) -> TribResult<()> {
+        for bin in write_keys_map.keys() {
+            let prepared = self
+                .bin_storage
+                .is_prepared(bin, &self.transaction_id, trans_key)
+                .await?;
+            if !prepared {
+                self.bin_storage
+                    .forget_txn_decision(&self.transaction_id, trans_key)
+                    .await?;
+                return Err(Box::new(TribblerError::TxnAbortedByRecovery(
+                    trans_key.to_string(),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// the prepare phase of [TxnClient::transaction_end]. A bin votes to
+    /// commit when the undo logs of the keys the transaction wrote there
+    /// still belong to it, which stops holding once its locks were lost and
+    /// recovery rolled it back. The vote is recorded as a [PrepareRecord].
+    async fn prepare(
+        &self,
+        trans_key: &str,
+        write_keys_map: &HashMap<String, Vec<String>>,
+    ) -> TribResult<()> {
+        for (bin, key_list) in write_keys_map.iter() {
+            let client = self.bin_storage.bin_with_locks(bin).await?;
+            for raw_key in key_list.iter() {
+                let (log_key, _, _) = trans_log_key(raw_key);
+                let owned = match client.get(&log_key).await? {
+                    Some(value) if !value.is_empty() => {
+                        let trans_log: TxnLog = serde_json::from_str(&value)?;
+                        trans_log.transaction_id == self.transaction_id
+                            && trans_log.transaction_key == trans_key
+                    }
+                    _ => false,
+                };
+                if !owned {
+                    return Err(Box::new(TribblerError::TxnPrepareFailed(format!(
+                        "{}::{}",
+                        bin, raw_key
+                    ))));
+                }
+            }
+            let record = PrepareRecord {
+                transaction_id: self.transaction_id.to_string(),
+                transaction_key: trans_key.to_string(),
+                keys: key_list.clone(),
+            };
+            client
+                .set(&KeyValue {
+                    key: prepare_key(&self.transaction_id, trans_key),
+                    value: serde_json::to_string(&record)?,
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// undoes the writes of the active transaction `trans_key`: the abort
+    /// decision is recorded, the old values saved by
+    /// [TxnClient::transaction_start] are written back, the undo logs,
+    /// prepare records and then the decision are deleted and the locks are
+    /// released.
+    pub async fn transaction_abort(
+        &self,
+        trans_key: String,
+        read_keys_map: HashMap<String, Vec<String>>,
+        write_keys_map: HashMap<String, Vec<String>>,
+    ) -> TribResult<()> {
+        self.ensure_active(&trans_key).await?;
+        *self.state.write().await = TxnState::Aborted(trans_key.to_string());
+        let decided = self
+            .bin_storage
+            .decide_txn(&self.transaction_id, &trans_key, TXN_ABORT_DECISION)
+            .await;
+        let mut first_err = match decided {
+            // the commit was recorded before the abort, the participants
+            // are finished as committed
+            Ok(decision) if decision == TXN_COMMIT_DECISION => {
+                *self.state.write().await = TxnState::Committed(trans_key.to_string());
+                None
+            }
+            Ok(_) => None,
+            Err(err) => Some(err),
+        };
+        let res = self
+            .bin_storage
+            .finish_txn(&OpenTxn::new(
+                &self.transaction_id,
+                &trans_key,
+                &write_keys_map,
+            ))
+            .await;
+        // the locks are released anyway, a log left behind is rolled back by
+        // the next recovery
+        if let Err(err) = res {
+            first_err.get_or_insert(err);
+        }
+        *self.start_ts.write().await = 0;
+        let (read_keys, write_keys) = lock_keys(&read_keys_map, &write_keys_map);
+        let released = self.lock_client.release_locks(read_keys, write_keys).await;
+        match first_err {
+            Some(err) => Err(err),
+            None => released,
+        }
+    }
+
+    /// finishes the transactions whose client crashed: those with a commit
+    /// decision keep their writes, all others are rolled back, and their undo
+    /// logs, prepare records and decisions are removed. Meant to be called
+    /// when a client restarts, keepers also run it periodically.
+    pub async fn recover(&self) -> TribResult<()> {
+        self.bin_storage
+            .recover_transactions(&self.lock_client)
+            .await
+    }
 }
 
 pub(crate) fn init_lock_servers_addresses() -> Vec<String> {
@@ -180,14 +513,22 @@ pub(crate) fn init_lock_servers_addresses() -> Vec<String> {
     return lock_addrs;
 }
 
+/// returns `locks`, or the lock servers of `config.env` when no lock server is
+/// configured.
+pub(crate) fn lock_addrs_or_default(locks: Vec<String>) -> Vec<String> {
+    if locks.is_empty() {
+        return init_lock_servers_addresses();
+    }
+    locks
+}
+
 #[derive(Debug, Default)]
 pub struct LockServerPinger {
     lock_addrs: Vec<String>,
 }
 
 impl LockServerPinger {
-    pub fn new() -> Self {
-        let lock_addrs = init_lock_servers_addresses();
+    pub fn new(lock_addrs: Vec<String>) -> Self {
         Self {
             lock_addrs: lock_addrs.clone(),
         }
@@ -217,47 +558,90 @@ pub struct BinStorageClient {
     pub backs: Vec<String>,
     back_status_mut: RwLock<Vec<bool>>,
     last_scan_ts: RwLock<u64>,
-    channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+    pub(crate) channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
     lock_client: Arc<LockClient>,
+    pub(crate) ring: Arc<HashRing>,
+    pub(crate) replicas: usize,
+    consistency: Consistency,
+    read_repairs: Arc<AtomicU64>,
+    last_write: Arc<RwLock<Option<WriteReport>>>,
 }
 
 impl BinStorageClient {
     pub fn new(backs: Vec<String>) -> Self {
+        Self::new_with_locks(backs, lock_addrs_or_default(vec![]))
+    }
+
+    pub fn new_with_locks(backs: Vec<String>, lock_addrs: Vec<String>) -> Self {
         let mut back_status = vec![];
         for _ in 0..backs.len() {
             back_status.push(false);
         }
-        let lock_addrs = init_lock_servers_addresses();
         Self {
             backs: backs.clone(),
             back_status_mut: RwLock::new(back_status),
             last_scan_ts: RwLock::new(0),
             channel_cache: Arc::new(RwLock::new(HashMap::new())),
             lock_client: Arc::new(LockClient::new(lock_addrs, false)),
+            ring: Arc::new(HashRing::new(&backs, DEFAULT_VNODES)),
+            replicas: DEFAULT_REPLICAS,
+            consistency: Consistency::One,
+            read_repairs: Arc::new(AtomicU64::new(0)),
+            last_write: Arc::new(RwLock::new(None)),
         }
     }
 
     pub fn new_with_channel(
         backs: &Vec<String>,
         channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+        lock_client: Arc<LockClient>,
     ) -> Self {
         let mut back_status = vec![];
         for _ in 0..backs.len() {
             back_status.push(false);
         }
-        let lock_addrs = init_lock_servers_addresses();
         Self {
             backs: backs.clone(),
             back_status_mut: RwLock::new(back_status),
             last_scan_ts: RwLock::new(0),
             channel_cache,
-            lock_client: Arc::new(LockClient::new(lock_addrs, false)),
+            lock_client,
+            ring: Arc::new(HashRing::new(backs, DEFAULT_VNODES)),
+            replicas: DEFAULT_REPLICAS,
+            consistency: Consistency::One,
+            read_repairs: Arc::new(AtomicU64::new(0)),
+            last_write: Arc::new(RwLock::new(None)),
         }
     }
 
     pub fn update_lock_client(&mut self, lock_client: Arc<LockClient>) {
         self.lock_client = lock_client;
     }
+
+    pub fn update_replicas(&mut self, replicas: usize) {
+        self.replicas = replicas;
+    }
+
+    pub fn update_vnodes(&mut self, vnodes: usize) {
+        self.ring = Arc::new(HashRing::new(&self.backs, vnodes));
+    }
+
+    pub fn update_consistency(&mut self, consistency: Consistency) {
+        self.consistency = consistency;
+    }
+
+    /// the number of replicas reads of this client have repaired so far.
+    /// Repairs happen in the background, after the read that found them
+    /// returned.
+    pub fn read_repairs(&self) -> u64 {
+        self.read_repairs.load(Ordering::Relaxed)
+    }
+
+    /// which replicas acknowledged the last write made through this client,
+    /// whether or not it reached enough of them to succeed.
+    pub async fn last_write_report(&self) -> Option<WriteReport> {
+        self.last_write.read().await.clone()
+    }
 }
 
 use async_trait::async_trait;
@@ -331,72 +715,400 @@ pub(crate) async fn update_channel_cache(
 
 impl BinStorageClient {
     pub async fn bin_with_locks(&self, name: &str) -> tribbler::err::TribResult<Box<dyn Storage>> {
-        let mut hasher = DefaultHasher::new();
+        let mut storage_bin_replicator_adapter = self.replicator(name).await;
+        storage_bin_replicator_adapter.with_lock = true;
+        Ok(Box::new(storage_bin_replicator_adapter))
+    }
+
+    // Builds the replicator adapter of a bin on top of the latest backend status.
+    pub(crate) async fn replicator(&self, name: &str) -> BinReplicatorAdapter {
         self.scan_backs_status().await;
-        name.hash(&mut hasher);
-        let hash_res = hasher.finish();
         let backs = self.backs.clone();
-        let len = backs.len() as u64;
-        let ind = (hash_res % len) as u32;
         let back_status = self.back_status_mut.read().await;
         let back_status_copy = (*back_status).clone();
-        let mut storage_bin_replicator_adapter = BinReplicatorAdapter::new(
-            ind,
-            backs.clone(),
+        BinReplicatorAdapter::new(
+            self.ring.clone(),
+            backs,
             name,
             back_status_copy,
             self.channel_cache.clone(),
             self.lock_client.clone(),
-        );
-        storage_bin_replicator_adapter.with_lock = true;
-        // println!("{}", target_back_addr);
-        Ok(Box::new(storage_bin_replicator_adapter))
+            self.replicas,
+            self.consistency,
+            self.read_repairs.clone(),
+            self.last_write.clone(),
+        )
+    }
+    // Which backends answered the last scan, scanning again if it is stale.
+    pub(crate) async fn scanned_backs_status(&self) -> Vec<bool> {
+        self.scan_backs_status().await;
+        self.back_status_mut.read().await.clone()
+    }
+
+    // Clients of the backends a channel can be opened to.
+    pub(crate) async fn reachable_backs(&self) -> Vec<StorageClient> {
+        let mut clients = vec![];
+        for back in self.backs.iter() {
+            if let Ok(chan) = update_channel_cache(self.channel_cache.clone(), back.clone()).await {
+                clients.push(StorageClient::new(back.as_str(), Some(chan)));
+            }
+        }
+        clients
     }
+
+    // Raises the clock of every reachable backend to the largest among them and
+    // returns it, so anything written afterwards is stamped past that clock.
+    pub(crate) async fn sync_clocks(&self) -> TribResult<u64> {
+        let clients = self.reachable_backs().await;
+        let mut clock = None;
+        for client in clients.iter() {
+            if let Ok(clk) = client.clock(0).await {
+                clock = Some(clock.map_or(clk, |cur: u64| cur.max(clk)));
+            }
+        }
+        let clock = match clock {
+            Some(clock) => clock,
+            None => {
+                return Err(Box::new(TribblerError::Unknown(
+                    "no backend is reachable".to_string(),
+                )))
+            }
+        };
+        for client in clients.iter() {
+            let _ = client.clock(clock).await;
+        }
+        Ok(clock)
+    }
+
     // This function is provided for keeper to use, so it do not need to scan
     pub fn bin_with_backs(
         &self,
         name: &str,
         backs_status: &Vec<bool>,
     ) -> tribbler::err::TribResult<Box<dyn Storage>> {
-        let mut hasher = DefaultHasher::new();
-        name.hash(&mut hasher);
-        let hash_res = hasher.finish();
-        let len = backs_status.len() as u64;
-        let ind = (hash_res % len) as u32;
         let storage_bin_replicator_adapter = BinReplicatorAdapter::new(
-            ind,
+            self.ring.clone(),
             self.backs.clone(),
             name,
             backs_status.clone(),
             self.channel_cache.clone(),
             self.lock_client.clone(),
+            self.replicas,
+            self.consistency,
+            self.read_repairs.clone(),
+            self.last_write.clone(),
         );
         // println!("{}", target_back_addr);
         Ok(Box::new(storage_bin_replicator_adapter))
     }
+
+    /// adds `open` to the index of open transactions, before it writes any
+    /// undo log or prepare record.
+    async fn open_txn(&self, open: &OpenTxn) -> TribResult<()> {
+        self.bin(TXN_INDEX_BIN)
+            .await?
+            .list_append(&open.index_entry()?)
+            .await?;
+        Ok(())
+    }
+
+    /// the transactions in the index of open transactions.
+    async fn open_txns(&self) -> TribResult<Vec<OpenTxn>> {
+        let entries = self
+            .bin(TXN_INDEX_BIN)
+            .await?
+            .list_get(TXN_OPEN_KEY)
+            .await?
+            .0;
+        let mut open_txns = vec![];
+        for entry in entries {
+            let open: OpenTxn = serde_json::from_str(&entry)?;
+            if !open_txns.contains(&open) {
+                open_txns.push(open);
+            }
+        }
+        Ok(open_txns)
+    }
+
+    /// finishes every bin `open` writes, and once all of them are finished
+    /// deletes its decision and removes it from the index of open
+    /// transactions. The caller holds the write locks of its keys.
+    async fn finish_txn(&self, open: &OpenTxn) -> TribResult<()> {
+        let mut first_err = None;
+        for (bin, keys) in open.write_keys.iter() {
+            let res = self
+                .finish_participant(bin, &open.transaction_id, &open.transaction_key, keys)
+                .await;
+            if let Err(err) = res {
+                first_err.get_or_insert(err);
+            }
+        }
+        if let Some(err) = first_err {
+            return Err(err);
+        }
+        self.close_txn(open).await
+    }
+
+    /// deletes the decision of `open`, which nothing reads once every bin it
+    /// wrote is finished, then removes it from the index of open
+    /// transactions.
+    async fn close_txn(&self, open: &OpenTxn) -> TribResult<()> {
+        self.forget_txn_decision(&open.transaction_id, &open.transaction_key)
+            .await?;
+        self.bin(TXN_INDEX_BIN)
+            .await?
+            .list_remove(&open.index_entry()?)
+            .await?;
+        Ok(())
+    }
+
+    /// deletes the decision of a transaction and drops its history.
+    async fn forget_txn_decision(&self, transaction_id: &str, trans_key: &str) -> TribResult<()> {
+        let decision_key = decision_key(trans_key);
+        // the lock list writers take, so no decision lands in between
+        let lock_keys = vec![format!("{}::{}", transaction_id, decision_key)];
+        let decisions = self.bin_with_locks(transaction_id).await?;
+        self.lock_client
+            .acquire_locks(vec![], lock_keys.clone())
+            .await?;
+        let res = match decisions
+            .list_set(&KeyValueList {
+                key: decision_key.to_string(),
+                list: vec![],
+            })
+            .await
+        {
+            Ok(_) => {
+                let mut replicator = self.replicator(transaction_id).await;
+                replicator.with_lock = true;
+                replicator
+                    .drop_history(&format!("{}{}", LIST_LOG_PREFIX, decision_key))
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        self.lock_client.release_locks(vec![], lock_keys).await?;
+        res
+    }
+
+    /// whether a transaction keeps an undo log on `raw_key` of `bin`, that
+    /// is whether the value there may still be rolled back. Read without
+    /// taking the lock of the key.
+    pub(crate) async fn has_open_txn_log(&self, bin: &str, raw_key: &str) -> TribResult<bool> {
+        let (log_key, _, _) = trans_log_key(raw_key);
+        let value = self.bin_with_locks(bin).await?.get(&log_key).await?;
+        Ok(value.is_some_and(|value| !value.is_empty()))
+    }
+
+    /// whether the prepare record of the transaction `trans_key` of
+    /// `transaction_id` is still kept in `bin`.
+    async fn is_prepared(
+        &self,
+        bin: &str,
+        transaction_id: &str,
+        trans_key: &str,
+    ) -> TribResult<bool> {
+        let value = self
+            .bin_with_locks(bin)
+            .await?
+            .get(&prepare_key(transaction_id, trans_key))
+            .await?;
+        Ok(value.is_some_and(|value| !value.is_empty()))
+    }
+
+    /// drops the history of the transaction record `record_key` of `bin`
+    /// once it was cleared. The caller holds the locks covering it.
+    async fn collect_txn_record(&self, bin: &str, record_key: &str) -> TribResult<()> {
+        let mut replicator = self.replicator(bin).await;
+        replicator.with_lock = true;
+        replicator
+            .drop_history(&format!("{}{}", STR_LOG_PREFIX, record_key))
+            .await
+    }
+
+    /// goes through the index of open transactions and applies their
+    /// decision: those with a commit decision keep their writes, all others
+    /// are rolled back, then the decision is deleted. Each bin of a
+    /// transaction is finished under the write locks of its keys, requested
+    /// without a timestamp so that wait-die never makes recovery die and it
+    /// waits for the locks instead.
+    /// A transaction still running keeps its locks until it is decided, and
+    /// the locks of a crashed one are given back when its lease runs out, so
+    /// a transaction seen undecided under the locks will never be decided by
+    /// its client.
+    pub async fn recover_transactions(&self, lock_client: &LockClient) -> TribResult<()> {
+        let mut first_err = None;
+        for open in self.open_txns().await? {
+            // keep going, the transactions that failed are picked up by the
+            // next recovery
+            if let Err(err) = self.recover_txn(&open, lock_client).await {
+                first_err.get_or_insert(err);
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    async fn recover_txn(&self, open: &OpenTxn, lock_client: &LockClient) -> TribResult<()> {
+        for (bin, keys) in open.write_keys.iter() {
+            let lock_keys = keys
+                .iter()
+                .map(|key| format!("{}::{}", bin, key))
+                .collect::<Vec<String>>();
+            lock_client
+                .acquire_locks_with_timestamp(vec![], lock_keys.clone(), TXN_RECOVERY_TIMESTAMP)
+                .await?;
+            let res = self
+                .finish_participant(bin, &open.transaction_id, &open.transaction_key, keys)
+                .await;
+            let released = lock_client.release_locks(vec![], lock_keys).await;
+            res.and(released)?;
+        }
+        self.close_txn(open).await
+    }
+
+    /// applies the decision of a transaction to one of the bins it wrote:
+    /// `keys` are rolled back from their undo logs unless it committed, then
+    /// the undo logs and the prepare record of the bin are removed.
+    async fn finish_participant(
+        &self,
+        bin: &str,
+        transaction_id: &str,
+        trans_key: &str,
+        keys: &[String],
+    ) -> TribResult<()> {
+        let mut first_err = None;
+        for raw_key in keys.iter() {
+            let res = self
+                .recover_txn_log(bin, raw_key, transaction_id, trans_key)
+                .await;
+            if let Err(err) = res {
+                first_err.get_or_insert(err);
+            }
+        }
+        if let Some(err) = first_err {
+            return Err(err);
+        }
+        let prepare_key = prepare_key(transaction_id, trans_key);
+        self.bin_with_locks(bin)
+            .await?
+            .set(&KeyValue {
+                key: prepare_key.to_string(),
+                value: "".to_string(),
+            })
+            .await?;
+        self.collect_txn_record(bin, &prepare_key).await
+    }
+
+    /// the decision of a transaction, kept in the bin of the client that
+    /// coordinated it. Decisions are appended to a list and the first one is
+    /// the outcome, so a decision once recorded is never overwritten.
+    async fn txn_decision(
+        &self,
+        transaction_id: &str,
+        trans_key: &str,
+    ) -> TribResult<Option<String>> {
+        let decisions = self
+            .bin(transaction_id)
+            .await?
+            .list_get(&decision_key(trans_key))
+            .await?
+            .0;
+        Ok(decisions.into_iter().next())
+    }
+
+    /// records `decision` for a transaction unless one was recorded first,
+    /// and returns the decision that stands.
+    pub(crate) async fn decide_txn(
+        &self,
+        transaction_id: &str,
+        trans_key: &str,
+        decision: &str,
+    ) -> TribResult<String> {
+        self.bin(transaction_id)
+            .await?
+            .list_append(&KeyValue {
+                key: decision_key(trans_key),
+                value: decision.to_string(),
+            })
+            .await?;
+        Ok(self
+            .txn_decision(transaction_id, trans_key)
+            .await?
+            .unwrap_or_else(|| decision.to_string()))
+    }
+
+    /// rolls the key `raw_key` of `bin` back from its undo log unless the
+    /// transaction `trans_key` of `transaction_id` committed, then removes
+    /// the log. The log of any other transaction is left alone.
+    async fn recover_txn_log(
+        &self,
+        bin: &str,
+        raw_key: &str,
+        transaction_id: &str,
+        trans_key: &str,
+    ) -> TribResult<()> {
+        let (log_key, key, is_list) = trans_log_key(raw_key);
+        let client = self.bin_with_locks(bin).await?;
+        let trans_log: TxnLog = match client.get(&log_key).await? {
+            Some(value) if !value.is_empty() => serde_json::from_str(&value)?,
+            // already cleared, only its history may be left
+            _ => return self.collect_txn_record(bin, &log_key).await,
+        };
+        if trans_log.transaction_id != transaction_id || trans_log.transaction_key != trans_key {
+            return Ok(());
+        }
+        let decision = self
+            .txn_decision(&trans_log.transaction_id, &trans_log.transaction_key)
+            .await?;
+        let decision = match decision {
+            Some(decision) => decision,
+            None => {
+                // presumed abort, recorded before anything is rolled back so
+                // that every bin of the transaction reaches the same outcome.
+                // A coordinator deciding at the same time may have won
+                self.decide_txn(
+                    &trans_log.transaction_id,
+                    &trans_log.transaction_key,
+                    TXN_ABORT_DECISION,
+                )
+                .await?
+            }
+        };
+        let committed = decision == TXN_COMMIT_DECISION;
+        if !committed {
+            if is_list {
+                client
+                    .list_set(&KeyValueList {
+                        key: key.to_string(),
+                        list: trans_log.old_value,
+                    })
+                    .await?;
+            } else {
+                let old_value = trans_log.old_value.into_iter().next().unwrap_or_default();
+                client
+                    .set(&KeyValue {
+                        key: key.to_string(),
+                        value: old_value,
+                    })
+                    .await?;
+            }
+        }
+        client
+            .set(&KeyValue {
+                key: log_key.to_string(),
+                value: "".to_string(),
+            })
+            .await?;
+        self.collect_txn_record(bin, &log_key).await
+    }
 }
 
 #[async_trait]
 impl BinStorage for BinStorageClient {
     async fn bin(&self, name: &str) -> tribbler::err::TribResult<Box<dyn Storage>> {
-        let mut hasher = DefaultHasher::new();
-        self.scan_backs_status().await;
-        name.hash(&mut hasher);
-        let hash_res = hasher.finish();
-        let backs = self.backs.clone();
-        let len = backs.len() as u64;
-        let ind = (hash_res % len) as u32;
-        let back_status = self.back_status_mut.read().await;
-        let back_status_copy = (*back_status).clone();
-        let storage_bin_replicator_adapter = BinReplicatorAdapter::new(
-            ind,
-            backs.clone(),
-            name,
-            back_status_copy,
-            self.channel_cache.clone(),
-            self.lock_client.clone(),
-        );
-        // println!("{}", target_back_addr);
-        Ok(Box::new(storage_bin_replicator_adapter))
+        Ok(Box::new(self.replicator(name).await))
     }
 }
@@ -8,6 +8,7 @@ use tonic::transport::Channel;
 use tribbler::err::TribResult;
 use tribbler::storage;
 
+#[derive(Clone)]
 pub struct BinPrefixAdapter {
     pub addr: String,
     pub bin: String,
@@ -2,19 +2,21 @@ use super::bin_client::update_channel_cache;
 use super::bin_prefix_adapter::BinPrefixAdapter;
 use super::client::StorageClient;
 use super::constants::{
-    APPEND_ACTION, KEYS_PREFIX, LIST_KEYS_PREFIX, LIST_LOG_PREFIX, REMOVE_ACTION, STR_LOG_PREFIX,
-    VALIDATION_BIT_KEY,
+    APPEND_ACTION, CHECKPOINT_ACTION, HISTORY_LOG_PREFIX, KEYS_PREFIX, LIST_KEYS_PREFIX,
+    LIST_LOG_PREFIX, REMOVE_ACTION, SET_ACTION, STR_LOG_PREFIX, VALIDATION_BIT_KEY,
 };
+use super::hash_ring::HashRing;
 use super::lock_client::LockClient;
 use serde::{Deserialize, Serialize};
-use std::cmp::{self, Ordering};
+use std::cmp;
 use std::collections::{HashMap, HashSet};
 use std::error;
 use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tonic::transport::Channel;
-use tribbler::err::TribResult;
+use tribbler::err::{TribResult, TribblerError};
 use tribbler::storage::{self, KeyList, KeyString, Storage};
 
 // Change the alias to `Box<error::Error>`.
@@ -29,41 +31,460 @@ impl fmt::Display for NotEnoughServers {
 
 impl error::Error for NotEnoughServers {}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// how many replicas of a bin a read consults and a write waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Consistency {
+    /// a single replica
+    #[default]
+    One,
+    /// a majority of the replicas
+    Quorum,
+    /// every replica
+    All,
+}
+
+impl Consistency {
+    /// the number of replicas required out of `replicas`.
+    pub fn required(&self, replicas: usize) -> usize {
+        match self {
+            Consistency::One => 1,
+            Consistency::Quorum => replicas / 2 + 1,
+            Consistency::All => replicas,
+        }
+    }
+}
+
+/// which replicas acknowledged the last write a bin client made.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteReport {
+    pub bin: String,
+    /// the key as stored on the backends, e.g. `LIST::tribs`
+    pub key: String,
+    /// addresses of the replicas the write landed on
+    pub acked: Vec<String>,
+    /// addresses of the replicas that failed or refused it
+    pub failed: Vec<String>,
+}
+
+impl WriteReport {
+    fn new(bin: &str, key: &str) -> Self {
+        Self {
+            bin: bin.to_string(),
+            key: key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn record(&mut self, replica: &BinPrefixAdapter, acked: bool) {
+        if acked {
+            self.acked.push(replica.addr.to_string());
+        } else {
+            self.failed.push(replica.addr.to_string());
+        }
+    }
+
+    /// the replicas among `replicas` that acknowledged the write.
+    fn acked_replicas(&self, replicas: &[BinPrefixAdapter]) -> Vec<BinPrefixAdapter> {
+        replicas
+            .iter()
+            .filter(|replica| self.acked.contains(&replica.addr))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct SortableLogRecord {
     pub wrapped_string: String,
     pub clock_id: u64,
-    pub action: String, // const APPEND_ACTION stands for append, REMOVE_ACTION stands for remove.
+    pub action: String, // const APPEND_ACTION stands for append, REMOVE_ACTION stands for remove, SET_ACTION for set, CHECKPOINT_ACTION for a compacted prefix.
+}
+
+impl SortableLogRecord {
+    fn is_checkpoint(&self) -> bool {
+        self.action == CHECKPOINT_ACTION
+    }
+}
+
+/// the value a string history leaves behind.
+fn replay_str(records: &[SortableLogRecord]) -> Option<String> {
+    records
+        .last()
+        .map(|record| record.wrapped_string.to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// the list a list history leaves behind. A set or a checkpoint holds the
+/// whole list as JSON.
+fn replay_list(records: &[SortableLogRecord]) -> TribResult<Vec<String>> {
+    let mut list = vec![];
+    for record in records.iter() {
+        if record.action == APPEND_ACTION {
+            list.push(record.wrapped_string.to_string());
+        } else if record.action == REMOVE_ACTION {
+            list.retain(|value| *value != record.wrapped_string);
+        } else if record.action == SET_ACTION || record.is_checkpoint() {
+            list = serde_json::from_str(&record.wrapped_string)?;
+        }
+    }
+    Ok(list)
+}
+
+/// orders records by clock. A checkpoint comes after the records of its own
+/// clock, since it already includes them; copies of the same record kept
+/// by several replicas are dropped. Records of the same clock are ordered by
+/// their content as well, so that copies always end up next to each other.
+fn sort_records(records: &mut Vec<SortableLogRecord>) {
+    records.sort_by(|a, b| {
+        (a.clock_id, a.is_checkpoint(), &a.action, &a.wrapped_string).cmp(&(
+            b.clock_id,
+            b.is_checkpoint(),
+            &b.action,
+            &b.wrapped_string,
+        ))
+    });
+    records.dedup();
+}
+
+/// the history of `history_key` on each of `replicas`.
+async fn load_histories(
+    replicas: &[BinPrefixAdapter],
+    history_key: &str,
+) -> TribResult<Vec<Vec<SortableLogRecord>>> {
+    let mut histories = vec![];
+    for replica in replicas.iter() {
+        let mut records = vec![];
+        for raw in replica.list_get(history_key).await?.0 {
+            records.push(serde_json::from_str::<SortableLogRecord>(&raw)?);
+        }
+        histories.push(records);
+    }
+    Ok(histories)
+}
+
+/// for each history, the records other histories hold and it lacks, in
+/// clock order. Records up to the latest checkpoint of any replica are not
+/// compared, since each replica compacts its history on its own.
+fn missing_records(histories: &[Vec<SortableLogRecord>]) -> Vec<Vec<SortableLogRecord>> {
+    let floor = histories
+        .iter()
+        .flatten()
+        .filter(|record| record.is_checkpoint())
+        .map(|record| record.clock_id)
+        .max()
+        .unwrap_or(0);
+    let mut merged = histories
+        .iter()
+        .flatten()
+        .filter(|record| !record.is_checkpoint() && record.clock_id > floor)
+        .cloned()
+        .collect::<Vec<SortableLogRecord>>();
+    sort_records(&mut merged);
+    histories
+        .iter()
+        .map(|history| {
+            merged
+                .iter()
+                .filter(|record| !history.contains(record))
+                .cloned()
+                .collect()
+        })
+        .collect()
+}
+
+/// writes the records of `wrapped_key` a replica missed to its history, and
+/// replays them onto its value. Returns the number of replicas repaired.
+async fn repair_replicas(replicas: &[BinPrefixAdapter], wrapped_key: &str) -> TribResult<u64> {
+    let history_key = format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key);
+    let histories = load_histories(replicas, &history_key).await?;
+    let missing = missing_records(&histories);
+    let latest = histories
+        .iter()
+        .flatten()
+        .max_by_key(|record| record.clock_id)
+        .cloned();
+    let mut repaired = 0;
+    for ((replica, history), missing) in replicas.iter().zip(histories.iter()).zip(missing) {
+        if missing.is_empty() {
+            continue;
+        }
+        for record in missing.iter() {
+            replica
+                .list_append(&storage::KeyValue {
+                    key: history_key.to_string(),
+                    value: serde_json::to_string(record)?,
+                })
+                .await?;
+        }
+        if wrapped_key.starts_with(LIST_LOG_PREFIX) {
+            for record in missing.iter() {
+                let kv = storage::KeyValue {
+                    key: wrapped_key.to_string(),
+                    value: record.wrapped_string.to_string(),
+                };
+                if record.action == APPEND_ACTION {
+                    replica.list_append(&kv).await?;
+                } else if record.action == REMOVE_ACTION {
+                    replica.list_remove(&kv).await?;
+                } else if record.action == SET_ACTION {
+                    replica
+                        .list_set(&storage::KeyValueList {
+                            key: wrapped_key.to_string(),
+                            list: serde_json::from_str(&record.wrapped_string)?,
+                        })
+                        .await?;
+                }
+            }
+        } else if let Some(latest) = latest.as_ref().filter(|latest| !history.contains(latest)) {
+            replica
+                .set(&storage::KeyValue {
+                    key: wrapped_key.to_string(),
+                    value: latest.wrapped_string.to_string(),
+                })
+                .await?;
+        }
+        repaired += 1;
+    }
+    Ok(repaired)
+}
+
+/// the value of `wrapped_key` on `replica`, a string value as a list of at
+/// most one element.
+async fn load_value(replica: &BinPrefixAdapter, wrapped_key: &str) -> TribResult<Vec<String>> {
+    if wrapped_key.starts_with(LIST_LOG_PREFIX) {
+        return Ok(replica.list_get(wrapped_key).await?.0);
+    }
+    Ok(replica.get(wrapped_key).await?.into_iter().collect())
+}
+
+/// copies the value of `wrapped_key` the majority of `replicas` hold onto the
+/// others, or without a majority the value of the replica with the freshest
+/// history, the first one on ties. Covers keys whose history does not explain
+/// the difference, such as keys written without one. Returns the number of
+/// replicas repaired.
+async fn converge_values(replicas: &[BinPrefixAdapter], wrapped_key: &str) -> TribResult<u64> {
+    let mut values = vec![];
+    for replica in replicas.iter() {
+        values.push(load_value(replica, wrapped_key).await?);
+    }
+    if values.iter().all(|value| *value == values[0]) {
+        return Ok(0);
+    }
+    let majority = values
+        .iter()
+        .find(|value| values.iter().filter(|other| other == value).count() * 2 > values.len());
+    let chosen = match majority {
+        Some(value) => value.clone(),
+        None => {
+            let history_key = format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key);
+            let histories = load_histories(replicas, &history_key).await?;
+            let freshest = histories
+                .iter()
+                .map(|history| history.iter().map(|record| record.clock_id).max())
+                .enumerate()
+                .max_by_key(|(i, clock)| (*clock, std::cmp::Reverse(*i)))
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            values[freshest].clone()
+        }
+    };
+    let mut repaired = 0;
+    for (replica, value) in replicas.iter().zip(values.iter()) {
+        if *value == chosen {
+            continue;
+        }
+        if wrapped_key.starts_with(LIST_LOG_PREFIX) {
+            replica
+                .list_set(&storage::KeyValueList {
+                    key: wrapped_key.to_string(),
+                    list: chosen.clone(),
+                })
+                .await?;
+        } else {
+            replica
+                .set(&storage::KeyValue {
+                    key: wrapped_key.to_string(),
+                    value: chosen.first().cloned().unwrap_or_default(),
+                })
+                .await?;
+        }
+        repaired += 1;
+    }
+    Ok(repaired)
 }
 
 pub struct BinReplicatorAdapter {
-    pub hash_index: u32,
+    pub ring: Arc<HashRing>,
     pub backs: Vec<String>,
     pub bin: String,
     back_status: Vec<bool>,
     channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
     lock_client: Arc<LockClient>,
     pub with_lock: bool,
+    pub replicas: usize,
+    pub consistency: Consistency,
+    read_repairs: Arc<AtomicU64>,
+    last_write: Arc<RwLock<Option<WriteReport>>>,
 }
 
 impl BinReplicatorAdapter {
     pub fn new(
-        hash_index: u32,
+        ring: Arc<HashRing>,
         backs: Vec<String>,
         bin: &str,
         back_status: Vec<bool>,
         channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
         lock_client: Arc<LockClient>,
+        replicas: usize,
+        consistency: Consistency,
+        read_repairs: Arc<AtomicU64>,
+        last_write: Arc<RwLock<Option<WriteReport>>>,
     ) -> Self {
         Self {
-            hash_index,
+            ring,
             backs: backs.clone(),
             bin: bin.to_string(),
             back_status: back_status.clone(),
             channel_cache,
             lock_client,
             with_lock: false,
+            replicas: cmp::max(replicas, 1),
+            consistency,
+            read_repairs,
+            last_write,
+        }
+    }
+
+    /// compares the histories of `wrapped_key` on `replicas` in the
+    /// background, and repairs the replicas that missed some of its writes
+    /// while holding the key's write lock. Reads inside a transaction are
+    /// not repaired, the transaction already holds the lock.
+    fn spawn_read_repair(&self, replicas: &[BinPrefixAdapter], wrapped_key: &str) {
+        if self.with_lock || replicas.len() < 2 {
+            return;
+        }
+        let replicas = replicas.to_vec();
+        let wrapped_key = wrapped_key.to_string();
+        let write_keys = self.repair_lock_keys(&wrapped_key);
+        let lock_client = self.lock_client.clone();
+        let read_repairs = self.read_repairs.clone();
+        tokio::spawn(async move {
+            let history_key = format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key);
+            match load_histories(&replicas, &history_key).await {
+                Ok(histories) if missing_records(&histories).iter().any(|m| !m.is_empty()) => (),
+                _ => return,
+            }
+            if lock_client
+                .acquire_locks(vec![], write_keys.clone())
+                .await
+                .is_err()
+            {
+                return;
+            }
+            if let Ok(repaired) = repair_replicas(&replicas, &wrapped_key).await {
+                read_repairs.fetch_add(repaired, Ordering::Relaxed);
+            }
+            let _ = lock_client.release_locks(vec![], write_keys).await;
+        });
+    }
+
+    /// repairs the replicas of `wrapped_key` that missed some of its writes
+    /// while holding the key's write lock, then converges the values the
+    /// histories left different. Returns the number of replica repairs.
+    pub(crate) async fn repair_key(&self, wrapped_key: &str) -> TribResult<u64> {
+        let replicas = self.get_read_replicas_access_new().await;
+        if replicas.len() < 2 {
+            return Ok(0);
+        }
+        let write_keys = self.repair_lock_keys(wrapped_key);
+        self.lock_client
+            .acquire_locks(vec![], write_keys.clone())
+            .await?;
+        let res = match repair_replicas(&replicas, wrapped_key).await {
+            Ok(repaired) => converge_values(&replicas, wrapped_key)
+                .await
+                .map(|converged| repaired + converged),
+            Err(e) => Err(e),
+        };
+        let _ = self.lock_client.release_locks(vec![], write_keys).await;
+        res
+    }
+
+    // the keys writers of `wrapped_key` lock, see set and list_append
+    fn repair_lock_keys(&self, wrapped_key: &str) -> Vec<String> {
+        let lock_key = match wrapped_key.strip_prefix(LIST_LOG_PREFIX) {
+            Some(key) => key.to_string(),
+            None => wrapped_key.to_string(),
+        };
+        self.lockkey_decorator(vec![lock_key])
+    }
+
+    fn read_quorum(&self) -> usize {
+        self.consistency.required(self.replicas)
+    }
+
+    fn check_read_quorum(&self, answered: usize) -> TribResult<()> {
+        if answered < self.read_quorum() {
+            return Err(Box::new(TribblerError::QuorumNotReached(
+                self.read_quorum(),
+                answered,
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_write_quorum(&self, report: &WriteReport) -> TribResult<()> {
+        let required = self.consistency.required(self.replicas);
+        if report.acked.len() < required {
+            return Err(Box::new(TribblerError::QuorumNotReached(
+                required,
+                report.acked.len(),
+            )));
+        }
+        Ok(())
+    }
+
+    /// publishes the report of a write to `wrapped_key` and, once enough
+    /// replicas acknowledged it, records it in the history of those that did.
+    async fn finish_write(
+        &self,
+        replicas: &[BinPrefixAdapter],
+        report: WriteReport,
+        action: &str,
+        value: String,
+    ) -> TribResult<()> {
+        *self.last_write.write().await = Some(report.clone());
+        self.check_write_quorum(&report)?;
+        self.record_history(&report.acked_replicas(replicas), &report.key, action, value)
+            .await
+    }
+
+    /// the index of the answer to trust among those read from several
+    /// replicas: the one of the replica whose history of `wrapped_key` holds
+    /// the latest write. Only when the answers differ are the histories read
+    /// and `replicas` repaired in the background.
+    async fn freshest_answer<T: PartialEq + Sync>(
+        &self,
+        replicas: &[BinPrefixAdapter],
+        answers: &[(&BinPrefixAdapter, T)],
+        wrapped_key: &str,
+    ) -> usize {
+        if answers.iter().all(|(_, value)| *value == answers[0].1) {
+            return 0;
         }
+        self.spawn_read_repair(replicas, wrapped_key);
+        let history_key = format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key);
+        let mut freshest = (0, 0);
+        for (index, (replica, _)) in answers.iter().enumerate() {
+            let latest = match self.get_sorted_log_struct(replica, &history_key).await {
+                Ok(records) => records.last().map_or(0, |record| record.clock_id),
+                Err(_) => continue,
+            };
+            if latest > freshest.1 {
+                freshest = (index, latest);
+            }
+        }
+        freshest.0
     }
 
     pub fn acquire_lock(&mut self) {
@@ -73,6 +494,259 @@ impl BinReplicatorAdapter {
     pub fn release_lock(&mut self) {
         self.with_lock = false;
     }
+
+    /// the replicas of this bin: the first `replicas` live backends met on
+    /// the ring from the bin on, leaving out those that fail to answer
+    /// `probe_key`, or that have no value for it when `require_value` is set.
+    async fn scan_replicas(&self, probe_key: &str, require_value: bool) -> Vec<BinPrefixAdapter> {
+        let backs = self.backs.clone();
+        let mut replicas = vec![];
+        for backend_index in self
+            .ring
+            .replicas(&self.bin, &self.back_status, self.replicas)
+        {
+            let backend_addr = &backs[backend_index];
+            let chan_res =
+                update_channel_cache(self.channel_cache.clone(), backend_addr.clone()).await;
+            if chan_res.is_err() {
+                continue;
+            }
+            let pinger = StorageClient::new(backend_addr, Some(chan_res.unwrap()));
+            match pinger.get(probe_key).await {
+                Ok(value) if value.is_some() || !require_value => (),
+                _ => continue,
+            }
+            replicas.push(BinPrefixAdapter::new(
+                backend_addr,
+                &self.bin.to_string(),
+                self.channel_cache.clone(),
+            ));
+        }
+        replicas
+    }
+
+    /// whether `replica` has finished migrating, i.e. holds the validation bit.
+    async fn is_valid(&self, replica: &BinPrefixAdapter) -> bool {
+        let chan_res = update_channel_cache(self.channel_cache.clone(), replica.addr.clone()).await;
+        if chan_res.is_err() {
+            return false;
+        }
+        let pinger = StorageClient::new(&replica.addr, Some(chan_res.unwrap()));
+        matches!(pinger.get(VALIDATION_BIT_KEY).await, Ok(Some(_)))
+    }
+
+    /// appends a clock-stamped record of a write on `wrapped_key` to its
+    /// history on every replica. The plain values stay the source of normal
+    /// reads, the history only serves reads at a past clock.
+    async fn record_history(
+        &self,
+        replicas: &[BinPrefixAdapter],
+        wrapped_key: &str,
+        action: &str,
+        value: String,
+    ) -> TribResult<()> {
+        let clock_id = match replicas.split_first() {
+            Some((first, rest)) => {
+                let clk = first.clock(0).await?;
+                for replica in rest.iter() {
+                    let _ = replica.clock(clk).await;
+                }
+                clk
+            }
+            None => return Err(Box::new(NotEnoughServers)),
+        };
+        let record = SortableLogRecord {
+            wrapped_string: value,
+            clock_id,
+            action: action.to_string(),
+        };
+        let report = self
+            .append_action(
+                replicas,
+                &format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key),
+                &storage::KeyValue {
+                    key: wrapped_key.to_string(),
+                    value: serde_json::to_string(&record)?,
+                },
+            )
+            .await;
+        self.check_write_quorum(&report)
+    }
+
+    /// the history records of `wrapped_key` up to `clock`, merged from the
+    /// replicas that can still serve `clock`. A replica can not once it
+    /// compacted its history past `clock`, and the read fails when no
+    /// replica can.
+    async fn history_until(
+        &self,
+        wrapped_key: &str,
+        clock: u64,
+    ) -> TribResult<Vec<SortableLogRecord>> {
+        let replicas = self.get_read_replicas_access_new().await;
+        if replicas.is_empty() {
+            return Err(Box::new(NotEnoughServers));
+        }
+        let history_key = format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key);
+        let mut records = vec![];
+        let mut compacted = false;
+        let mut served = false;
+        for adapter in replicas.iter() {
+            let logs = match self.get_sorted_log_struct(adapter, &history_key).await {
+                Ok(logs) => logs,
+                Err(_) => continue,
+            };
+            // a checkpoint folds every record up to its clock
+            if logs
+                .iter()
+                .any(|record| record.is_checkpoint() && record.clock_id > clock)
+            {
+                compacted = true;
+                continue;
+            }
+            served = true;
+            records.extend(logs.into_iter().filter(|record| record.clock_id <= clock));
+        }
+        if compacted && !served {
+            return Err(Box::new(TribblerError::SnapshotCompacted(clock)));
+        }
+        sort_records(&mut records);
+        Ok(records)
+    }
+
+    /// the clock of the latest write on `wrapped_key` the replicas recorded
+    /// in its history, 0 when it was never written. Compaction keeps it, as
+    /// the clock of the checkpoint.
+    pub async fn latest_clock(&self, wrapped_key: &str) -> TribResult<u64> {
+        let replicas = self.get_read_replicas_access_new().await;
+        let history_key = format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key);
+        let mut latest = None;
+        for adapter in replicas.iter() {
+            if let Ok(logs) = self.get_sorted_log_struct(adapter, &history_key).await {
+                let clock = logs.last().map_or(0, |record| record.clock_id);
+                latest = Some(cmp::max(latest.unwrap_or(0), clock));
+            }
+        }
+        match latest {
+            Some(latest) => Ok(latest),
+            None => Err(Box::new(NotEnoughServers)),
+        }
+    }
+
+    /// returns the value `key` held at `clock`.
+    pub async fn get_at(&self, key: &str, clock: u64) -> TribResult<Option<String>> {
+        let wrapped_key = format!("{}{}", STR_LOG_PREFIX, key);
+        Ok(replay_str(&self.history_until(&wrapped_key, clock).await?))
+    }
+
+    /// returns the list `key` held at `clock`.
+    pub async fn list_get_at(&self, key: &str, clock: u64) -> TribResult<storage::List> {
+        let wrapped_key = format!("{}{}", LIST_LOG_PREFIX, key);
+        let records = self.history_until(&wrapped_key, clock).await?;
+        Ok(storage::List(replay_list(&records)?))
+    }
+
+    /// folds the history records of `wrapped_key` up to `safe_clock` into a
+    /// single checkpoint record, on each replica on its own, under the lock
+    /// the writers of the key take so no write lands in between. The checkpoint is
+    /// appended before the records it replaces are removed, and replays to
+    /// the same state as them, so a concurrent reader sees the same value
+    /// whichever of them it reads. Snapshots at a clock before `safe_clock`
+    /// can no longer be read afterwards.
+    pub async fn compact_history(&self, wrapped_key: &str, safe_clock: u64) -> TribResult<()> {
+        let write_keys = self.repair_lock_keys(wrapped_key);
+        if !self.with_lock {
+            self.lock_client
+                .acquire_locks(vec![], write_keys.clone())
+                .await?;
+        }
+        let res = self.compact_history_locked(wrapped_key, safe_clock).await;
+        if !self.with_lock {
+            self.lock_client.release_locks(vec![], write_keys).await?;
+        }
+        res
+    }
+
+    async fn compact_history_locked(&self, wrapped_key: &str, safe_clock: u64) -> TribResult<()> {
+        let replicas = self.get_read_replicas_access_new().await;
+        if replicas.is_empty() {
+            return Err(Box::new(NotEnoughServers));
+        }
+        let history_key = format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key);
+        for adapter in replicas.iter() {
+            let mut folded = vec![];
+            for raw in adapter.list_get(&history_key).await?.0 {
+                let record: SortableLogRecord = serde_json::from_str(&raw)?;
+                if record.clock_id <= safe_clock {
+                    folded.push((raw, record));
+                }
+            }
+            if folded.len() <= 1 {
+                continue;
+            }
+            let mut records = folded
+                .iter()
+                .map(|(_, record)| record.clone())
+                .collect::<Vec<SortableLogRecord>>();
+            sort_records(&mut records);
+            let wrapped_string = if wrapped_key.starts_with(LIST_LOG_PREFIX) {
+                serde_json::to_string(&replay_list(&records)?)?
+            } else {
+                replay_str(&records).unwrap_or_default()
+            };
+            let checkpoint = SortableLogRecord {
+                wrapped_string,
+                clock_id: records.last().map_or(0, |record| record.clock_id),
+                action: CHECKPOINT_ACTION.to_string(),
+            };
+            adapter
+                .list_append(&storage::KeyValue {
+                    key: history_key.to_string(),
+                    value: serde_json::to_string(&checkpoint)?,
+                })
+                .await?;
+            for (raw, _) in folded.iter() {
+                adapter
+                    .list_remove(&storage::KeyValue {
+                        key: history_key.to_string(),
+                        value: raw.to_string(),
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// removes the whole history of `wrapped_key` from every replica, under
+    /// the write lock of the key, for keys that were cleared and are never
+    /// read at a past clock.
+    pub(crate) async fn drop_history(&self, wrapped_key: &str) -> TribResult<()> {
+        let write_keys = self.repair_lock_keys(wrapped_key);
+        if !self.with_lock {
+            self.lock_client
+                .acquire_locks(vec![], write_keys.clone())
+                .await?;
+        }
+        let res = self.drop_history_locked(wrapped_key).await;
+        if !self.with_lock {
+            self.lock_client.release_locks(vec![], write_keys).await?;
+        }
+        res
+    }
+
+    async fn drop_history_locked(&self, wrapped_key: &str) -> TribResult<()> {
+        let replicas = self.get_write_replicas_access().await;
+        if replicas.is_empty() {
+            return Err(Box::new(NotEnoughServers));
+        }
+        let empty = storage::KeyValueList {
+            key: format!("{}{}", HISTORY_LOG_PREFIX, wrapped_key),
+            list: vec![],
+        };
+        for replica in replicas.iter() {
+            replica.list_set(&empty).await?;
+        }
+        Ok(())
+    }
 }
 
 use async_trait::async_trait;
@@ -89,20 +763,13 @@ impl storage::KeyString for BinReplicatorAdapter {
         }
 
         // Get ther first alive and valid bin.
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_read_replicas_access_new().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        let replicas = self.get_read_replicas_access_new().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
         // Get all logs
-        let result_str = self
-            .get_action(
-                &primary_adapter_option,
-                &secondary_adapter_option,
-                &wrapped_key,
-            )
-            .await;
+        let result_str = self.get_action(&replicas, &wrapped_key).await;
 
         if !self.with_lock {
             self.lock_client
@@ -115,10 +782,9 @@ impl storage::KeyString for BinReplicatorAdapter {
     async fn set(&self, kv: &storage::KeyValue) -> TribResult<bool> {
         let wrapped_key = format!("{}{}", STR_LOG_PREFIX, kv.key);
 
-        // Get first two "valid" bins.
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_write_replicas_access().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        // Get the first `replicas` "valid" bins.
+        let replicas = self.get_write_replicas_access().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
@@ -134,15 +800,12 @@ impl storage::KeyString for BinReplicatorAdapter {
                 .await?;
         }
 
-        // Try to append the entry in primary and secondary
+        // Try to set the entry in every replica
+        let report = self.set_action(&replicas, &wrapped_key, kv).await;
         let result = self
-            .set_action(
-                &primary_adapter_option,
-                &secondary_adapter_option,
-                &wrapped_key,
-                kv,
-            )
-            .await;
+            .finish_write(&replicas, report, SET_ACTION, kv.value.to_string())
+            .await
+            .map(|_| true);
         // println!(
         //     "set release {:?}",
         //     self.lockkey_decorator(write_keys.clone())
@@ -158,9 +821,8 @@ impl storage::KeyString for BinReplicatorAdapter {
     async fn keys(&self, p: &storage::Pattern) -> TribResult<storage::List> {
         let wrapped_prefx = format!("{}{}", STR_LOG_PREFIX, p.prefix);
 
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_read_replicas_access_new().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        let replicas = self.get_read_replicas_access_new().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
@@ -174,8 +836,7 @@ impl storage::KeyString for BinReplicatorAdapter {
 
         let potential_keys = self
             .keys_action(
-                &primary_adapter_option,
-                &secondary_adapter_option,
+                &replicas,
                 &storage::Pattern {
                     prefix: wrapped_prefx.to_string(),
                     suffix: p.suffix.to_string(),
@@ -201,9 +862,7 @@ impl storage::KeyString for BinReplicatorAdapter {
 pub trait BinReplicatorHelper {
     fn lockkey_decorator(&self, keys: Vec<String>) -> Vec<String>;
     async fn get_read_replicas_access(&self) -> Option<BinPrefixAdapter>; // starting from returning the first living valid machine
-    async fn get_write_replicas_access(
-        &self,
-    ) -> (Option<BinPrefixAdapter>, Option<BinPrefixAdapter>); // return two first trues from back_status
+    async fn get_write_replicas_access(&self) -> Vec<BinPrefixAdapter>; // return the first `replicas` trues from back_status
     async fn get_sorted_log_struct(
         &self,
         bin_prefix_adapter: &BinPrefixAdapter,
@@ -211,59 +870,49 @@ pub trait BinReplicatorHelper {
     ) -> TribResult<Vec<SortableLogRecord>>; // Get all logs. Sort and dedup.
     async fn append_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
         kv: &storage::KeyValue,
-    ) -> TribResult<bool>;
+    ) -> WriteReport;
     async fn remove_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
         kv: &storage::KeyValue,
-    ) -> TribResult<u32>;
+    ) -> (u32, WriteReport);
     async fn set_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
         kv: &storage::KeyValue,
-    ) -> TribResult<bool>;
+    ) -> WriteReport;
     async fn get_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
     ) -> TribResult<Option<String>>;
     async fn get_list_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
     ) -> TribResult<storage::List>;
     async fn set_list_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
         kl: &storage::KeyValueList,
-    ) -> TribResult<bool>;
+    ) -> WriteReport;
     async fn keys_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         p: &storage::Pattern,
     ) -> TribResult<Vec<String>>;
     async fn list_keys_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         p: &storage::Pattern,
     ) -> TribResult<Vec<String>>;
-    async fn get_read_replicas_access_new(
-        &self,
-    ) -> (Option<BinPrefixAdapter>, Option<BinPrefixAdapter>);
+    async fn get_read_replicas_access_new(&self) -> Vec<BinPrefixAdapter>;
 }
 
 #[async_trait]
@@ -279,13 +928,10 @@ impl BinReplicatorHelper for BinReplicatorAdapter {
 
     async fn get_read_replicas_access(&self) -> Option<BinPrefixAdapter> {
         let backs = self.backs.clone();
-        let start = self.hash_index as usize;
-        let end = start + backs.len();
-        for i in start..end {
-            let backend_index = i % backs.len();
-            if self.back_status[backend_index] == false {
-                continue;
-            }
+        for backend_index in self
+            .ring
+            .replicas(&self.bin, &self.back_status, backs.len())
+        {
             let backend_addr = &backs[backend_index];
             let chan_res =
                 update_channel_cache(self.channel_cache.clone(), backend_addr.clone()).await;
@@ -310,77 +956,8 @@ impl BinReplicatorHelper for BinReplicatorAdapter {
         }
         return None;
     }
-    async fn get_write_replicas_access(
-        &self,
-    ) -> (Option<BinPrefixAdapter>, Option<BinPrefixAdapter>) {
-        let backs = self.backs.clone();
-        let start = self.hash_index as usize;
-        let end = start + backs.len();
-        let mut primary_adapter_option = None;
-        let mut secondary_adapter_option = None;
-        for i in start..end {
-            // start scanning the primary replica
-            let primary_backend_index = i % backs.len();
-            if self.back_status[primary_backend_index] == false {
-                continue;
-            }
-            let primary_backend_addr = &backs[primary_backend_index];
-            let primary_chan_res =
-                update_channel_cache(self.channel_cache.clone(), primary_backend_addr.clone())
-                    .await;
-            if !primary_chan_res.is_err() {
-                let primary_pinger =
-                    StorageClient::new(primary_backend_addr, Some(primary_chan_res.unwrap()));
-                let primary_resp = primary_pinger.get("DUMMY").await;
-                if primary_resp.is_err() {
-                    primary_adapter_option = None;
-                } else {
-                    primary_adapter_option = Some(BinPrefixAdapter::new(
-                        &primary_backend_addr,
-                        &self.bin.to_string(),
-                        self.channel_cache.clone(),
-                    ));
-                }
-            } else {
-                // println!("Channel get failed!");
-            }
-
-            // start scanning the secondary replica
-            for j in i + 1..end {
-                let secondary_backend_index = j % backs.len();
-                if self.back_status[secondary_backend_index] == false {
-                    continue;
-                }
-                let secondary_backend_addr = &backs[secondary_backend_index];
-                let secondary_chan_res = update_channel_cache(
-                    self.channel_cache.clone(),
-                    secondary_backend_addr.clone(),
-                )
-                .await;
-
-                if !secondary_chan_res.is_err() {
-                    let secondary_pinger = StorageClient::new(
-                        secondary_backend_addr,
-                        Some(secondary_chan_res.unwrap()),
-                    );
-                    let secondary_resp = secondary_pinger.get("DUMMY").await;
-                    if secondary_resp.is_err() {
-                        secondary_adapter_option = None;
-                    } else {
-                        secondary_adapter_option = Some(BinPrefixAdapter::new(
-                            &secondary_backend_addr,
-                            &self.bin.to_string(),
-                            self.channel_cache.clone(),
-                        ));
-                    }
-                } else {
-                    // println!("Channel get failed!");
-                }
-                break;
-            }
-            break;
-        }
-        return (primary_adapter_option, secondary_adapter_option);
+    async fn get_write_replicas_access(&self) -> Vec<BinPrefixAdapter> {
+        self.scan_replicas("DUMMY", false).await
     }
 
     async fn get_sorted_log_struct(
@@ -391,349 +968,174 @@ impl BinReplicatorHelper for BinReplicatorAdapter {
         let logs_string = bin_prefix_adapter.list_get(&wrapped_key).await?.0;
         let mut logs_struct = vec![];
         for element in logs_string {
-            let log_entry: SortableLogRecord = serde_json::from_str(&element).unwrap();
+            let log_entry: SortableLogRecord = serde_json::from_str(&element)?;
             logs_struct.push(log_entry);
         }
-        logs_struct.sort_unstable_by(|a, b| {
-            if a.clock_id < b.clock_id {
-                return Ordering::Less;
-            } else if a.clock_id > b.clock_id {
-                return Ordering::Greater;
-            }
-            return Ordering::Equal;
-        });
-        logs_struct.dedup_by(|a, b| a.clock_id == b.clock_id); // Is it necessary to dedup?
+        sort_records(&mut logs_struct);
         Ok(logs_struct)
     }
 
     async fn append_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
         kv: &storage::KeyValue,
-    ) -> TribResult<bool> {
+    ) -> WriteReport {
         let new_kv = &storage::KeyValue {
             key: wrapped_key.to_string(),
             value: kv.value.to_string(),
         };
-        if primary_adapter_option.is_some() && secondary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            // println!(
-            //     "{},{}",
-            //     primary_bin_prefix_adapter.addr, secondary_bin_prefix_adapter.addr
-            // );
-            primary_bin_prefix_adapter.list_append(new_kv).await;
-            secondary_bin_prefix_adapter.list_append(new_kv).await;
-        } else if primary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            primary_bin_prefix_adapter.list_append(new_kv).await;
-        } else {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            secondary_bin_prefix_adapter.list_append(new_kv).await;
+        let mut report = WriteReport::new(&self.bin, wrapped_key);
+        for replica in replicas.iter() {
+            let res = replica.list_append(new_kv).await;
+            report.record(replica, matches!(res, Ok(true)));
         }
-        Ok(true)
+        report
     }
 
     async fn set_list_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
         kl: &storage::KeyValueList,
-    ) -> TribResult<bool> {
+    ) -> WriteReport {
         let new_kl = &storage::KeyValueList {
             key: wrapped_key.to_string(),
             list: kl.list.clone(),
         };
-        if primary_adapter_option.is_some() && secondary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            // println!(
-            //     "{},{}",
-            //     primary_bin_prefix_adapter.addr, secondary_bin_prefix_adapter.addr
-            // );
-            primary_bin_prefix_adapter.list_set(new_kl).await;
-            secondary_bin_prefix_adapter.list_set(new_kl).await;
-        } else if primary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            primary_bin_prefix_adapter.list_set(new_kl).await;
-        } else {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            secondary_bin_prefix_adapter.list_set(new_kl).await;
+        let mut report = WriteReport::new(&self.bin, wrapped_key);
+        for replica in replicas.iter() {
+            let res = replica.list_set(new_kl).await;
+            report.record(replica, matches!(res, Ok(true)));
         }
-        Ok(true)
+        report
     }
 
     async fn remove_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
         kv: &storage::KeyValue,
-    ) -> TribResult<u32> {
-        if primary_adapter_option.is_some() && secondary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let result1 = primary_bin_prefix_adapter.list_remove(kv).await;
-            let result2 = secondary_bin_prefix_adapter.list_remove(kv).await;
-            if result1.is_err() {
-                return result2;
+    ) -> (u32, WriteReport) {
+        let kv = &storage::KeyValue {
+            key: wrapped_key.to_string(),
+            value: kv.value.to_string(),
+        };
+        // the count comes from the first replica that removed successfully
+        let mut removed = None;
+        let mut report = WriteReport::new(&self.bin, wrapped_key);
+        for replica in replicas.iter() {
+            let res = replica.list_remove(kv).await;
+            report.record(replica, res.is_ok());
+            if let Ok(count) = res {
+                removed.get_or_insert(count);
             }
-            return result1;
-        } else if primary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let result = primary_bin_prefix_adapter.list_remove(kv).await;
-            return result;
-        } else {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let result = secondary_bin_prefix_adapter.list_remove(kv).await;
-            return result;
         }
+        (removed.unwrap_or(0), report)
     }
 
     async fn set_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
         kv: &storage::KeyValue,
-    ) -> TribResult<bool> {
+    ) -> WriteReport {
         let new_kv = &storage::KeyValue {
             key: wrapped_key.to_string(),
             value: kv.value.to_string(),
         };
-        if primary_adapter_option.is_some() && secondary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            primary_bin_prefix_adapter.set(new_kv).await;
-            secondary_bin_prefix_adapter.set(new_kv).await;
-        } else if primary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            primary_bin_prefix_adapter.set(new_kv).await;
-        } else if secondary_adapter_option.is_some() {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            secondary_bin_prefix_adapter.set(new_kv).await;
+        let mut report = WriteReport::new(&self.bin, wrapped_key);
+        for replica in replicas.iter() {
+            let res = replica.set(new_kv).await;
+            report.record(replica, matches!(res, Ok(true)));
         }
-        Ok(true)
+        report
     }
 
     async fn get_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
     ) -> TribResult<Option<String>> {
-        if primary_adapter_option.is_some() && secondary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let vec_primary = primary_bin_prefix_adapter.get(wrapped_key).await;
-            if !vec_primary.is_err() {
-                return Ok(vec_primary.unwrap());
-            }
-            let vec_secondary = secondary_bin_prefix_adapter.get(wrapped_key).await;
-            if !vec_secondary.is_err() {
-                return Ok(vec_secondary.unwrap());
-            }
-            return Ok(None);
-        } else if primary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let vec_primary = primary_bin_prefix_adapter.get(wrapped_key).await;
-            if !vec_primary.is_err() {
-                return Ok(vec_primary.unwrap());
+        let mut answers = vec![];
+        for replica in replicas.iter() {
+            if answers.len() == self.read_quorum() {
+                break;
             }
-            return Ok(None);
-        } else if secondary_adapter_option.is_some() {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let vec_secondary = secondary_bin_prefix_adapter.get(wrapped_key).await;
-            if !vec_secondary.is_err() {
-                return Ok(vec_secondary.unwrap());
+            if let Ok(value) = replica.get(wrapped_key).await {
+                answers.push((replica, value));
             }
-            return Ok(None);
         }
-        return Ok(None);
+        self.check_read_quorum(answers.len())?;
+        let freshest = self.freshest_answer(replicas, &answers, wrapped_key).await;
+        Ok(answers.swap_remove(freshest).1)
     }
 
     async fn get_list_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         wrapped_key: &str,
     ) -> TribResult<storage::List> {
-        if primary_adapter_option.is_some() && secondary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let vec_primary = primary_bin_prefix_adapter.list_get(wrapped_key).await;
-            if !vec_primary.is_err() {
-                return Ok(vec_primary.unwrap());
-            }
-            let vec_secondary = secondary_bin_prefix_adapter.list_get(wrapped_key).await;
-            if !vec_secondary.is_err() {
-                return Ok(vec_secondary.unwrap());
-            }
-            return Ok(storage::List(vec![] as Vec<String>));
-        } else if primary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let vec_primary = primary_bin_prefix_adapter.list_get(wrapped_key).await;
-            if !vec_primary.is_err() {
-                return Ok(vec_primary.unwrap());
+        let mut answers = vec![];
+        for replica in replicas.iter() {
+            if answers.len() == self.read_quorum() {
+                break;
             }
-            return Ok(storage::List(vec![] as Vec<String>));
-        } else if secondary_adapter_option.is_some() {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let vec_secondary = secondary_bin_prefix_adapter.list_get(wrapped_key).await;
-            if !vec_secondary.is_err() {
-                return Ok(vec_secondary.unwrap());
+            if let Ok(list) = replica.list_get(wrapped_key).await {
+                answers.push((replica, list.0));
             }
-            return Ok(storage::List(vec![] as Vec<String>));
         }
-        return Ok(storage::List(vec![] as Vec<String>));
+        self.check_read_quorum(answers.len())?;
+        let freshest = self.freshest_answer(replicas, &answers, wrapped_key).await;
+        Ok(storage::List(answers.swap_remove(freshest).1))
     }
 
     async fn keys_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         p: &storage::Pattern,
     ) -> TribResult<Vec<String>> {
-        if primary_adapter_option.is_some() && secondary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let vec_primary = primary_bin_prefix_adapter.keys(p).await;
-            if !vec_primary.is_err() {
-                return Ok(vec_primary.unwrap().0);
-            }
-            let vec_secondary = secondary_bin_prefix_adapter.keys(p).await;
-            if !vec_secondary.is_err() {
-                return Ok(vec_secondary.unwrap().0);
-            }
-            return Ok(vec![] as Vec<String>);
-        } else if primary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let vec_primary = primary_bin_prefix_adapter.keys(p).await;
-            if !vec_primary.is_err() {
-                return Ok(vec_primary.unwrap().0);
+        let mut answered = 0;
+        let mut keys = vec![];
+        for replica in replicas.iter() {
+            if answered == self.read_quorum() {
+                break;
             }
-            return Ok(vec![] as Vec<String>);
-        } else if secondary_adapter_option.is_some() {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let vec_secondary = secondary_bin_prefix_adapter.keys(p).await;
-            if !vec_secondary.is_err() {
-                return Ok(vec_secondary.unwrap().0);
+            if let Ok(mut replica_keys) = replica.keys(p).await {
+                answered += 1;
+                keys.append(&mut replica_keys.0);
             }
-            return Ok(vec![] as Vec<String>);
         }
-        return Ok(vec![] as Vec<String>);
+        self.check_read_quorum(answered)?;
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
     }
 
     async fn list_keys_action(
         &self,
-        primary_adapter_option: &Option<BinPrefixAdapter>,
-        secondary_adapter_option: &Option<BinPrefixAdapter>,
+        replicas: &[BinPrefixAdapter],
         p: &storage::Pattern,
     ) -> TribResult<Vec<String>> {
-        if primary_adapter_option.is_some() && secondary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let vec_primary = primary_bin_prefix_adapter.list_keys(p).await;
-            if !vec_primary.is_err() {
-                return Ok(vec_primary.unwrap().0);
-            }
-            let vec_secondary = secondary_bin_prefix_adapter.list_keys(p).await;
-            if !vec_secondary.is_err() {
-                return Ok(vec_secondary.unwrap().0);
-            }
-            return Ok(vec![] as Vec<String>);
-        } else if primary_adapter_option.is_some() {
-            let primary_bin_prefix_adapter = primary_adapter_option.as_ref().unwrap();
-            let vec_primary = primary_bin_prefix_adapter.list_keys(p).await;
-            if !vec_primary.is_err() {
-                return Ok(vec_primary.unwrap().0);
+        let mut answered = 0;
+        let mut keys = vec![];
+        for replica in replicas.iter() {
+            if answered == self.read_quorum() {
+                break;
             }
-            return Ok(vec![] as Vec<String>);
-        } else if secondary_adapter_option.is_some() {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-            let vec_secondary = secondary_bin_prefix_adapter.list_keys(p).await;
-            if !vec_secondary.is_err() {
-                return Ok(vec_secondary.unwrap().0);
+            if let Ok(mut replica_keys) = replica.list_keys(p).await {
+                answered += 1;
+                keys.append(&mut replica_keys.0);
             }
-            return Ok(vec![] as Vec<String>);
         }
-        return Ok(vec![] as Vec<String>);
+        self.check_read_quorum(answered)?;
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
     }
 
-    async fn get_read_replicas_access_new(
-        &self,
-    ) -> (Option<BinPrefixAdapter>, Option<BinPrefixAdapter>) {
-        let backs = self.backs.clone();
-        let start = self.hash_index as usize;
-        let end = start + backs.len();
-        let mut primary_adapter_option = None;
-        let mut secondary_adapter_option = None;
-        for i in start..end {
-            // start scanning the primary replica
-            let primary_backend_index = i % backs.len();
-            if self.back_status[primary_backend_index] == false {
-                continue;
-            }
-            let primary_backend_addr = &backs[primary_backend_index];
-            let primary_chan_res =
-                update_channel_cache(self.channel_cache.clone(), primary_backend_addr.clone())
-                    .await;
-            if !primary_chan_res.is_err() {
-                let primary_pinger =
-                    StorageClient::new(primary_backend_addr, Some(primary_chan_res.unwrap()));
-                let primary_resp = primary_pinger.get(VALIDATION_BIT_KEY).await;
-                if primary_resp.is_err() || primary_resp.unwrap().is_none() {
-                    primary_adapter_option = None;
-                } else {
-                    primary_adapter_option = Some(BinPrefixAdapter::new(
-                        &primary_backend_addr,
-                        &self.bin.to_string(),
-                        self.channel_cache.clone(),
-                    ));
-                }
-            } else {
-                // println!("Channel get failed!");
-            }
-
-            // start scanning the secondary replica
-            for j in i + 1..end {
-                let secondary_backend_index = j % backs.len();
-                if self.back_status[secondary_backend_index] == false {
-                    continue;
-                }
-                let secondary_backend_addr = &backs[secondary_backend_index];
-                let secondary_chan_res = update_channel_cache(
-                    self.channel_cache.clone(),
-                    secondary_backend_addr.clone(),
-                )
-                .await;
-
-                if !secondary_chan_res.is_err() {
-                    let secondary_pinger = StorageClient::new(
-                        secondary_backend_addr,
-                        Some(secondary_chan_res.unwrap()),
-                    );
-                    let secondary_resp = secondary_pinger.get(VALIDATION_BIT_KEY).await;
-                    if secondary_resp.is_err() || secondary_resp.unwrap().is_none() {
-                        secondary_adapter_option = None;
-                    } else {
-                        secondary_adapter_option = Some(BinPrefixAdapter::new(
-                            &secondary_backend_addr,
-                            &self.bin.to_string(),
-                            self.channel_cache.clone(),
-                        ));
-                    }
-                } else {
-                    // println!("Channel get failed!");
-                }
-                break;
-            }
-            break;
-        }
-        return (primary_adapter_option, secondary_adapter_option);
+    async fn get_read_replicas_access_new(&self) -> Vec<BinPrefixAdapter> {
+        self.scan_replicas(VALIDATION_BIT_KEY, true).await
     }
 }
 
@@ -742,9 +1144,8 @@ impl storage::KeyList for BinReplicatorAdapter {
     async fn list_get(&self, key: &str) -> TribResult<storage::List> {
         let wrapped_key = format!("{}{}", LIST_LOG_PREFIX, key);
 
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_read_replicas_access_new().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        let replicas = self.get_read_replicas_access_new().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
@@ -757,30 +1158,22 @@ impl storage::KeyList for BinReplicatorAdapter {
         }
 
         // Get all logs
-        let list_ret = self
-            .get_list_action(
-                &primary_adapter_option,
-                &secondary_adapter_option,
-                &wrapped_key,
-            )
-            .await;
-        let res = list_ret.unwrap();
-        // println!("get list action: {:?}", res.0);
+        let list_ret = self.get_list_action(&replicas, &wrapped_key).await;
+        // println!("get list action: {:?}", list_ret);
         if !self.with_lock {
             self.lock_client
                 .release_locks(self.lockkey_decorator(read_keys), vec![])
                 .await?;
         }
-        return Ok(res);
+        return list_ret;
     }
 
     async fn list_append(&self, kv: &storage::KeyValue) -> TribResult<bool> {
         let wrapped_key = format!("{}{}", LIST_LOG_PREFIX, kv.key);
 
-        // Get first two "valid" bins.
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_write_replicas_access().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        // Get the first `replicas` "valid" bins.
+        let replicas = self.get_write_replicas_access().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
@@ -792,15 +1185,12 @@ impl storage::KeyList for BinReplicatorAdapter {
                 .await?;
         }
 
-        // Try to append the entry in primary and secondary
+        // Try to append the entry in every replica
+        let report = self.append_action(&replicas, &wrapped_key, kv).await;
         let result = self
-            .append_action(
-                &primary_adapter_option,
-                &secondary_adapter_option,
-                &wrapped_key,
-                kv,
-            )
-            .await;
+            .finish_write(&replicas, report, APPEND_ACTION, kv.value.to_string())
+            .await
+            .map(|_| true);
         if !self.with_lock {
             self.lock_client
                 .release_locks(vec![], self.lockkey_decorator(write_keys))
@@ -812,10 +1202,9 @@ impl storage::KeyList for BinReplicatorAdapter {
     async fn list_set(&self, kl: &storage::KeyValueList) -> TribResult<bool> {
         let wrapped_key = format!("{}{}", LIST_LOG_PREFIX, kl.key);
 
-        // Get first two "valid" bins.
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_write_replicas_access().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        // Get the first `replicas` "valid" bins.
+        let replicas = self.get_write_replicas_access().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
@@ -827,15 +1216,17 @@ impl storage::KeyList for BinReplicatorAdapter {
                 .await?;
         }
 
-        // Try to append the entry in primary and secondary
+        // Try to set the list in every replica
+        let report = self.set_list_action(&replicas, &wrapped_key, kl).await;
         let result = self
-            .set_list_action(
-                &primary_adapter_option,
-                &secondary_adapter_option,
-                &wrapped_key,
-                kl,
+            .finish_write(
+                &replicas,
+                report,
+                SET_ACTION,
+                serde_json::to_string(&kl.list)?,
             )
-            .await;
+            .await
+            .map(|_| true);
 
         if !self.with_lock {
             self.lock_client
@@ -848,10 +1239,9 @@ impl storage::KeyList for BinReplicatorAdapter {
     async fn list_remove(&self, kv: &storage::KeyValue) -> TribResult<u32> {
         let wrapped_key = format!("{}{}", LIST_LOG_PREFIX, kv.key);
 
-        // Get first two "valid" bins.
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_write_replicas_access().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        // Get the first `replicas` "valid" bins.
+        let replicas = self.get_write_replicas_access().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
@@ -863,15 +1253,12 @@ impl storage::KeyList for BinReplicatorAdapter {
                 .await?;
         }
 
-        // Try to remove the entry in primary and secondary
+        // Try to remove the entry in every replica
+        let (removed, report) = self.remove_action(&replicas, &wrapped_key, kv).await;
         let result = self
-            .remove_action(
-                &primary_adapter_option,
-                &secondary_adapter_option,
-                &wrapped_key,
-                kv,
-            )
-            .await;
+            .finish_write(&replicas, report, REMOVE_ACTION, kv.value.to_string())
+            .await
+            .map(|_| removed);
 
         if !self.with_lock {
             self.lock_client
@@ -885,9 +1272,8 @@ impl storage::KeyList for BinReplicatorAdapter {
         let wrapped_prefx = format!("{}{}", LIST_LOG_PREFIX, p.prefix);
 
         // Get ther first alive and valid bin.
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_read_replicas_access_new().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        let replicas = self.get_read_replicas_access_new().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
@@ -901,8 +1287,7 @@ impl storage::KeyList for BinReplicatorAdapter {
 
         let potential_keys = self
             .list_keys_action(
-                &primary_adapter_option,
-                &secondary_adapter_option,
+                &replicas,
                 &storage::Pattern {
                     prefix: wrapped_prefx.to_string(),
                     suffix: p.suffix.to_string(),
@@ -928,55 +1313,41 @@ impl storage::KeyList for BinReplicatorAdapter {
 #[async_trait]
 impl storage::Storage for BinReplicatorAdapter {
     async fn clock(&self, at_least: u64) -> TribResult<u64> {
-        // Get first two "valid" bins.
-        let (primary_adapter_option, secondary_adapter_option) =
-            self.get_write_replicas_access().await;
-        if primary_adapter_option.is_none() && secondary_adapter_option.is_none() {
+        // Get the first `replicas` "valid" bins.
+        let replicas = self.get_write_replicas_access().await;
+        if replicas.is_empty() {
             return Err(Box::new(NotEnoughServers));
         }
 
-        // Sync clock
-        let mut clk = 0;
-        if primary_adapter_option.is_none() {
-            let secondary_bin_prefix_adapter = secondary_adapter_option.unwrap();
-            clk = secondary_bin_prefix_adapter.clock(at_least).await?;
-        } else if secondary_adapter_option.is_none() {
-            let primary_bin_prefix_adapter = primary_adapter_option.unwrap();
-            clk = primary_bin_prefix_adapter.clock(at_least).await?;
-        } else {
-            let primary_bin_prefix_adapter = primary_adapter_option.unwrap();
-            let secondary_bin_prefix_adapter = secondary_adapter_option.as_ref().unwrap();
-
-            let primary_chan_res = update_channel_cache(
-                self.channel_cache.clone(),
-                primary_bin_prefix_adapter.addr.clone(),
-            )
-            .await;
-            if primary_chan_res.is_err() {
-                // println!("Should not happen!")
+        // Sync clock. A replica that is not valid yet may lag behind, so the
+        // clock is taken from the first valid one that answers and then
+        // pushed to the others.
+        let mut source = None;
+        for (index, replica) in replicas.iter().enumerate() {
+            if self.is_valid(replica).await {
+                if let Ok(clk) = replica.clock(at_least).await {
+                    source = Some((index, clk));
+                    break;
+                }
             }
-            let primary_pinger = StorageClient::new(
-                &primary_bin_prefix_adapter.addr,
-                Some(primary_chan_res.unwrap()),
-            );
-            // Check whether primary is valid
-            let resp = primary_pinger.get(VALIDATION_BIT_KEY).await;
-            // let validation = resp.unwrap();
-            if !resp.is_err() && resp.unwrap() == None {
-                // If primary is not valid, sync primary using backup
-                clk = secondary_bin_prefix_adapter.clock(at_least).await?;
-                let _ = primary_bin_prefix_adapter.clock(clk).await;
-            } else {
-                let clk_res = primary_bin_prefix_adapter.clock(at_least).await;
-                if !clk_res.is_err() {
-                    clk = clk_res?;
-                    let _ = secondary_bin_prefix_adapter.clock(clk).await;
-                } else {
-                    let secondary_bin_prefix_adapter = secondary_adapter_option.unwrap();
-                    clk = secondary_bin_prefix_adapter.clock(at_least).await?;
+        }
+        if source.is_none() {
+            for (index, replica) in replicas.iter().enumerate() {
+                if let Ok(clk) = replica.clock(at_least).await {
+                    source = Some((index, clk));
+                    break;
                 }
             }
         }
+        let (source_index, clk) = match source {
+            Some(source) => source,
+            None => return Err(Box::new(NotEnoughServers)),
+        };
+        for (index, replica) in replicas.iter().enumerate() {
+            if index != source_index {
+                let _ = replica.clock(clk).await;
+            }
+        }
         Ok(clk)
     }
 }
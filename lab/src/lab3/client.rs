@@ -24,6 +24,55 @@ impl StorageClient {
         let client = TribStorageClient::connect(formatted_addr).await?;
         return Ok(client);
     }
+
+    /// the digests of the tree nodes named in `request`.
+    pub async fn digest(&self, request: &rpc::DigestRequest) -> TribResult<Vec<u64>> {
+        let mut client = match &self.chan {
+            Some(chan) => TribStorageClient::new(chan.clone()),
+            None => self.connect().await?,
+        };
+
+        let r = client.digest(request.clone()).await?;
+        Ok(r.into_inner().digests)
+    }
+
+    /// the keys that fall in the leaves named in `request`.
+    pub async fn digest_keys(&self, request: &rpc::DigestRequest) -> TribResult<Vec<String>> {
+        let mut client = match &self.chan {
+            Some(chan) => TribStorageClient::new(chan.clone()),
+            None => self.connect().await?,
+        };
+
+        let r = client.digest_keys(request.clone()).await?;
+        Ok(r.into_inner().list)
+    }
+
+    /// the string values of `keys` and the lists of `list_keys`, in one call.
+    pub async fn get_batch(&self, keys: &[String], list_keys: &[String]) -> TribResult<rpc::Batch> {
+        let mut client = match &self.chan {
+            Some(chan) => TribStorageClient::new(chan.clone()),
+            None => self.connect().await?,
+        };
+
+        let r = client
+            .get_batch(rpc::BatchKeys {
+                keys: keys.to_vec(),
+                list_keys: list_keys.to_vec(),
+            })
+            .await?;
+        Ok(r.into_inner())
+    }
+
+    /// writes every value and list of `batch`, in one call.
+    pub async fn set_batch(&self, batch: rpc::Batch) -> TribResult<bool> {
+        let mut client = match &self.chan {
+            Some(chan) => TribStorageClient::new(chan.clone()),
+            None => self.connect().await?,
+        };
+
+        let r = client.set_batch(batch).await?;
+        Ok(r.into_inner().value)
+    }
 }
 
 use async_trait::async_trait;
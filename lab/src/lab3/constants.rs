@@ -2,19 +2,57 @@ pub const STR_LOG_PREFIX: &str = "STR::";
 pub const STR_LOG_KEYWORD: &str = "STR";
 pub const LIST_LOG_PREFIX: &str = "LIST::";
 pub const LIST_LOG_KEYWORD: &str = "LIST";
+pub const HISTORY_LOG_PREFIX: &str = "HISTORY::";
+pub const HISTORY_LOG_KEYWORD: &str = "HISTORY";
 pub const KEYS_PREFIX: &str = "KEYS::";
 pub const LIST_KEYS_PREFIX: &str = "LIST-KEYS::";
 pub const VALIDATION_BIT_KEY: &str = "VALIDATION-BIT";
 pub const SCAN_INTERVAL_CONSTANT: u64 = 3;
 pub const APPEND_ACTION: &str = "APPEND";
 pub const REMOVE_ACTION: &str = "REMOVE";
+pub const SET_ACTION: &str = "SET";
+pub const CHECKPOINT_ACTION: &str = "CHECKPOINT";
 pub const KEEPER_STORE_NAME: &str = "KEEPER-STORE";
 pub const BACK_STATUS_STORE_KEY: &str = "BACK-STATUS";
 pub const MIGRATION_LOG_KEY: &str = "MIGRATION-LOG";
+pub const MIGRATION_PAUSED_KEY: &str = "MIGRATION-PAUSED";
+pub const KEEPER_LEADER_KEY: &str = "KEEPER-LEADER";
 pub const TRANS_LOG_STR_PREFIX: &str = "TRANS-LOG-STR::";
 pub const TRANS_LOG_LIST_PREFIX: &str = "TRANS-LOG-LIST::";
+pub const TXN_PREPARE_PREFIX: &str = "TXN-PREPARE::";
+pub const TXN_DECISION_PREFIX: &str = "TXN-DECISION::";
+pub const TXN_COMMIT_DECISION: &str = "COMMIT";
+pub const TXN_ABORT_DECISION: &str = "ABORT";
+pub const TXN_INDEX_BIN: &str = "TXN-INDEX";
+pub const TXN_OPEN_KEY: &str = "OPEN-TXNS";
+pub const TXN_RECOVERY_INTERVAL: u64 = 10;
+pub const HISTORY_COMPACTION_INTERVAL: u64 = 30;
+pub const ANTI_ENTROPY_INTERVAL: u64 = 5;
+pub const ANTI_ENTROPY_BUCKETS: u32 = 64;
+/// the lock server's "no timestamp": requests carrying it never die under
+/// wait-die, they wait for the lock instead.
+pub const TXN_RECOVERY_TIMESTAMP: u64 = 0;
+pub const TXN_MAX_RETRIES: u32 = 10;
 pub const MIGRATION_INTERVAL: u64 = 7;
+pub const MIGRATION_CHECKPOINT_KEYS: usize = 64;
 pub const BRAODCAST_CLOCK_INTERVAL: u64 = 1;
+pub const KEEPER_HEARTBEAT_INTERVAL: u64 = 1;
+pub const KEEPER_LEASE_INTERVAL: u64 = 3;
+pub const KEEPER_ELECTION_STAGGER_MILLIS: u64 = 500;
+pub const LOCK_LEASE_INTERVAL: u64 = 10;
+pub const LOCK_HEARTBEAT_INTERVAL: u64 = 3;
+pub const LOCK_REAP_INTERVAL: u64 = 1;
+pub const EXPIRED_LEASE_RETENTION: u64 = 60;
+pub const LEASE_EXPIRED_MESSAGE: &str = "lease expired";
+pub const LOCK_CONNECT_RETRIES: u32 = 3;
+pub const LOCK_RETRY_BACKOFF_MILLIS: u64 = 100;
+pub const LOCK_ACQUIRE_TIMEOUT: u64 = 30;
+pub const LOCK_REPLICATION_FACTOR: usize = 2;
+pub const LOCK_RING_VNODES: usize = 64;
+pub const LOCK_PEER_PING_INTERVAL: u64 = 1;
+pub const NOT_PRIMARY_MESSAGE: &str = "not primary";
+pub const NOT_LEADER_MESSAGE: &str = "not leader";
+pub const MIXED_PARTITIONS_MESSAGE: &str = "keys of a request span several partitions";
 
 pub const LOCK_SERVERS_STARTING_PORT_KEY: &str = "LOCK_SERVERS_STARTING_PORT";
 pub const NUM_LOCK_SERVERS_KEY: &str = "NUM_LOCK_SERVERS";
@@ -0,0 +1,115 @@
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64 bit FNV-1a followed by the murmur3 finalizer. FNV alone leaves strings
+/// that only differ in their last bytes (`node#1`, `node#2`, ...) next to each
+/// other on the ring, the finalizer spreads them out. Unlike
+/// [std::collections::hash_map::DefaultHasher], the result is fixed and does
+/// not change between Rust releases.
+pub fn stable_hash(data: &str) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in data.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^= hash >> 33;
+    hash
+}
+
+/// A consistent hash ring over a list of node addresses. Every node is placed
+/// on the ring `vnodes` times, and a key belongs to the node of the first
+/// point at or after the hash of the key.
+///
+/// Points only depend on the node addresses, so adding or removing a node
+/// only moves the keys of the ring ranges it takes or gives back, about
+/// `1 / nodes` of all keys.
+#[derive(Debug, Default, Clone)]
+pub struct HashRing {
+    // (point, index of the node in the address list), sorted by point
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    pub fn new(nodes: &[String], vnodes: usize) -> Self {
+        let mut points = vec![];
+        for (ind, node) in nodes.iter().enumerate() {
+            for vnode in 0..vnodes {
+                points.push((stable_hash(&format!("{}#{}", node, vnode)), ind));
+            }
+        }
+        points.sort();
+        Self { points }
+    }
+
+    /// returns the index of the node owning `key`, or 0 on an empty ring.
+    pub fn locate(&self, key: &str) -> usize {
+        if self.points.is_empty() {
+            return 0;
+        }
+        self.points[self.position(stable_hash(key))].1
+    }
+
+    /// returns the indices of the first `count` distinct nodes marked alive
+    /// in `alive` met walking the ring from `key`. A dead node is skipped
+    /// point by point, so its keys spread over the nodes after each of its
+    /// points instead of all landing on a single successor.
+    pub fn replicas(&self, key: &str, alive: &[bool], count: usize) -> Vec<usize> {
+        if self.points.is_empty() {
+            return vec![];
+        }
+        self.walk(self.position(stable_hash(key)), alive, count)
+    }
+
+    /// returns the arcs between consecutive points as `(start, end)` hash
+    /// ranges, see [in_arc], each with the [Self::replicas] of the keys
+    /// hashing into it.
+    pub fn arcs(&self, alive: &[bool], count: usize) -> Vec<(u64, u64, Vec<usize>)> {
+        let len = self.points.len();
+        let mut arcs = vec![];
+        for pos in 0..len {
+            let start = self.points[(pos + len - 1) % len].0;
+            let end = self.points[pos].0;
+            if start == end && len > 1 {
+                // two points on the same hash, the arc is empty
+                continue;
+            }
+            arcs.push((start, end, self.walk(pos, alive, count)));
+        }
+        arcs
+    }
+
+    // the position of the first point at or after `hash`, wrapping around
+    fn position(&self, hash: u64) -> usize {
+        let pos = self.points.partition_point(|(point, _)| *point < hash);
+        pos % self.points.len()
+    }
+
+    fn walk(&self, pos: usize, alive: &[bool], count: usize) -> Vec<usize> {
+        let mut nodes = vec![];
+        for step in 0..self.points.len() {
+            if nodes.len() == count {
+                break;
+            }
+            let node = self.points[(pos + step) % self.points.len()].1;
+            if alive.get(node) == Some(&true) && !nodes.contains(&node) {
+                nodes.push(node);
+            }
+        }
+        nodes
+    }
+}
+
+/// whether `hash` falls into the ring arc from `start` (exclusive) to `end`
+/// (inclusive). An arc starting and ending at the same point is the whole
+/// ring.
+pub fn in_arc(hash: u64, start: u64, end: u64) -> bool {
+    if start < end {
+        start < hash && hash <= end
+    } else {
+        start < hash || hash <= end
+    }
+}
@@ -0,0 +1,138 @@
+use super::super::keeper;
+use super::super::keeper::keeper_service_client::KeeperServiceClient;
+use super::constants::NOT_LEADER_MESSAGE;
+use super::keeper_helper::MigrationCursor;
+use super::keeper_server::{MigrationLog, MigrationStep};
+use tonic::transport::Channel;
+use tonic::{Code, Status};
+use tribbler::err::{TribResult, TribblerError};
+
+/// what a keeper reports about itself, see [KeeperClient::status].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeeperStatus {
+    pub this: usize,
+    /// the leader this keeper holds a lease for
+    pub leader: Option<usize>,
+    pub term: u64,
+    /// liveness of the backends in the last scan of the keeper
+    pub backs: Vec<bool>,
+    /// the migration in progress
+    pub migration: Option<MigrationLog>,
+    pub paused: bool,
+    /// the clock the keeper broadcast last, and when in unix milliseconds.
+    /// 0 when it never did
+    pub last_clock: u64,
+    pub last_broadcast: u64,
+}
+
+// turns the refusal of a keeper that does not lead into
+// [TribblerError::NotLeader]
+fn leader_error(status: Status) -> Box<dyn std::error::Error + Send + Sync> {
+    if status.code() == Code::FailedPrecondition {
+        if let Some(leader) = status.message().strip_prefix(NOT_LEADER_MESSAGE) {
+            return Box::new(TribblerError::NotLeader(leader.trim().parse().ok()));
+        }
+    }
+    Box::new(status)
+}
+
+/// Status and control client of a single keeper, for operators.
+#[derive(Debug, Default)]
+pub struct KeeperClient {
+    pub addr: String,
+    pub chan: Option<Channel>,
+}
+
+impl KeeperClient {
+    pub fn new(addr: &str, chan: Option<Channel>) -> Self {
+        Self {
+            addr: addr.to_string(),
+            chan,
+        }
+    }
+
+    async fn client(&self) -> TribResult<KeeperServiceClient<Channel>> {
+        match &self.chan {
+            Some(chan) => Ok(KeeperServiceClient::new(chan.clone())),
+            None => Ok(KeeperServiceClient::connect(format!("http://{}", self.addr)).await?),
+        }
+    }
+
+    /// the status of the keeper, which only the leader reports. The others
+    /// fail with [TribblerError::NotLeader].
+    pub async fn status(&self) -> TribResult<KeeperStatus> {
+        let status = self
+            .client()
+            .await?
+            .get_status(keeper::StatusRequest {})
+            .await
+            .map_err(leader_error)?
+            .into_inner();
+        Ok(KeeperStatus {
+            this: status.this as usize,
+            leader: match status.has_leader {
+                true => Some(status.leader as usize),
+                false => None,
+            },
+            term: status.term,
+            backs: status.backs,
+            migration: status.migration.map(|log| MigrationLog {
+                from: log.from,
+                to: log.to,
+                steps: log
+                    .steps
+                    .into_iter()
+                    .map(|step| MigrationStep {
+                        back_ids: step.back_ids.into_iter().map(|i| i as usize).collect(),
+                        leave: step.leave,
+                    })
+                    .collect(),
+                next: log.next as usize,
+                term: log.term,
+                cursors: log
+                    .cursors
+                    .into_iter()
+                    .map(|cursor| MigrationCursor {
+                        back_id: cursor.back_id as usize,
+                        is_list: cursor.is_list,
+                        key: cursor.key,
+                    })
+                    .collect(),
+                done: log.done as usize,
+                total: log.total as usize,
+            }),
+            paused: status.paused,
+            last_clock: status.last_clock,
+            last_broadcast: status.last_broadcast,
+        })
+    }
+
+    /// keeps the keeper from starting migrations until resumed.
+    pub async fn pause_migration(&self) -> TribResult<()> {
+        self.client()
+            .await?
+            .pause_migration(keeper::ControlRequest {})
+            .await?;
+        Ok(())
+    }
+
+    pub async fn resume_migration(&self) -> TribResult<()> {
+        self.client()
+            .await?
+            .resume_migration(keeper::ControlRequest {})
+            .await?;
+        Ok(())
+    }
+
+    /// makes the keeper scan the backends right away and compare them with
+    /// the recorded back status. Only the leader scans, the others fail with
+    /// [TribblerError::NotLeader].
+    pub async fn force_rescan(&self) -> TribResult<()> {
+        self.client()
+            .await?
+            .force_rescan(keeper::ControlRequest {})
+            .await
+            .map_err(leader_error)?;
+        Ok(())
+    }
+}
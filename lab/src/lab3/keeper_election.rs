@@ -0,0 +1,267 @@
+use super::super::keeper;
+use super::super::keeper::keeper_service_client::KeeperServiceClient;
+use super::bin_client::update_channel_cache;
+use super::constants::{
+    KEEPER_ELECTION_STAGGER_MILLIS, KEEPER_HEARTBEAT_INTERVAL, KEEPER_LEASE_INTERVAL,
+};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::time::{self, Duration, Instant};
+use tonic::transport::Channel;
+
+/// the leader of a term, as recorded in the keeper store by the leader
+/// before it acts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LeaderRecord {
+    pub term: u64,
+    pub leader: usize,
+}
+
+/// what a keeper knows about the current term.
+#[derive(Debug, Clone)]
+pub struct ElectionState {
+    pub term: u64,
+    /// the candidate this keeper voted for in `term`
+    pub voted_for: Option<usize>,
+    /// the keeper this keeper holds a lease for, the leader of `term` or
+    /// the candidate it last voted for
+    pub leader: Option<usize>,
+    /// when the lease of `leader` runs out
+    pub lease_until: Instant,
+}
+
+/// Lease-based leader election among the keepers.
+///
+/// A candidate needs the votes of a majority of all keepers and each keeper
+/// votes once per term, so a term has at most one leader. The leader keeps
+/// its lease while a majority acknowledges its heartbeats. A keeper that
+/// acknowledged a heartbeat or granted a vote refuses any other candidate
+/// until that lease runs out, and the lease of the leader runs out first, so
+/// an old leader has stopped acting before a new one is elected.
+///
+/// Only the leader migrates data, so while fewer than a majority of the
+/// configured keepers are up no leader is elected and all migration stops,
+/// even with keepers still running.
+pub struct KeeperElection {
+    pub this: usize,
+    pub keepers: Vec<String>,
+    channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+    state: RwLock<ElectionState>,
+}
+
+fn lease() -> Duration {
+    Duration::from_secs(KEEPER_LEASE_INTERVAL)
+}
+
+fn step_down(state: &mut ElectionState, term: u64) {
+    state.term = term;
+    state.voted_for = None;
+    state.leader = None;
+}
+
+impl KeeperElection {
+    pub fn new(
+        this: usize,
+        keepers: Vec<String>,
+        channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+    ) -> Self {
+        Self {
+            this,
+            keepers,
+            channel_cache,
+            state: RwLock::new(ElectionState {
+                term: 0,
+                voted_for: None,
+                leader: None,
+                lease_until: Instant::now(),
+            }),
+        }
+    }
+
+    pub async fn state(&self) -> ElectionState {
+        self.state.read().await.clone()
+    }
+
+    /// the term this keeper leads, if it holds the lease of a majority.
+    pub async fn leading_term(&self) -> Option<u64> {
+        let state = self.state.read().await;
+        if state.leader == Some(self.this) && Instant::now() < state.lease_until {
+            Some(state.term)
+        } else {
+            None
+        }
+    }
+
+    /// the keeper whose lease this keeper holds, if it did not run out.
+    pub async fn leader(&self) -> Option<usize> {
+        let state = self.state.read().await;
+        if Instant::now() < state.lease_until {
+            state.leader
+        } else {
+            None
+        }
+    }
+
+    pub async fn leads(&self, term: u64) -> bool {
+        self.leading_term().await == Some(term)
+    }
+
+    /// renews the lease of the leader, or runs for leader once the lease
+    /// this keeper holds ran out. Called every KEEPER_HEARTBEAT_INTERVAL.
+    pub async fn tick(&self) {
+        let state = self.state().await;
+        if state.leader == Some(self.this) {
+            self.renew(state.term).await;
+        } else {
+            self.campaign().await;
+        }
+    }
+
+    /// handles the vote request of `candidate` for `term`, returns the term
+    /// of this keeper and whether the vote is granted.
+    pub async fn handle_vote(&self, term: u64, candidate: usize) -> (u64, bool) {
+        let mut state = self.state.write().await;
+        let now = Instant::now();
+        if term < state.term {
+            return (state.term, false);
+        }
+        // a keeper holding the lease of another one does not help replace it
+        if now < state.lease_until && state.leader.is_some() && state.leader != Some(candidate) {
+            return (state.term, false);
+        }
+        if term > state.term {
+            step_down(&mut state, term);
+        }
+        if state.voted_for.is_some() && state.voted_for != Some(candidate) {
+            return (state.term, false);
+        }
+        state.voted_for = Some(candidate);
+        state.leader = Some(candidate);
+        state.lease_until = now + lease();
+        (state.term, true)
+    }
+
+    /// handles the heartbeat of `leader` for `term`, returns the term of
+    /// this keeper and whether the lease is granted.
+    pub async fn handle_append_entries(&self, term: u64, leader: usize) -> (u64, bool) {
+        let mut state = self.state.write().await;
+        if term < state.term {
+            return (state.term, false);
+        }
+        if term > state.term {
+            step_down(&mut state, term);
+        }
+        state.leader = Some(leader);
+        state.lease_until = Instant::now() + lease();
+        (state.term, true)
+    }
+
+    fn peers(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.keepers.len()).filter(move |i| *i != self.this)
+    }
+
+    fn is_majority(&self, count: usize) -> bool {
+        2 * count > self.keepers.len()
+    }
+
+    async fn client(&self, peer: usize) -> Option<KeeperServiceClient<Channel>> {
+        let chan = update_channel_cache(self.channel_cache.clone(), self.keepers[peer].clone())
+            .await
+            .ok()?;
+        Some(KeeperServiceClient::new(chan))
+    }
+
+    async fn request_vote(&self, peer: usize, term: u64) -> Option<(u64, bool)> {
+        let mut client = self.client(peer).await?;
+        let request = keeper::VoteRequest {
+            term,
+            candidate: self.this as u64,
+        };
+        let timeout = Duration::from_secs(KEEPER_HEARTBEAT_INTERVAL);
+        match time::timeout(timeout, client.request_vote(request)).await {
+            Ok(Ok(resp)) => {
+                let resp = resp.into_inner();
+                Some((resp.term, resp.granted))
+            }
+            _ => None,
+        }
+    }
+
+    async fn append_entries(&self, peer: usize, term: u64) -> Option<(u64, bool)> {
+        let mut client = self.client(peer).await?;
+        let request = keeper::AppendEntriesRequest {
+            term,
+            leader: self.this as u64,
+        };
+        let timeout = Duration::from_secs(KEEPER_HEARTBEAT_INTERVAL);
+        match time::timeout(timeout, client.append_entries(request)).await {
+            Ok(Ok(resp)) => {
+                let resp = resp.into_inner();
+                Some((resp.term, resp.success))
+            }
+            _ => None,
+        }
+    }
+
+    // Runs for leader of the next term. Keepers are staggered by index after
+    // a lease runs out so they rarely split the votes.
+    async fn campaign(&self) {
+        let term = {
+            let mut state = self.state.write().await;
+            let stagger = Duration::from_millis(KEEPER_ELECTION_STAGGER_MILLIS * self.this as u64);
+            if Instant::now() < state.lease_until + stagger {
+                return;
+            }
+            let term = state.term + 1;
+            step_down(&mut state, term);
+            state.voted_for = Some(self.this);
+            term
+        };
+        let sent_at = Instant::now();
+        let replies = join_all(self.peers().map(|peer| self.request_vote(peer, term))).await;
+        let mut votes = 1;
+        {
+            let mut state = self.state.write().await;
+            for (reply_term, granted) in replies.into_iter().flatten() {
+                if reply_term > state.term {
+                    step_down(&mut state, reply_term);
+                    return;
+                }
+                if granted {
+                    votes += 1;
+                }
+            }
+            // another candidate may have won a later term meanwhile
+            if state.term != term || state.voted_for != Some(self.this) || !self.is_majority(votes)
+            {
+                return;
+            }
+            state.leader = Some(self.this);
+            state.lease_until = sent_at + lease();
+        }
+        // let the other keepers know right away
+        self.renew(term).await;
+    }
+
+    async fn renew(&self, term: u64) {
+        let sent_at = Instant::now();
+        let replies = join_all(self.peers().map(|peer| self.append_entries(peer, term))).await;
+        let mut acks = 1;
+        let mut state = self.state.write().await;
+        for (reply_term, success) in replies.into_iter().flatten() {
+            if reply_term > state.term {
+                step_down(&mut state, reply_term);
+                return;
+            }
+            if success {
+                acks += 1;
+            }
+        }
+        if state.term == term && state.leader == Some(self.this) && self.is_majority(acks) {
+            state.lease_until = sent_at + lease();
+        }
+    }
+}
@@ -1,13 +1,20 @@
 use crate::lab3::bin_client::update_channel_cache;
 use crate::lab3::client::StorageClient;
 
-use super::constants::{LIST_LOG_KEYWORD, STR_LOG_KEYWORD, VALIDATION_BIT_KEY};
+use super::constants::{
+    HISTORY_LOG_KEYWORD, LIST_LOG_KEYWORD, MIGRATION_CHECKPOINT_KEYS, STR_LOG_KEYWORD,
+    VALIDATION_BIT_KEY,
+};
+use super::hash_ring::HashRing;
 use super::lock_client::{self, LockClient};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::{HashMap, HashSet};
-use std::hash::{Hash, Hasher};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::Instant;
 use tonic::transport::Channel;
 use tribbler::err::TribResult;
 use tribbler::storage::{KeyList, KeyString, KeyValue, Pattern};
@@ -34,7 +41,10 @@ async fn extract_raw_keys_from_addr(
             continue;
         }
         let identifier = splits[1];
-        if identifier == LIST_LOG_KEYWORD || identifier == STR_LOG_KEYWORD {
+        if identifier == LIST_LOG_KEYWORD
+            || identifier == STR_LOG_KEYWORD
+            || identifier == HISTORY_LOG_KEYWORD
+        {
             filtered.push(element.clone());
         }
     }
@@ -78,223 +88,238 @@ fn extract_bin_name_from_raw_key(raw_key: &str) -> String {
     return splits[0].to_string();
 }
 
-// backend servers index
-// interval_start: inclusive; interval_end: inclusive
-fn falls_into_interval(
-    backs: Vec<String>,
-    target_string: String,
-    interval_start: usize,
-    interval_end: usize,
-) -> bool {
-    let mut hasher = DefaultHasher::new();
-    target_string.hash(&mut hasher);
-    let hash_res = hasher.finish();
-    let num_backs = backs.len() as u64;
-    let delta = (hash_res % num_backs) as usize;
-    if interval_start == interval_end {
-        return delta == interval_end;
-    }
-    if interval_start < interval_end {
-        return interval_start <= delta && delta <= interval_end;
-    } else {
-        return interval_start <= delta || delta <= interval_end;
+/// how far the copy off one source backend got. Its keys are copied in
+/// order, the string ones then the list ones, and every key up to and
+/// including this one is done.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MigrationCursor {
+    pub back_id: usize,
+    pub is_list: bool,
+    pub key: String,
+}
+
+impl MigrationCursor {
+    fn passed(&self, is_list: bool, key: &str) -> bool {
+        (is_list, key) <= (self.is_list, self.key.as_str())
     }
 }
 
-async fn migrate_data(
-    backs: Vec<String>,
-    channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
-    from: usize,
-    to: usize,
-    interval_start: usize,
-    interval_end: usize,
-) -> TribResult<()> {
-    // println!(
-    //     "migration: from {}, to {}, interval start {}, interval end {}",
-    //     from, to, interval_start, interval_end
-    // );
-    let addr_from = &backs[from];
-    let addr_to = &backs[to];
-    let chan_from = update_channel_cache(channel_cache.clone(), addr_from.to_string()).await?;
-    let chan_to = update_channel_cache(channel_cache.clone(), addr_to.to_string()).await?;
-    let client_from = StorageClient::new(addr_from, Some(chan_from));
-    let client_to = StorageClient::new(addr_to, Some(chan_to));
-    let raw_key_list = extract_raw_keys_from_addr(addr_from, channel_cache.clone()).await?;
-    for element in raw_key_list.iter() {
-        let bin_name = extract_bin_name_from_raw_key(element);
-        // println!("bin name: {}, element: {}", &bin_name, &element);
-        if bin_name == "" {
-            continue;
-        }
-        let mut hasher = DefaultHasher::new();
-        bin_name.hash(&mut hasher);
-        if !falls_into_interval(backs.clone(), bin_name, interval_start, interval_end) {
-            continue;
-        }
-        let values_from = client_from.list_get(&element).await?.0;
-        let values_to = client_to.list_get(&element).await?.0;
-        let mut hash_str = HashSet::new();
-        for s in values_to {
-            hash_str.insert(s);
+/// records how far a migration got, see [migrate_membership_change].
+#[async_trait]
+pub trait MigrationCheckpoint: Send + Sync {
+    /// the copy off `cursor.back_id` got to `cursor`, and `done` of the
+    /// `total` keys on the sources are.
+    async fn checkpoint(
+        &self,
+        cursor: MigrationCursor,
+        done: usize,
+        total: usize,
+    ) -> TribResult<()>;
+}
+
+/// the backends a migration works on and how hard it may load them.
+#[derive(Clone)]
+pub struct MigrationContext {
+    pub backs: Vec<String>,
+    pub channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+    pub lock_client: Arc<LockClient>,
+    pub replicas: usize,
+    pub vnodes: usize,
+    /// keys copied per second, 0 for no limit
+    pub rate: u64,
+    /// batches of keys copied at once
+    pub concurrency: usize,
+}
+
+/// paces a migration to `rate` keys per second, a token bucket refilled
+/// since the last admission. At most a second's worth of keys goes through
+/// at once, however long the migration waited before.
+pub struct MigrationThrottle {
+    rate: u64,
+    /// the keys due, negative for the ones admitted ahead of the rate, and
+    /// when they were counted last
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl MigrationThrottle {
+    /// a throttle to `rate` keys per second, 0 for no limit.
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            bucket: Mutex::new((rate as f64, Instant::now())),
         }
-        for s in values_from {
-            if hash_str.contains(&s) {
-                continue;
-            }
-            client_to
-                .list_append(&KeyValue {
-                    key: element.to_string(),
-                    value: s.to_string(),
-                })
-                .await?;
-            // println!(
-            //     "{} start appending to {}---key: {}, value: {}",
-            //     addr_from.to_string(),
-            //     addr_to.to_string(),
-            //     element.to_string(),
-            //     s.to_string()
-            // );
+    }
+
+    /// waits until `keys` more keys are due.
+    pub async fn admit(&self, keys: usize) {
+        if self.rate == 0 {
+            return;
         }
+        let wait = {
+            let mut bucket = self.bucket.lock().await;
+            let now = Instant::now();
+            let refilled = bucket.0 + (now - bucket.1).as_secs_f64() * self.rate as f64;
+            bucket.0 = refilled.min(self.rate as f64) - keys as f64;
+            bucket.1 = now;
+            Duration::from_secs_f64((-bucket.0).max(0.0) / self.rate as f64)
+        };
+        tokio::time::sleep(wait).await;
     }
-    client_to
-        .set(&KeyValue {
-            key: VALIDATION_BIT_KEY.to_string(),
-            value: "true".to_string(),
-        })
-        .await?;
-    Ok(())
 }
 
-async fn migrate_set_data(
-    chan_from: Channel,
-    chan_to: Channel,
-    lock_client: Arc<LockClient>,
-    addr_to: String,
-    addr_from: String,
-    key: String,
+// copies `keys` off `from` onto `to` with one read and one write, holding
+// their locks meanwhile
+async fn migrate_batch(
+    ctx: &MigrationContext,
+    from: usize,
+    to: usize,
+    keys: Vec<(bool, String)>,
 ) -> TribResult<()> {
-    let client_from = StorageClient::new(&addr_from, Some(chan_from));
-    let client_to = StorageClient::new(&addr_to, Some(chan_to));
-    let mut read_keys = vec![];
-    read_keys.push(key.to_string());
-    lock_client.acquire_locks(read_keys.clone(), vec![]).await?;
-    let values_from = client_from.get(&key).await?;
-    if let Some(value) = values_from {
-        client_to
-            .set(&KeyValue {
-                key: key.to_string(),
-                value,
-            })
-            .await?;
-    }
-    lock_client.release_locks(read_keys, vec![]).await?;
+    let chan_from =
+        update_channel_cache(ctx.channel_cache.clone(), ctx.backs[from].clone()).await?;
+    let chan_to = update_channel_cache(ctx.channel_cache.clone(), ctx.backs[to].clone()).await?;
+    let client_from = StorageClient::new(&ctx.backs[from], Some(chan_from));
+    let client_to = StorageClient::new(&ctx.backs[to], Some(chan_to));
+    let (lists, strings): (Vec<_>, Vec<_>) = keys.into_iter().partition(|(is_list, _)| *is_list);
+    let strings: Vec<String> = strings.into_iter().map(|(_, key)| key).collect();
+    let lists: Vec<String> = lists.into_iter().map(|(_, key)| key).collect();
+    let mut read_keys: Vec<String> = strings.iter().chain(lists.iter()).cloned().collect();
+    read_keys.sort();
+    read_keys.dedup();
+    ctx.lock_client
+        .acquire_locks(read_keys.clone(), vec![])
+        .await?;
+    let res = match client_from.get_batch(&strings, &lists).await {
+        Ok(batch) => client_to.set_batch(batch).await,
+        Err(err) => Err(err),
+    };
+    ctx.lock_client.release_locks(read_keys, vec![]).await?;
+    res?;
     Ok(())
 }
 
-async fn migrate_list_data(
-    chan_from: Channel,
-    chan_to: Channel,
-    lock_client: Arc<LockClient>,
-    addr_to: String,
-    addr_from: String,
-    key: String,
+// copies one chunk of the keys of `from`, batch by batch
+async fn migrate_chunk(
+    ctx: &MigrationContext,
+    throttle: &MigrationThrottle,
+    from: usize,
+    batches: Vec<(usize, Vec<(bool, String)>)>,
 ) -> TribResult<()> {
-    let client_from = StorageClient::new(&addr_from, Some(chan_from));
-    let client_to = StorageClient::new(&addr_to, Some(chan_to));
-    let mut read_keys = vec![];
-    read_keys.push(key.to_string());
-    lock_client.acquire_locks(read_keys.clone(), vec![]).await?;
-    let values_from = client_from.list_get(&key).await?.0;
-    let values_to = client_to.list_get(&key).await?.0;
-    let mut values_hash = HashSet::new();
-    for v in values_to {
-        values_hash.insert(v);
-    }
-    for v in values_hash {
-        client_to
-            .list_remove(&KeyValue {
-                key: key.to_string(),
-                value: v,
-            })
-            .await?;
-    }
-    for v in values_from {
-        client_to
-            .list_append(&KeyValue {
-                key: key.to_string(),
-                value: v,
-            })
-            .await?;
+    for (to, keys) in batches {
+        throttle.admit(keys.len()).await;
+        migrate_batch(ctx, from, to, keys).await?;
     }
-    lock_client.release_locks(read_keys, vec![]).await?;
     Ok(())
 }
 
-async fn migrate_data_with_lock(
-    backs: Vec<String>,
-    channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
-    from: usize,
-    to: usize,
-    interval_start: usize,
-    interval_end: usize,
-    lock_client: Arc<LockClient>,
-) -> TribResult<()> {
-    // println!(
-    //     "migration: from {}, to {}, interval start {}, interval end {}",
-    //     from, to, interval_start, interval_end
-    // );
-    let addr_from = &backs[from];
-    let addr_to = &backs[to];
-    let chan_from = update_channel_cache(channel_cache.clone(), addr_from.to_string()).await?;
-    let chan_to = update_channel_cache(channel_cache.clone(), addr_to.to_string()).await?;
-    let client_to = StorageClient::new(addr_to, Some(chan_to.clone()));
-    let raw_key_list = extract_raw_keys_from_addr(addr_from, channel_cache.clone()).await?;
-    let raw_string_list = extract_string_keys_from_addr(addr_from, channel_cache.clone()).await?;
-    let mut joinHandlers = vec![];
-    for element in raw_key_list.iter() {
-        let bin_name = extract_bin_name_from_raw_key(element);
-        // println!("bin name: {}, element: {}", &bin_name, &element);
-        if bin_name == "" {
+// Copies every bin whose replicas change from `old_status` to `new_status`
+// onto the backends that newly keep it, reading it from the first of its old
+// replicas still alive. The keys of a source are copied in chunks of
+// MIGRATION_CHECKPOINT_KEYS, each sent to a backend in one batch. Keys behind
+// the cursor of their source are skipped, and the cursor moves on once a
+// chunk and all the ones before it are copied. Returns the backends copied
+// to.
+async fn migrate_moved_bins(
+    ctx: &MigrationContext,
+    old_status: &[bool],
+    new_status: &[bool],
+    cursors: &[MigrationCursor],
+    checkpoint: Option<&dyn MigrationCheckpoint>,
+) -> TribResult<Vec<usize>> {
+    let ring = HashRing::new(&ctx.backs, ctx.vnodes);
+    let mut sources = vec![];
+    for (from, addr_from) in ctx.backs.iter().enumerate() {
+        if !new_status[from] {
             continue;
         }
-        let mut hasher = DefaultHasher::new();
-        bin_name.hash(&mut hasher);
-        if !falls_into_interval(backs.clone(), bin_name, interval_start, interval_end) {
-            continue;
-        }
-        joinHandlers.push(tokio::spawn(migrate_list_data(
-            chan_from.clone(),
-            chan_to.clone(),
-            lock_client.clone(),
-            addr_to.to_string(),
-            addr_from.to_string(),
-            element.to_string(),
-        )));
+        let raw_key_list = extract_raw_keys_from_addr(addr_from, ctx.channel_cache.clone()).await?;
+        let raw_string_list =
+            extract_string_keys_from_addr(addr_from, ctx.channel_cache.clone()).await?;
+        let mut elements: Vec<(bool, String)> = raw_string_list
+            .into_iter()
+            .map(|element| (false, element))
+            .chain(raw_key_list.into_iter().map(|element| (true, element)))
+            .collect();
+        elements.sort();
+        sources.push((from, elements));
     }
-    for element in raw_string_list.iter() {
-        let bin_name = extract_bin_name_from_raw_key(element);
-        // println!("bin name: {}, element: {}", &bin_name, &element);
-        if bin_name == "" {
-            continue;
+    let total = sources.iter().map(|(_, elements)| elements.len()).sum();
+    let mut done = 0;
+
+    let throttle = MigrationThrottle::new(ctx.rate);
+    let mut targets = vec![];
+    for (from, elements) in sources {
+        let cursor = cursors.iter().find(|cursor| cursor.back_id == from);
+        let (passed, elements): (Vec<_>, Vec<_>) =
+            elements
+                .into_iter()
+                .partition(|(is_list, element)| match cursor {
+                    Some(cursor) => cursor.passed(*is_list, element),
+                    None => false,
+                });
+        done += passed.len();
+        // the keys of each chunk that move, batched by the backend they move
+        // to
+        let mut chunks = vec![];
+        for chunk in elements.chunks(MIGRATION_CHECKPOINT_KEYS) {
+            let mut batches: Vec<(usize, Vec<(bool, String)>)> = vec![];
+            for (is_list, element) in chunk {
+                let bin_name = extract_bin_name_from_raw_key(element);
+                if bin_name.is_empty() {
+                    continue;
+                }
+                let old_replicas = ring.replicas(&bin_name, old_status, ctx.replicas);
+                if old_replicas.iter().find(|index| new_status[**index]) != Some(&from) {
+                    continue;
+                }
+                for to in ring.replicas(&bin_name, new_status, ctx.replicas) {
+                    if old_replicas.contains(&to) {
+                        continue;
+                    }
+                    if !targets.contains(&to) {
+                        targets.push(to);
+                    }
+                    match batches.iter_mut().find(|(target, _)| *target == to) {
+                        Some((_, keys)) => keys.push((*is_list, element.to_string())),
+                        None => batches.push((to, vec![(*is_list, element.to_string())])),
+                    }
+                }
+            }
+            let last = chunk.last().map(|(is_list, element)| MigrationCursor {
+                back_id: from,
+                is_list: *is_list,
+                key: element.to_string(),
+            });
+            chunks.push((chunk.len(), last, batches));
         }
-        let mut hasher = DefaultHasher::new();
-        bin_name.hash(&mut hasher);
-        if !falls_into_interval(backs.clone(), bin_name, interval_start, interval_end) {
-            continue;
+        // up to `concurrency` chunks are copied at once, and their results
+        // come back in order
+        let throttle = &throttle;
+        let mut copies = stream::iter(chunks.into_iter().map(|(len, last, batches)| async move {
+            migrate_chunk(ctx, throttle, from, batches)
+                .await
+                .map(|_| (len, last))
+        }))
+        .buffered(ctx.concurrency.max(1));
+        while let Some(res) = copies.next().await {
+            let (len, last) = res?;
+            done += len;
+            if let (Some(checkpoint), Some(cursor)) = (checkpoint, last) {
+                checkpoint.checkpoint(cursor, done, total).await?;
+            }
         }
-        joinHandlers.push(tokio::spawn(migrate_set_data(
-            chan_from.clone(),
-            chan_to.clone(),
-            lock_client.clone(),
-            addr_to.to_string(),
-            addr_from.to_string(),
-            element.to_string(),
-        )));
     }
-    for ret in joinHandlers {
-        ret.await?;
-    }
-    client_to
+    Ok(targets)
+}
+
+// lets clients read from `to`, now that it holds its bins
+async fn mark_valid(
+    backs: &[String],
+    channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+    to: usize,
+) -> TribResult<()> {
+    let chan_to = update_channel_cache(channel_cache, backs[to].to_string()).await?;
+    StorageClient::new(&backs[to], Some(chan_to))
         .set(&KeyValue {
             key: VALIDATION_BIT_KEY.to_string(),
             value: "true".to_string(),
@@ -303,156 +328,22 @@ async fn migrate_data_with_lock(
     Ok(())
 }
 
-pub async fn migrate_to_joined_node(
-    backs: Vec<String>,
-    channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
-    joined_node_index: usize,
-    back_status: Vec<bool>,
-    lock_client: Arc<LockClient>,
-) -> TribResult<()> {
-    let backs_len = backs.len();
-    let mut index = joined_node_index + backs_len - 1;
-    let mut interval_start: usize = 0;
-    let interval_end: usize = joined_node_index as usize;
-    while index >= joined_node_index {
-        if back_status[index % backs_len] {
-            if index == joined_node_index {
-                // if the predecessor is itself, which means it is the only node.
-                return Ok(());
-            }
-            index = index - 1;
-            // find the second predecessor
-            while index >= joined_node_index {
-                if back_status[index % backs_len] {
-                    interval_start = index % backs_len as usize;
-                    break;
-                }
-                index = index - 1;
-            }
-            break;
-        }
-        index = index - 1;
-    }
-    // exclude start itself
-    interval_start = (interval_start + 1) % backs_len;
-    index = joined_node_index + 1;
-    // find the successor, and fetch all the data from successor
-    while index <= joined_node_index + backs_len {
-        if back_status[index % backs_len] {
-            migrate_data_with_lock(
-                backs.clone(),
-                channel_cache.clone(),
-                index % backs_len,
-                joined_node_index,
-                interval_start,
-                interval_end,
-                lock_client.clone(),
-            )
-            .await?;
-        }
-        index = index + 1;
-    }
-    Ok(())
-}
-
-pub async fn migrate_to_left_node(
-    backs: Vec<String>,
-    channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
-    left_node_index: usize,
-    back_status: Vec<bool>,
-    lock_client: Arc<LockClient>,
+/// migrates the bins whose replicas change from `old_status` to
+/// `new_status`, for any number of backends leaving or joining at once, then
+/// lets clients read from the joined backends and the ones copied to. The
+/// copy picks up from `cursors` and reports its own to `checkpoint`, if any.
+pub async fn migrate_membership_change(
+    ctx: &MigrationContext,
+    old_status: Vec<bool>,
+    new_status: Vec<bool>,
+    cursors: &[MigrationCursor],
+    checkpoint: Option<&dyn MigrationCheckpoint>,
 ) -> TribResult<()> {
-    let backs_len = backs.len();
-    let mut interval_start: usize = 0;
-    let mut interval_end: usize = left_node_index as usize;
-    let mut successor: usize = 0;
-    let mut second_successor: usize = 0;
-    let mut index = left_node_index + 1;
-    let mut first_predecessor: usize = 0;
-    let mut second_predecessor: usize = 0;
-    //println!("Get back statsu: {:?}", back_status);
-    // find the first and second successor
-    while index <= left_node_index + backs_len {
-        // if cannot find succesor, which means it is the last node in system, crash
-        if index % backs_len == left_node_index {
-            return Ok(());
-        }
-        if back_status[index % backs_len] {
-            successor = index % backs_len;
-            //println!("Find first successor {}", successor);
-            index = index + 1;
-            while index <= left_node_index + backs_len {
-                // if cannot find second successor, which means there is only one successor left
-                if index % backs_len == left_node_index {
-                    return Ok(());
-                }
-                if back_status[index % backs_len] {
-                    second_successor = index % backs_len;
-                    //println!("Find first successor {}", second_successor);
-                    break;
-                }
-                index = index + 1;
-            }
-            break;
-        }
-        index = index + 1;
-    }
-    // try to find the first and second predecessor of successor
-    index = successor + backs_len - 1;
-    while index >= successor {
-        if index == successor {
-            // if the predecessor is itself, which means it is the only node.
-            return Ok(());
-        }
-        if back_status[index % backs_len] {
-            first_predecessor = index % backs_len;
-            //println!("Find first predecessor {}", first_predecessor);
-            index = index - 1;
-            // find the second predecessor
-            while index >= successor {
-                if back_status[index % backs_len] {
-                    second_predecessor = index % backs_len;
-                    //println!("Find second predecessor {}", second_predecessor);
-                    break;
-                }
-                index = index - 1;
-            }
-            break;
+    let targets = migrate_moved_bins(ctx, &old_status, &new_status, cursors, checkpoint).await?;
+    for to in 0..ctx.backs.len() {
+        if targets.contains(&to) || (new_status[to] && !old_status[to]) {
+            mark_valid(&ctx.backs, ctx.channel_cache.clone(), to).await?;
         }
-        index = index - 1;
     }
-    // exclude start itself
-    interval_start = (second_predecessor + 1) % backs_len;
-    interval_end = first_predecessor;
-    /*println!(
-        "Migrate first from node {} to node {}, the data range from index {} to {}",
-        first_predecessor, successor, interval_start, interval_end
-    );*/
-    let migrate_first = tokio::spawn(migrate_data_with_lock(
-        backs.clone(),
-        channel_cache.clone(),
-        first_predecessor,
-        successor,
-        interval_start,
-        interval_end,
-        lock_client.clone(),
-    ));
-    interval_start = (first_predecessor + 1) % backs_len;
-    interval_end = left_node_index;
-    /*println!(
-        "Migrate second from node {} to node {}, the data range from index {} to {}",
-        successor, second_successor, interval_start, interval_end
-    );*/
-    let migrate_second = tokio::spawn(migrate_data_with_lock(
-        backs,
-        channel_cache,
-        successor,
-        second_successor,
-        interval_start,
-        interval_end,
-        lock_client,
-    ));
-    migrate_first.await?;
-    migrate_second.await?;
     Ok(())
 }
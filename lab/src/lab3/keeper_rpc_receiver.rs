@@ -1,9 +1,23 @@
 use super::super::keeper;
+use super::bin_client::BinStorageClient;
+use super::constants::{KEEPER_STORE_NAME, MIGRATION_PAUSED_KEY, NOT_LEADER_MESSAGE};
+use super::keeper_election::KeeperElection;
+use super::keeper_server::KeeperState;
 use crate::keeper::keeper_service_server::KeeperService;
 use async_trait::async_trait;
-use tonic::Response;
+use std::sync::Arc;
+use tokio::sync::{Notify, RwLock};
+use tonic::{Response, Status};
+use tribbler::storage::{BinStorage, KeyValue};
 
-pub struct KeeperRPCReceiver {}
+pub struct KeeperRPCReceiver {
+    pub election: Arc<KeeperElection>,
+    pub state: Arc<RwLock<KeeperState>>,
+    pub rescan: Arc<Notify>,
+    /// reaches the keeper store, where the paused flag is kept for whichever
+    /// keeper leads
+    store: BinStorageClient,
+}
 
 #[async_trait] // VERY IMPORTANT !!!=
 impl KeeperService for KeeperRPCReceiver {
@@ -14,10 +28,152 @@ impl KeeperService for KeeperRPCReceiver {
         let msg_body = keeper::HeartbeatResponse { value: true };
         Ok(Response::new(msg_body))
     }
+
+    async fn request_vote(
+        &self,
+        request: tonic::Request<keeper::VoteRequest>,
+    ) -> Result<tonic::Response<keeper::VoteResponse>, tonic::Status> {
+        let request = request.into_inner();
+        let (term, granted) = self
+            .election
+            .handle_vote(request.term, request.candidate as usize)
+            .await;
+        Ok(Response::new(keeper::VoteResponse { term, granted }))
+    }
+
+    async fn append_entries(
+        &self,
+        request: tonic::Request<keeper::AppendEntriesRequest>,
+    ) -> Result<tonic::Response<keeper::AppendEntriesResponse>, tonic::Status> {
+        let request = request.into_inner();
+        let (term, success) = self
+            .election
+            .handle_append_entries(request.term, request.leader as usize)
+            .await;
+        Ok(Response::new(keeper::AppendEntriesResponse {
+            term,
+            success,
+        }))
+    }
+
+    async fn get_status(
+        &self,
+        _request: tonic::Request<keeper::StatusRequest>,
+    ) -> Result<tonic::Response<keeper::KeeperStatus>, tonic::Status> {
+        self.check_leads().await?;
+        let leader = self.election.leader().await;
+        let term = self.election.state().await.term;
+        let state = self.state.read().await.clone();
+        Ok(Response::new(keeper::KeeperStatus {
+            this: self.election.this as u64,
+            has_leader: leader.is_some(),
+            leader: leader.unwrap_or(0) as u64,
+            term,
+            backs: state.backs_status,
+            migration: state.migration.map(|log| keeper::MigrationLog {
+                from: log.from,
+                to: log.to,
+                steps: log
+                    .steps
+                    .into_iter()
+                    .map(|step| keeper::MigrationStep {
+                        back_ids: step.back_ids.into_iter().map(|i| i as u64).collect(),
+                        leave: step.leave,
+                    })
+                    .collect(),
+                next: log.next as u64,
+                term: log.term,
+                cursors: log
+                    .cursors
+                    .into_iter()
+                    .map(|cursor| keeper::MigrationCursor {
+                        back_id: cursor.back_id as u64,
+                        is_list: cursor.is_list,
+                        key: cursor.key,
+                    })
+                    .collect(),
+                done: log.done as u64,
+                total: log.total as u64,
+            }),
+            paused: state.paused,
+            last_clock: state.last_clock,
+            last_broadcast: state.last_broadcast,
+        }))
+    }
+
+    async fn pause_migration(
+        &self,
+        _request: tonic::Request<keeper::ControlRequest>,
+    ) -> Result<tonic::Response<keeper::ControlResponse>, tonic::Status> {
+        self.set_paused(true).await?;
+        Ok(Response::new(keeper::ControlResponse { value: true }))
+    }
+
+    async fn resume_migration(
+        &self,
+        _request: tonic::Request<keeper::ControlRequest>,
+    ) -> Result<tonic::Response<keeper::ControlResponse>, tonic::Status> {
+        self.set_paused(false).await?;
+        Ok(Response::new(keeper::ControlResponse { value: true }))
+    }
+
+    async fn force_rescan(
+        &self,
+        _request: tonic::Request<keeper::ControlRequest>,
+    ) -> Result<tonic::Response<keeper::ControlResponse>, tonic::Status> {
+        self.check_leads().await?;
+        self.rescan.notify_one();
+        Ok(Response::new(keeper::ControlResponse { value: true }))
+    }
 }
 
 impl KeeperRPCReceiver {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(
+        election: Arc<KeeperElection>,
+        state: Arc<RwLock<KeeperState>>,
+        rescan: Arc<Notify>,
+        store: BinStorageClient,
+    ) -> Self {
+        Self {
+            election,
+            state,
+            rescan,
+            store,
+        }
+    }
+
+    // only the leader scans the backends, broadcasts clocks and migrates, so
+    // the others refuse to report or rescan, naming the leader they know
+    async fn check_leads(&self) -> Result<(), Status> {
+        if self.election.leading_term().await.is_some() {
+            return Ok(());
+        }
+        let leader = match self.election.leader().await {
+            Some(leader) => leader.to_string(),
+            None => "none".to_string(),
+        };
+        Err(Status::failed_precondition(format!(
+            "{} {}",
+            NOT_LEADER_MESSAGE, leader
+        )))
+    }
+
+    // records the paused flag in the keeper store, setting it to "" deletes
+    // it
+    async fn set_paused(&self, paused: bool) -> Result<(), Status> {
+        let value = if paused { "true" } else { "" };
+        let res = match self.store.bin(KEEPER_STORE_NAME).await {
+            Ok(keeper_store) => {
+                keeper_store
+                    .set(&KeyValue::new(MIGRATION_PAUSED_KEY, value))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if res.is_err() {
+            return Err(Status::unavailable("keeper store error"));
+        }
+        self.state.write().await.paused = paused;
+        Ok(())
     }
 }
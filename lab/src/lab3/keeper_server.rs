@@ -1,40 +1,152 @@
-use super::super::keeper;
-use super::super::keeper::keeper_service_client::KeeperServiceClient;
-use super::bin_client::init_lock_servers_addresses;
 use super::bin_client::update_channel_cache;
-use super::bin_client::BinStorageClient;
+use super::bin_client::{lock_addrs_or_default, BinStorageClient};
 use super::client::StorageClient;
 use super::constants::{
-    BACK_STATUS_STORE_KEY, KEEPER_STORE_NAME, MIGRATION_LOG_KEY, SCAN_INTERVAL_CONSTANT,
+    BACK_STATUS_STORE_KEY, KEEPER_LEADER_KEY, KEEPER_STORE_NAME, MIGRATION_LOG_KEY,
+    MIGRATION_PAUSED_KEY, SCAN_INTERVAL_CONSTANT,
 };
-use super::keeper_helper;
+use super::keeper_election::{KeeperElection, LeaderRecord};
+use super::keeper_helper::{self, MigrationCheckpoint, MigrationContext, MigrationCursor};
 use super::lock_client::LockClient;
 use serde::Deserialize;
 use serde::Serialize;
 use std::cmp;
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, Notify, RwLock};
 use tonic::transport::Channel;
-use tribbler::err::TribResult;
+use tribbler::config::{KeeperConfig, DEFAULT_MIGRATION_CONCURRENCY};
+use tribbler::err::{TribResult, TribblerError};
 use tribbler::storage::KeyValue;
 use tribbler::storage::{KeyString, Storage};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct MigrationLog {
-    pub back_id: usize,
+/// one step of a migration plan: the backends that left, all at once as
+/// none of them is left to read from, or a single backend that joined.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStep {
+    pub back_ids: Vec<usize>,
     pub leave: bool,
 }
+
+/// the migrations planned for the membership changes of one round, as
+/// recorded in the keeper store while they are carried out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MigrationLog {
+    /// the back status the plan starts from, and the one it ends in
+    pub from: Vec<bool>,
+    pub to: Vec<bool>,
+    /// carried out one at a time, in order
+    pub steps: Vec<MigrationStep>,
+    /// the step in progress, the ones before are done
+    pub next: usize,
+    /// the term of the leader that started the migration
+    pub term: u64,
+    /// how far the copy of the step in progress got off each source, and
+    /// how many of the keys on the sources it went through
+    #[serde(default)]
+    pub cursors: Vec<MigrationCursor>,
+    #[serde(default)]
+    pub done: usize,
+    #[serde(default)]
+    pub total: usize,
+}
+
+impl MigrationLog {
+    /// plans the migrations from `from` to `to`. The backends that left go
+    /// first, so the bins they kept are back to full replication before any
+    /// replica moves to a joined backend, then the backends that joined one
+    /// by one.
+    pub fn new(from: Vec<bool>, to: Vec<bool>, term: u64) -> Self {
+        let left: Vec<usize> = (0..to.len()).filter(|&i| from[i] && !to[i]).collect();
+        let mut steps = vec![];
+        if !left.is_empty() {
+            steps.push(MigrationStep {
+                back_ids: left,
+                leave: true,
+            });
+        }
+        for i in 0..to.len() {
+            if !from[i] && to[i] {
+                steps.push(MigrationStep {
+                    back_ids: vec![i],
+                    leave: false,
+                });
+            }
+        }
+        Self {
+            from,
+            to,
+            steps,
+            next: 0,
+            term,
+            cursors: vec![],
+            done: 0,
+            total: 0,
+        }
+    }
+
+    /// how much of the plan is done, in percent.
+    pub fn percent(&self) -> u32 {
+        if self.steps.is_empty() {
+            return 100;
+        }
+        let step = match self.total {
+            0 => 0.0,
+            total => self.done as f64 / total as f64,
+        };
+        ((self.next as f64 + step) * 100.0 / self.steps.len() as f64) as u32
+    }
+
+    /// the back status once the first `done` steps are carried out.
+    pub fn status_after(&self, done: usize) -> Vec<bool> {
+        let mut status = self.from.clone();
+        for step in &self.steps[..done] {
+            for &i in &step.back_ids {
+                status[i] = !step.leave;
+            }
+        }
+        status
+    }
+}
+
+/// what a keeper shares with its RPC service, for operators to inspect and
+/// steer migrations.
+#[derive(Debug, Clone, Default)]
+pub struct KeeperState {
+    /// liveness of the backends in the last scan
+    pub backs_status: Vec<bool>,
+    /// the migration in progress
+    pub migration: Option<MigrationLog>,
+    /// no migration starts or goes on while set, the membership changes
+    /// seen meanwhile are compared with the recorded back status once
+    /// resumed. Kept in the keeper store, so every keeper sees it
+    pub paused: bool,
+    /// the clock broadcast last, and when in unix milliseconds
+    pub last_clock: u64,
+    pub last_broadcast: u64,
+}
+
 pub struct KeeperMigrator {
     pub backs: Vec<String>,
     pub keepers: Vec<String>,
     pub this: usize,
     pub my_addr: String,
-    pub activated: bool,
     backs_status_mut: RwLock<Vec<bool>>,
     pub channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
     pub lock_client: Arc<LockClient>,
+    pub replicas: usize,
+    pub vnodes: usize,
+    /// keys copied per second in a migration, 0 for no limit
+    pub migration_rate: u64,
+    /// batches of keys copied at once in a migration
+    pub migration_concurrency: usize,
+    pub election: Arc<KeeperElection>,
+    /// the last term this keeper led
+    pub term: u64,
+    pub state: Arc<RwLock<KeeperState>>,
+    /// wakes the migration loop up for a forced rescan
+    pub rescan: Arc<Notify>,
 }
 
 impl KeeperMigrator {
@@ -43,39 +155,96 @@ impl KeeperMigrator {
         keepers: Vec<String>,
         backs: &Vec<String>,
         backs_status: Vec<bool>,
+        lock_addrs: Vec<String>,
+        replicas: usize,
+        vnodes: usize,
     ) -> Self {
-        let lock_addrs = init_lock_servers_addresses();
+        let channel_cache = Arc::new(RwLock::new(HashMap::new()));
         Self {
             this,
             keepers: keepers.clone(),
             my_addr: keepers[this].clone(),
             backs: backs.clone(),
-            activated: false,
             backs_status_mut: RwLock::new(backs_status.clone()),
-            channel_cache: Arc::new(RwLock::new(HashMap::new())),
+            channel_cache: channel_cache.clone(),
             lock_client: Arc::new(LockClient::new(lock_addrs, true)),
+            replicas,
+            vnodes,
+            migration_rate: 0,
+            migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
+            election: Arc::new(KeeperElection::new(this, keepers, channel_cache)),
+            term: 0,
+            state: Arc::new(RwLock::new(KeeperState::default())),
+            rescan: Arc::new(Notify::new()),
         }
     }
 
+    /// the migrator of the keeper `kc` configures, placing bins and pacing
+    /// migrations as `kc` says, over the channels of `channel_cache` and
+    /// leading when `election` elects it.
     pub fn new_with_channel(
-        this: usize,
-        keepers: Vec<String>,
-        backs: &Vec<String>,
+        kc: &KeeperConfig,
         backs_status: Vec<bool>,
         channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+        election: Arc<KeeperElection>,
     ) -> Self {
-        let lock_addrs = init_lock_servers_addresses();
         Self {
-            this,
-            keepers: keepers.clone(),
-            my_addr: keepers[this].clone(),
-            backs: backs.clone(),
-            activated: false,
-            backs_status_mut: RwLock::new(backs_status.clone()),
+            this: kc.this,
+            keepers: kc.addrs.clone(),
+            my_addr: kc.addr().to_string(),
+            backs: kc.backs.clone(),
+            backs_status_mut: RwLock::new(backs_status),
             channel_cache,
-            lock_client: Arc::new(LockClient::new(lock_addrs, true)),
+            lock_client: Arc::new(LockClient::new(
+                lock_addrs_or_default(kc.locks.clone()),
+                true,
+            )),
+            replicas: kc.replicas,
+            vnodes: kc.vnodes,
+            migration_rate: kc.migration_rate,
+            migration_concurrency: kc.migration_concurrency,
+            election,
+            term: 0,
+            state: Arc::new(RwLock::new(KeeperState::default())),
+            rescan: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn migration_context(&self) -> MigrationContext {
+        MigrationContext {
+            backs: self.backs.clone(),
+            channel_cache: self.channel_cache.clone(),
+            lock_client: self.lock_client.clone(),
+            replicas: self.replicas,
+            vnodes: self.vnodes,
+            rate: self.migration_rate,
+            concurrency: self.migration_concurrency,
         }
     }
+
+    /// records this keeper as the leader of `term` in the keeper store.
+    /// Returns false if a leader of a later term is recorded already.
+    async fn claim_term(&self, bin_client: &dyn Storage, term: u64) -> TribResult<bool> {
+        if let Some(record) = bin_client.get(KEEPER_LEADER_KEY).await? {
+            let record: LeaderRecord = serde_json::from_str(&record)?;
+            if record.term > term {
+                return Ok(false);
+            }
+            if record.term == term {
+                return Ok(true);
+            }
+        }
+        bin_client
+            .set(&KeyValue {
+                key: KEEPER_LEADER_KEY.to_string(),
+                value: serde_json::to_string(&LeaderRecord {
+                    term,
+                    leader: self.this,
+                })?,
+            })
+            .await?;
+        Ok(true)
+    }
 }
 
 pub struct KeeperClockBroadcastor {
@@ -83,15 +252,20 @@ pub struct KeeperClockBroadcastor {
     pub keepers: Vec<String>,
     pub this: usize,
     channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+    pub election: Arc<KeeperElection>,
+    pub state: Arc<RwLock<KeeperState>>,
 }
 
 impl KeeperClockBroadcastor {
     pub fn new(this: usize, keepers: Vec<String>, backs: &Vec<String>) -> Self {
+        let channel_cache = Arc::new(RwLock::new(HashMap::new()));
         Self {
             this,
             keepers: keepers.clone(),
             backs: backs.clone(),
-            channel_cache: Arc::new(RwLock::new(HashMap::new())),
+            channel_cache: channel_cache.clone(),
+            election: Arc::new(KeeperElection::new(this, keepers, channel_cache)),
+            state: Arc::new(RwLock::new(KeeperState::default())),
         }
     }
 
@@ -100,12 +274,16 @@ impl KeeperClockBroadcastor {
         keepers: Vec<String>,
         backs: &Vec<String>,
         channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+        election: Arc<KeeperElection>,
+        state: Arc<RwLock<KeeperState>>,
     ) -> Self {
         Self {
             this,
             keepers: keepers.clone(),
             backs: backs.clone(),
             channel_cache,
+            election,
+            state,
         }
     }
 }
@@ -119,214 +297,249 @@ pub trait KeeperMigratorTrait {
 #[async_trait]
 impl KeeperMigratorTrait for KeeperMigrator {
     async fn check_migration<'life0>(self: &'life0 mut KeeperMigrator) -> TribResult<()> {
-        // my own jurisdiction starts from self.this
-        // gonna cover the gap if my next keepers are down
-        // scan for next available keeper
-        let mut smallest_keeper_alive = self.this;
-        //println!("{}, begin broadcasting", self.this);
-        for i in 0..self.keepers.len() {
-            if i == self.this {
-                continue;
-            }
-            let chan_res =
-                update_channel_cache(self.channel_cache.clone(), self.keepers[i].clone()).await;
-            if chan_res.is_err() {
-                // println!("ping channel failed: from {}, to {}", self.this, i);
-                continue;
-            }
-            let mut client = KeeperServiceClient::new(chan_res.unwrap().clone());
-            let resp_res = client.ping(keeper::Heartbeat { value: true }).await;
-            if resp_res.is_err() {
-                // println!("ping failed: from {}, to {}", self.this, i);
-                continue;
-            }
-            // println!("ping success: from {}, to {}", self.this, i);
-            smallest_keeper_alive = cmp::min(smallest_keeper_alive, i);
-        }
-        if smallest_keeper_alive != self.this {
-            // only smallest keeper alive is in charge of migration
-            self.activated = false;
-            return Ok(());
-        }
+        // only the leader of the current term is in charge of migration
+        let term = match self.election.leading_term().await {
+            Some(term) => term,
+            None => return Ok(()),
+        };
+        self.term = term;
         // scan 300
-        let mut node_join_migration_index = None;
-        let mut node_leave_migration_index = None;
         let mut back_status = self.backs_status_mut.write().await;
         for i in 0..self.backs.len() {
             let chan_res =
                 update_channel_cache(self.channel_cache.clone(), self.backs[i].clone()).await;
             if chan_res.is_err() {
-                if (*back_status)[i] == true {
-                    // node leave from jurisdiction
-                    node_leave_migration_index = Some(i);
-                }
                 (*back_status)[i] = false;
                 continue;
             }
             let client = StorageClient::new(&self.backs[i], Some(chan_res.unwrap().clone()));
-            let clock_res = client.get("DUMMY").await;
-            if clock_res.is_err() {
-                // server is now down
-                if (*back_status)[i] == true {
-                    // node leave from jurisdiction
-                    node_leave_migration_index = Some(i);
-                }
-                (*back_status)[i] = false;
-            } else {
-                // server is now up
-                if (*back_status)[i] == false {
-                    // node join in jurisdictioin
-                    node_join_migration_index = Some(i);
-                }
-                (*back_status)[i] = true;
-            }
+            (*back_status)[i] = client.get("DUMMY").await.is_ok();
         }
-        /*if smallest_keeper_alive != self.this {
-            // only smallest keeper alive is in charge of migration
-            return Ok(());
-        }*/
         let back_status_copy = back_status.clone();
-        let mut bin_store =
-            BinStorageClient::new_with_channel(&self.backs, self.channel_cache.clone());
-        bin_store.update_lock_client(self.lock_client.clone());
+        drop(back_status);
+        self.state.write().await.backs_status = back_status_copy.clone();
+        let mut bin_store = BinStorageClient::new_with_channel(
+            &self.backs,
+            self.channel_cache.clone(),
+            self.lock_client.clone(),
+        );
+        bin_store.update_replicas(self.replicas);
+        bin_store.update_vnodes(self.vnodes);
         let bin_client = bin_store.bin_with_backs(KEEPER_STORE_NAME, &back_status_copy)?;
+        if !self.claim_term(bin_client.as_ref(), term).await? {
+            // a leader of a later term took over already
+            return Ok(());
+        }
+        if self.paused(bin_client.as_ref()).await? {
+            // the changes seen meanwhile are found again once resumed
+            return Ok(());
+        }
 
-        // if the keeper is in its first round, fetch back status and migration log
-        if !self.activated {
-            // println!("Keeper {} is promoted", self.this);
-            node_join_migration_index = None;
-            node_leave_migration_index = None;
-            let back_status_str = bin_client.get(BACK_STATUS_STORE_KEY).await?;
-            let migration_log_str = bin_client.get(MIGRATION_LOG_KEY).await?;
-            if back_status_str.is_none() {
+        // a plan an earlier leader did not finish goes first, the changes
+        // since are planned in the next round
+        if let Some(log) = bin_client.get(MIGRATION_LOG_KEY).await? {
+            let log: MigrationLog = serde_json::from_str(&log)?;
+            return self.run_migration(bin_client.as_ref(), log, term).await;
+        }
+        let back_status_old: Vec<bool> = match bin_client.get(BACK_STATUS_STORE_KEY).await? {
+            Some(status) => serde_json::from_str(&status)?,
+            None => {
+                // first round of the cluster, nothing to migrate from
                 bin_client
                     .set(&KeyValue {
                         key: BACK_STATUS_STORE_KEY.to_string(),
                         value: serde_json::to_string(&back_status_copy)?,
                     })
                     .await?;
-                self.activated = true;
-                // println!("Get null back status");
+                return Ok(());
             }
-            if migration_log_str.is_none() {
-                if !back_status_str.is_none() {
-                    let back_status_old: Vec<bool> =
-                        serde_json::from_str(&back_status_str.unwrap())?;
-                    for i in 0..back_status.len() {
-                        if back_status[i] != back_status_old[i] {
-                            if back_status[i] {
-                                node_join_migration_index = Some(i);
-                            } else {
-                                node_leave_migration_index = Some(i);
-                            }
-                        }
-                    }
+        };
+        if back_status_old == back_status_copy {
+            return Ok(());
+        }
+        let log = MigrationLog::new(back_status_old, back_status_copy, term);
+        self.state.write().await.migration = Some(log.clone());
+        tokio::time::sleep(Duration::from_secs(SCAN_INTERVAL_CONSTANT)).await;
+        if !self.election.leads(term).await {
+            self.state.write().await.migration = None;
+            return Ok(());
+        }
+        // append migration log
+        bin_client
+            .set(&KeyValue {
+                key: MIGRATION_LOG_KEY.to_string(),
+                value: serde_json::to_string(&log)?,
+            })
+            .await?;
+        self.run_migration(bin_client.as_ref(), log, term).await
+    }
+}
+
+impl KeeperMigrator {
+    /// carries out the steps of `log` from `log.next` on, recording each step
+    /// done and the checkpoints of the one in progress in the migration log,
+    /// so a successor resumes from the last checkpoint. A pause stops it at
+    /// the next checkpoint, and it resumes from there. A step that fails is
+    /// resumed the same way in the next round, unless a backend it needs is
+    /// gone: then the plan is given up, the back status reached so far is
+    /// recorded and the next round plans again from there.
+    async fn run_migration(
+        &self,
+        bin_client: &dyn Storage,
+        mut log: MigrationLog,
+        term: u64,
+    ) -> TribResult<()> {
+        let ctx = self.migration_context();
+        while log.next < log.steps.len() {
+            if !self.election.leads(term).await {
+                self.state.write().await.migration = None;
+                return Ok(());
+            }
+            self.state.write().await.migration = Some(log.clone());
+            if self.paused(bin_client).await? {
+                return Ok(());
+            }
+            let checkpoint = LogCheckpoint {
+                bin_client,
+                log: Mutex::new(log.clone()),
+                election: self.election.clone(),
+                term,
+                state: self.state.clone(),
+            };
+            let res = keeper_helper::migrate_membership_change(
+                &ctx,
+                log.status_after(log.next),
+                log.status_after(log.next + 1),
+                &log.cursors,
+                Some(&checkpoint),
+            )
+            .await;
+            log = checkpoint.log.into_inner();
+            if let Err(err) = res {
+                if !self.election.leads(term).await {
+                    // the successor resumes from the last checkpoint
+                    self.state.write().await.migration = None;
+                    return Ok(());
+                }
+                if self.paused(bin_client).await.unwrap_or(false) {
+                    // resumed from the last checkpoint once unpaused
+                    return Ok(());
                 }
-            } else {
-                let migration_log: MigrationLog =
-                    serde_json::from_str(&migration_log_str.unwrap())?;
-                if migration_log.leave {
-                    node_leave_migration_index = Some(migration_log.back_id);
+                self.state.write().await.migration = None;
+                // the next round resumes from the last checkpoint, unless a
+                // backend the step copies to or from is gone by now
+                let scan = self.state.read().await.backs_status.clone();
+                let gone = log
+                    .status_after(log.next + 1)
+                    .iter()
+                    .zip(scan)
+                    .any(|(&planned, alive)| planned && !alive);
+                if gone {
+                    self.finish_migration(bin_client, &log.status_after(log.next))
+                        .await?;
                 } else {
-                    node_join_migration_index = Some(migration_log.back_id);
+                    bin_client
+                        .set(&KeyValue {
+                            key: MIGRATION_LOG_KEY.to_string(),
+                            value: serde_json::to_string(&log)?,
+                        })
+                        .await?;
                 }
+                return Err(err);
+            }
+            log.next += 1;
+            log.cursors.clear();
+            log.done = 0;
+            log.total = 0;
+            if log.next < log.steps.len() {
+                bin_client
+                    .set(&KeyValue {
+                        key: MIGRATION_LOG_KEY.to_string(),
+                        value: serde_json::to_string(&log)?,
+                    })
+                    .await?;
             }
         }
-        drop(back_status);
+        self.state.write().await.migration = None;
+        self.finish_migration(bin_client, &log.to).await
+    }
 
-        if node_join_migration_index.is_some() {
-            self.activated = true;
-            let node_join_index = node_join_migration_index.unwrap();
-            let log_str = serde_json::to_string(&MigrationLog {
-                back_id: node_join_index,
-                leave: false,
-            })?;
-            tokio::time::sleep(Duration::from_secs(SCAN_INTERVAL_CONSTANT)).await;
-            // append migration log
-            bin_client
-                .set(&KeyValue {
-                    key: MIGRATION_LOG_KEY.to_string(),
-                    value: log_str.clone(),
-                })
-                .await?;
-            // update back status
-            bin_client
-                .set(&KeyValue {
-                    key: BACK_STATUS_STORE_KEY.to_string(),
-                    value: serde_json::to_string(&back_status_copy)?,
-                })
-                .await?;
-            keeper_helper::migrate_to_joined_node(
-                self.backs.clone(),
-                self.channel_cache.clone(),
-                node_join_index,
-                back_status_copy,
-                self.lock_client.clone(),
-            )
+    /// whether an operator paused migrations, as recorded in the keeper
+    /// store.
+    async fn paused(&self, bin_client: &dyn Storage) -> TribResult<bool> {
+        let paused = migration_paused(bin_client).await?;
+        self.state.write().await.paused = paused;
+        Ok(paused)
+    }
+
+    /// records `back_status` as the one migrated to and clears the log.
+    async fn finish_migration(
+        &self,
+        bin_client: &dyn Storage,
+        back_status: &[bool],
+    ) -> TribResult<()> {
+        bin_client
+            .set(&KeyValue {
+                key: BACK_STATUS_STORE_KEY.to_string(),
+                value: serde_json::to_string(back_status)?,
+            })
             .await?;
-            // println!("End migrate_to_joined_node");
-            bin_client
-                .set(&KeyValue {
-                    key: MIGRATION_LOG_KEY.to_string(),
-                    value: "".to_string(),
-                })
-                .await?;
-            return Ok(());
-        } else if node_leave_migration_index.is_some() {
-            self.activated = true;
-            let node_leave_index = node_leave_migration_index.unwrap();
-            let log_str = serde_json::to_string(&MigrationLog {
-                back_id: node_leave_index,
-                leave: true,
-            })?;
-            tokio::time::sleep(Duration::from_secs(SCAN_INTERVAL_CONSTANT)).await;
-            // append migration log
-            bin_client
-                .set(&KeyValue {
-                    key: MIGRATION_LOG_KEY.to_string(),
-                    value: log_str.clone(),
-                })
-                .await?;
-            // update back status
-            bin_client
-                .set(&KeyValue {
-                    key: BACK_STATUS_STORE_KEY.to_string(),
-                    value: serde_json::to_string(&back_status_copy)?,
-                })
-                .await?;
-            // println!("Start migrate_to_left_node");
-            keeper_helper::migrate_to_left_node(
-                self.backs.clone(),
-                self.channel_cache.clone(),
-                node_leave_migration_index.unwrap(),
-                back_status_copy,
-                self.lock_client.clone(),
-            )
+        bin_client
+            .set(&KeyValue {
+                key: MIGRATION_LOG_KEY.to_string(),
+                value: "".to_string(),
+            })
             .await?;
-            // println!("End migrate_to_left_node");
-            let res = bin_client
-                .set(&KeyValue {
-                    key: MIGRATION_LOG_KEY.to_string(),
-                    value: "".to_string(),
-                })
-                .await;
-            if res.is_err() {
-                // println!("Send back status error");
-                // println!("{:?}", res);
-            }
-            return Ok(());
+        Ok(())
+    }
+}
+
+/// whether the keeper store records migrations as paused.
+pub(crate) async fn migration_paused(bin_client: &dyn Storage) -> TribResult<bool> {
+    Ok(bin_client.get(MIGRATION_PAUSED_KEY).await?.is_some())
+}
+
+// checkpoints the step in progress into the migration log, as long as this
+// keeper leads the term it runs the migration in, and stops the migration
+// there once paused
+struct LogCheckpoint<'a> {
+    bin_client: &'a dyn Storage,
+    log: Mutex<MigrationLog>,
+    election: Arc<KeeperElection>,
+    term: u64,
+    state: Arc<RwLock<KeeperState>>,
+}
+
+#[async_trait]
+impl MigrationCheckpoint for LogCheckpoint<'_> {
+    async fn checkpoint(
+        &self,
+        cursor: MigrationCursor,
+        done: usize,
+        total: usize,
+    ) -> TribResult<()> {
+        let mut log = self.log.lock().await;
+        if !self.election.leads(self.term).await {
+            return Err(Box::new(TribblerError::Unknown(
+                "keeper lost the lead during migration".to_string(),
+            )));
         }
-        // only update back status if the keeper is in its first round or view change happens
-        if !self.activated {
-            self.activated = true;
-            bin_client
-                .set(&KeyValue {
-                    key: BACK_STATUS_STORE_KEY.to_string(),
-                    value: serde_json::to_string(&back_status_copy)?,
-                })
-                .await?;
+        log.cursors.retain(|other| other.back_id != cursor.back_id);
+        log.cursors.push(cursor);
+        log.done = done;
+        log.total = total;
+        self.bin_client
+            .set(&KeyValue {
+                key: MIGRATION_LOG_KEY.to_string(),
+                value: serde_json::to_string(&*log)?,
+            })
+            .await?;
+        self.state.write().await.migration = Some(log.clone());
+        if migration_paused(self.bin_client).await? {
+            return Err(Box::new(TribblerError::Unknown(
+                "migration paused".to_string(),
+            )));
         }
-
         Ok(())
     }
 }
@@ -357,27 +570,12 @@ impl KeeperClockBroadcastorTrait for KeeperClockBroadcastor {
     }
 
     async fn broadcast_logical_clock(&self) -> TribResult<()> {
-        let mut largest_keeper_alive = self.this;
-        for i in 0..self.keepers.len() {
-            if i == self.this {
-                continue;
-            }
-            let chan_res =
-                update_channel_cache(self.channel_cache.clone(), self.keepers[i].clone()).await;
-            if chan_res.is_err() {
-                continue;
-            }
-            let mut client = KeeperServiceClient::new(chan_res.unwrap().clone());
-            let resp_res = client.ping(keeper::Heartbeat { value: true }).await;
-            if resp_res.is_err() {
-                continue;
-            }
-            largest_keeper_alive = cmp::max(largest_keeper_alive, i);
-        }
-        if largest_keeper_alive != self.this {
-            // only smallest keeper alive is in charge of migration
-            return Ok(());
-        }
+        // only the leader of the current term broadcasts the clock, a keeper
+        // that lost its term leaves the clocks to the next leader
+        let term = match self.election.leading_term().await {
+            Some(term) => term,
+            None => return Ok(()),
+        };
 
         let len = self.backs.len();
 
@@ -393,9 +591,18 @@ impl KeeperClockBroadcastorTrait for KeeperClockBroadcastor {
             max_clock = cmp::max(max_clock, recv_clock);
         }
 
+        if !self.election.leads(term).await {
+            return Ok(());
+        }
         for i in 0..len {
             let _ = self.update_clock_send(i, max_clock).await;
         }
+        let mut state = self.state.write().await;
+        state.last_clock = max_clock;
+        state.last_broadcast = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
         Ok(())
     }
 }
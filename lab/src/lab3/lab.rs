@@ -1,14 +1,22 @@
-use super::bin_client::{self, init_lock_servers_addresses};
+use super::bin_client::lock_addrs_or_default;
 use super::lock_client::{self, LockClient};
 use crate::big_fucking_tester::generate_random_username;
 use crate::lab3::keeper_server::{KeeperClockBroadcastorTrait, KeeperMigratorTrait};
 
-use super::constants::{BRAODCAST_CLOCK_INTERVAL, MIGRATION_INTERVAL, VALIDATION_BIT_KEY};
+use super::constants::{
+    ANTI_ENTROPY_INTERVAL, BRAODCAST_CLOCK_INTERVAL, HISTORY_COMPACTION_INTERVAL,
+    KEEPER_HEARTBEAT_INTERVAL, LOCK_PEER_PING_INTERVAL, LOCK_REAP_INTERVAL, MIGRATION_INTERVAL,
+    TXN_RECOVERY_INTERVAL, VALIDATION_BIT_KEY,
+};
+use super::keeper_election::KeeperElection;
 use super::keeper_rpc_receiver::KeeperRPCReceiver;
 use super::keeper_server::{KeeperClockBroadcastor, KeeperMigrator};
+use super::lock_server::LockServer;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
 use std::sync::Arc;
+use tokio::sync::mpsc::Receiver;
 use tokio::sync::RwLock;
 use tokio::time;
 use tonic::transport::Channel;
@@ -16,16 +24,20 @@ use tribbler::rpc::trib_storage_server::TribStorageServer;
 use tribbler::storage::BinStorage;
 use tribbler::storage::{self, KeyString};
 use tribbler::trib;
-use tribbler::{config::BackConfig, err::TribResult};
+use tribbler::{
+    config::BackConfig,
+    err::{TribResult, TribblerError},
+};
 
 use super::bin_client::{update_channel_cache, BinStorageClient, LockServerPinger, TxnClient};
 use super::frontend_server::FrontendServer;
-use tribbler::config::KeeperConfig;
+use tribbler::config::{KeeperConfig, DEFAULT_VNODES};
 
 use super::backend_server::BackendServer;
 use super::client::StorageClient;
 
 use super::super::keeper::keeper_service_server::KeeperServiceServer;
+use super::super::lockserver::lock_service_server::LockServiceServer;
 use tribbler::storage::Storage;
 
 /// This function accepts a list of backend addresses, and returns a
@@ -37,12 +49,50 @@ pub async fn new_bin_client(backs: Vec<String>) -> TribResult<Box<dyn BinStorage
     return Ok(Box::new(bin_client));
 }
 
+/// same as [new_bin_client], locking through the lock servers at `locks`
+/// instead of the ones of `config.env`.
+pub async fn new_bin_client_with_locks(
+    backs: Vec<String>,
+    locks: Vec<String>,
+) -> TribResult<Box<dyn BinStorage>> {
+    let bin_client = BinStorageClient::new_with_locks(backs, lock_addrs_or_default(locks));
+    Ok(Box::new(bin_client))
+}
+
+/// same as [new_bin_client_with_locks], keeping each bin on `replicas`
+/// backends instead of the default two.
+pub async fn new_bin_client_with_replicas(
+    backs: Vec<String>,
+    locks: Vec<String>,
+    replicas: usize,
+) -> TribResult<Box<dyn BinStorage>> {
+    new_bin_client_with_placement(backs, locks, replicas, DEFAULT_VNODES).await
+}
+
+/// same as [new_bin_client_with_replicas], placing each backend `vnodes`
+/// times on the ring bins are hashed onto.
+pub async fn new_bin_client_with_placement(
+    backs: Vec<String>,
+    locks: Vec<String>,
+    replicas: usize,
+    vnodes: usize,
+) -> TribResult<Box<dyn BinStorage>> {
+    let mut bin_client = BinStorageClient::new_with_locks(backs, lock_addrs_or_default(locks));
+    bin_client.update_replicas(replicas);
+    bin_client.update_vnodes(vnodes);
+    Ok(Box::new(bin_client))
+}
+
 /// this async function accepts a [KeeperConfig] that should be used to start
 /// a new keeper server on the address given in the config.
 ///
 /// This function should block indefinitely and only return upon erroring. Make
 /// sure to send the proper signal to the channel in `kc` when the keeper has
 /// started.
+///
+/// The keepers of `kc.addrs` elect a leader that alone migrates data, which
+/// takes a majority of them running. Once fewer are up, no migration happens
+/// until a majority is back.
 
 #[allow(unused_variables)]
 pub async fn serve_keeper(kc: KeeperConfig) -> TribResult<()> {
@@ -86,14 +136,37 @@ pub async fn serve_keeper(kc: KeeperConfig) -> TribResult<()> {
                 .await?;
         }
     }
+    // elects the keeper that migrates and broadcasts clocks in each term
+    let election = Arc::new(KeeperElection::new(
+        kc.this,
+        kc.addrs.clone(),
+        channel_cache.clone(),
+    ));
+    election.tick().await;
+    let election_ticker = election.clone();
+    let (election_shut_sender, mut election_shut_receiver) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut election_interval =
+            time::interval(time::Duration::from_secs(KEEPER_HEARTBEAT_INTERVAL));
+        loop {
+            tokio::select! {
+                _ = election_interval.tick() => {
+                    election_ticker.tick().await;
+                }
+                _ = election_shut_receiver.recv() => {
+                    break;
+                }
+            }
+        }
+    });
+
     /*let mut keeper_migrator =
     KeeperMigrator::new(kc.this, kc.addrs.clone(), &kc.backs.clone(), backs_status);*/
     let mut keeper_migrator = KeeperMigrator::new_with_channel(
-        kc.this,
-        kc.addrs.clone(),
-        &kc.backs.clone(),
+        &kc,
         backs_status,
         channel_cache.clone(),
+        election.clone(),
     );
     /*let keeper_clock_broadcastor =
     KeeperClockBroadcastor::new(kc.this, kc.addrs.clone(), &kc.backs.clone());*/
@@ -102,6 +175,8 @@ pub async fn serve_keeper(kc: KeeperConfig) -> TribResult<()> {
         kc.addrs.clone(),
         &kc.backs.clone(),
         channel_cache.clone(),
+        election.clone(),
+        keeper_migrator.state.clone(),
     );
 
     let (broadcast_shutdown_sender, mut broadcast_shutdown_receiver) =
@@ -121,6 +196,107 @@ pub async fn serve_keeper(kc: KeeperConfig) -> TribResult<()> {
         }
     });
 
+    // each background task locks through its own client: held locks are
+    // cached per client, so tasks sharing one would take each other's locks
+    // as their own and release them under each other's feet
+    let new_task_lock_client = || {
+        Arc::new(LockClient::new(
+            lock_addrs_or_default(kc.locks.clone()),
+            true,
+        ))
+    };
+
+    // rolls back the transactions of crashed clients, on the leader only
+    let recovery_election = election.clone();
+    let recovery_lock_client = new_task_lock_client();
+    let mut recovery_storage = BinStorageClient::new_with_channel(
+        &kc.backs,
+        channel_cache.clone(),
+        recovery_lock_client.clone(),
+    );
+    recovery_storage.update_replicas(kc.replicas);
+    recovery_storage.update_vnodes(kc.vnodes);
+    let (recovery_shut_sender, mut recovery_shut_receiver) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut recovery_interval =
+            time::interval(time::Duration::from_secs(TXN_RECOVERY_INTERVAL));
+        loop {
+            tokio::select! {
+                _ = recovery_interval.tick() => {
+                    if recovery_election.leading_term().await.is_some() {
+                        let _ = recovery_storage.recover_transactions(&recovery_lock_client).await;
+                    }
+                }
+                _ = recovery_shut_receiver.recv() => {
+                    break;
+                }
+            }
+        }
+    });
+
+    // compacts the key histories up to the clock seen one round earlier,
+    // on the leader only
+    let compaction_election = election.clone();
+    let mut compaction_storage = BinStorageClient::new_with_channel(
+        &kc.backs,
+        channel_cache.clone(),
+        new_task_lock_client(),
+    );
+    compaction_storage.update_replicas(kc.replicas);
+    compaction_storage.update_vnodes(kc.vnodes);
+    let (compaction_shut_sender, mut compaction_shut_receiver) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut compaction_interval =
+            time::interval(time::Duration::from_secs(HISTORY_COMPACTION_INTERVAL));
+        let mut safe_clock = None;
+        loop {
+            tokio::select! {
+                _ = compaction_interval.tick() => {
+                    if let Some(clock) = safe_clock {
+                        if compaction_election.leading_term().await.is_some() {
+                            let _ = compaction_storage.compact_histories(clock).await;
+                        }
+                    }
+                    safe_clock = compaction_storage.sync_clocks().await.ok();
+                }
+                _ = compaction_shut_receiver.recv() => {
+                    break;
+                }
+            }
+        }
+    });
+
+    // repairs the keys replicas disagree on, found by comparing digests, on
+    // the leader only
+    let anti_entropy_election = election.clone();
+    let mut anti_entropy_storage = BinStorageClient::new_with_channel(
+        &kc.backs,
+        channel_cache.clone(),
+        new_task_lock_client(),
+    );
+    anti_entropy_storage.update_replicas(kc.replicas);
+    anti_entropy_storage.update_vnodes(kc.vnodes);
+    let (anti_entropy_shut_sender, mut anti_entropy_shut_receiver) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut anti_entropy_interval =
+            time::interval(time::Duration::from_secs(ANTI_ENTROPY_INTERVAL));
+        loop {
+            tokio::select! {
+                _ = anti_entropy_interval.tick() => {
+                    if anti_entropy_election.leading_term().await.is_some() {
+                        let _ = anti_entropy_storage.anti_entropy().await;
+                    }
+                }
+                _ = anti_entropy_shut_receiver.recv() => {
+                    break;
+                }
+            }
+        }
+    });
+
+    let keeper_state = keeper_migrator.state.clone();
+    let keeper_rescan = keeper_migrator.rescan.clone();
+    let migrate_rescan = keeper_migrator.rescan.clone();
     let (migrate_shut_sender, mut migrate_shut_receiver) = tokio::sync::mpsc::channel(1);
     tokio::spawn(async move {
         let mut migrate_interval = time::interval(time::Duration::from_secs(MIGRATION_INTERVAL));
@@ -129,13 +305,26 @@ pub async fn serve_keeper(kc: KeeperConfig) -> TribResult<()> {
                 _ = migrate_interval.tick() => {
                     let _ = keeper_migrator.check_migration().await;
                 }
+                _ = migrate_rescan.notified() => {
+                    let _ = keeper_migrator.check_migration().await;
+                }
                 _ = migrate_shut_receiver.recv() => {
                     break;
                 }
             }
         }
     });
-    let keeper_rpc_server = KeeperRPCReceiver::new();
+
+    // records the operator's pause in the keeper store
+    let mut control_storage = BinStorageClient::new_with_channel(
+        &kc.backs,
+        channel_cache.clone(),
+        new_task_lock_client(),
+    );
+    control_storage.update_replicas(kc.replicas);
+    control_storage.update_vnodes(kc.vnodes);
+    let keeper_rpc_server =
+        KeeperRPCReceiver::new(election, keeper_state, keeper_rescan, control_storage);
     let config_addr = &kc.addrs.clone()[kc.this];
     let config_addr_str = config_addr.as_str();
     let config_addr_string = config_addr_str.replace("localhost", "127.0.0.1");
@@ -158,8 +347,12 @@ pub async fn serve_keeper(kc: KeeperConfig) -> TribResult<()> {
                         let sig_sent = ready_chan.clone().send(true);
                     }
                     shut_chan.recv().await;
-                    migrate_shut_sender.clone().send(()).await;
-                    broadcast_shutdown_sender.clone().send(()).await;
+                    let _ = migrate_shut_sender.clone().send(()).await;
+                    let _ = recovery_shut_sender.clone().send(()).await;
+                    let _ = compaction_shut_sender.clone().send(()).await;
+                    let _ = anti_entropy_shut_sender.clone().send(()).await;
+                    let _ = broadcast_shutdown_sender.clone().send(()).await;
+                    let _ = election_shut_sender.clone().send(()).await;
                 })
                 .await;
             if server_status.is_err() {}
@@ -254,9 +447,95 @@ pub async fn new_client(addr: &str) -> TribResult<Box<dyn Storage>> {
     Ok(Box::new(storage_client))
 }
 
+/// an async function which blocks indefinitely until interrupted serving a
+/// lock server on `addr`.
+///
+/// `peers` lists every lock server of the deployment, `addr` included, in the
+/// same order as the lock clients see them. The lock state is replicated
+/// among them.
+///
+/// `ready` and `shutdown` behave the same way as the channels in a
+/// [BackConfig].
+pub async fn serve_lock_server(
+    addr: String,
+    peers: Vec<String>,
+    ready: Option<Sender<bool>>,
+    shutdown: Option<Receiver<()>>,
+) -> TribResult<()> {
+    let replaced_addr = addr.replace("localhost", "127.0.0.1");
+    let server_addr = match replaced_addr.parse::<SocketAddr>() {
+        Ok(value) => value,
+        Err(e) => return Err(Box::new(e)),
+    };
+    let this = match peers
+        .iter()
+        .position(|peer| peer.replace("localhost", "127.0.0.1") == replaced_addr)
+    {
+        Some(this) => this,
+        None => {
+            return Err(Box::new(TribblerError::Unknown(format!(
+                "lock server {} is not one of its peers",
+                addr
+            ))))
+        }
+    };
+
+    let server = LockServer::new(this, peers);
+    server.refresh_peers().await;
+    server.recover().await;
+
+    // reclaims the leases that stopped being renewed and keeps track of the
+    // alive peers. Dropping `maintenance_tx` when the server returns stops
+    // the loop.
+    let (maintenance_tx, mut maintenance_rx) = tokio::sync::mpsc::channel::<()>(1);
+    let maintainer = server.clone();
+    tokio::spawn(async move {
+        let mut reap_interval = time::interval(time::Duration::from_secs(LOCK_REAP_INTERVAL));
+        let mut ping_interval = time::interval(time::Duration::from_secs(LOCK_PEER_PING_INTERVAL));
+        loop {
+            tokio::select! {
+                _ = maintenance_rx.recv() => break,
+                _ = reap_interval.tick() => maintainer.reap_expired_leases().await,
+                _ = ping_interval.tick() => maintainer.refresh_peers().await,
+            }
+        }
+    });
+
+    match shutdown {
+        Some(mut shut_chan) => {
+            tonic::transport::Server::builder()
+                .add_service(LockServiceServer::new(server))
+                .serve_with_shutdown(server_addr, async {
+                    if let Some(ready_chan) = ready {
+                        let _ = ready_chan.send(true);
+                    }
+                    shut_chan.recv().await;
+                })
+                .await?;
+        }
+        None => {
+            if let Some(ready_chan) = ready {
+                let _ = ready_chan.send(true);
+            }
+            tonic::transport::Server::builder()
+                .add_service(LockServiceServer::new(server))
+                .serve(server_addr)
+                .await?;
+        }
+    }
+    drop(maintenance_tx);
+    Ok(())
+}
+
+/// returns `locks`, or the lock servers of `config.env` when no lock server is
+/// configured.
+pub fn lock_servers_or_default(locks: Vec<String>) -> Vec<String> {
+    lock_addrs_or_default(locks)
+}
+
 pub async fn new_lockserver_ping_test() -> TribResult<()> {
     // let mut client = TribStorageClient::connect(String::from(addr)).await?;
-    let pinger = LockServerPinger::new();
+    let pinger = LockServerPinger::new(lock_addrs_or_default(vec![]));
     pinger.ping_test().await?;
     Ok(())
 }
@@ -275,13 +554,14 @@ pub fn new_txn_client(
     return client;
 }
 
-pub fn new_lock_client() -> LockClient {
-    let lock_addrs = bin_client::init_lock_servers_addresses();
+/// creates a lock client for the lock servers at `locks`, falling back to the
+/// lock servers of `config.env` when `locks` is empty.
+pub fn new_lock_client(locks: Vec<String>) -> LockClient {
+    let lock_addrs = lock_addrs_or_default(locks);
     let clock_client = LockClient::new(lock_addrs, false);
     return clock_client;
 }
 
-pub fn new_bin_client_for_txn(backs: Vec<String>) -> BinStorageClient {
-    let binStorage = BinStorageClient::new(backs);
-    return binStorage;
+pub fn new_bin_client_for_txn(backs: Vec<String>, locks: Vec<String>) -> BinStorageClient {
+    BinStorageClient::new_with_locks(backs, lock_addrs_or_default(locks))
 }
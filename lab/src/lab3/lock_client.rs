@@ -3,28 +3,50 @@ use crate::lockserver::ReleaseLocksInfo;
 
 use super::super::lockserver::lock_service_client::LockServiceClient;
 use super::bin_client::update_channel_cache;
-use std::collections::hash_map::DefaultHasher;
+use super::constants::{
+    LOCK_ACQUIRE_TIMEOUT, LOCK_CONNECT_RETRIES, LOCK_HEARTBEAT_INTERVAL, LOCK_REPLICATION_FACTOR,
+    LOCK_RETRY_BACKOFF_MILLIS, LOCK_RING_VNODES,
+};
+use super::hash_ring::HashRing;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::hash::Hash;
-use std::hash::Hasher;
 use std::sync::Arc;
+use tokio::sync::mpsc;
 use tokio::sync::RwLock;
+use tokio::time;
 use tonic::transport::Channel;
+use tonic::Code;
 use uuid::Uuid;
 
 #[derive(Debug, Default)]
 pub struct LockClient {
     client_id: String,
-    read_held_cache: RwLock<HashSet<String>>,
-    write_held_cache: RwLock<HashSet<String>>,
+    read_held_cache: Arc<RwLock<HashSet<String>>>,
+    write_held_cache: Arc<RwLock<HashSet<String>>>,
+    // held keys whose lease a lock server reclaimed. They are not renewed
+    // anymore, and stay here until `release_locks` reports the expiry.
+    expired_cache: Arc<RwLock<HashSet<String>>>,
     locks_addrs: Vec<String>,
+    // places every lock key on the lock server owning it
+    ring: HashRing,
     channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
     is_keeper: bool,
+    // renews the leases of the held locks in the background, started on the
+    // first acquire. Dropping the sender stops it.
+    heartbeat_stop: RwLock<Option<mpsc::Sender<()>>>,
+    // how many times an unreachable lock server is retried, and the delay
+    // before the first retry (doubled on every attempt)
+    max_retries: u32,
+    retry_backoff: time::Duration,
+    // upper bound of a whole acquire_locks call, zero means wait forever
+    acquire_timeout: time::Duration,
+    // last lock server known to be the primary of each partition
+    primaries: RwLock<HashMap<usize, usize>>,
 }
 
 // use tonic::transport::Endpoint;
 use tribbler::err::TribResult;
+use tribbler::err::TribblerError;
 use tribbler::rpc;
 use tribbler::storage;
 
@@ -37,45 +59,203 @@ impl LockClient {
         }
         Self {
             client_id: format!("{}{}", keeper_prefix, uuid),
-            read_held_cache: RwLock::new(HashSet::new()),
-            write_held_cache: RwLock::new(HashSet::new()),
+            read_held_cache: Arc::new(RwLock::new(HashSet::new())),
+            write_held_cache: Arc::new(RwLock::new(HashSet::new())),
+            expired_cache: Arc::new(RwLock::new(HashSet::new())),
             channel_cache: Arc::new(RwLock::new(HashMap::new())),
+            ring: HashRing::new(&locks_addrs, LOCK_RING_VNODES),
             locks_addrs: locks_addrs.clone(),
             is_keeper: is_keeper,
+            heartbeat_stop: RwLock::new(None),
+            max_retries: LOCK_CONNECT_RETRIES,
+            retry_backoff: time::Duration::from_millis(LOCK_RETRY_BACKOFF_MILLIS),
+            acquire_timeout: time::Duration::from_secs(LOCK_ACQUIRE_TIMEOUT),
+            primaries: RwLock::new(HashMap::new()),
         }
     }
 
+    pub fn update_retry_policy(&mut self, max_retries: u32, retry_backoff: time::Duration) {
+        self.max_retries = max_retries;
+        self.retry_backoff = retry_backoff;
+    }
+
+    pub fn update_acquire_timeout(&mut self, acquire_timeout: time::Duration) {
+        self.acquire_timeout = acquire_timeout;
+    }
+
+    /// Stops renewing the leases of the held locks, as a holder that hangs
+    /// would. The next acquire starts renewing them again.
+    pub async fn stop_heartbeat(&self) {
+        self.heartbeat_stop.write().await.take();
+    }
+
+    /// Returns the lock servers of the chain of partition `ind`, starting
+    /// from the last known primary.
+    async fn chain_from_primary(&self, ind: usize) -> Vec<usize> {
+        let mut chain = lock_chain(ind, self.locks_addrs.len());
+        if let Some(primary) = self.primaries.read().await.get(&ind) {
+            if let Some(pos) = chain.iter().position(|server| server == primary) {
+                chain.rotate_left(pos);
+            }
+        }
+        chain
+    }
+
+    async fn connect(&self, server: usize) -> Option<LockServiceClient<Channel>> {
+        let chan =
+            update_channel_cache(self.channel_cache.clone(), self.locks_addrs[server].clone())
+                .await
+                .ok()?;
+        Some(LockServiceClient::new(chan))
+    }
+
     fn get_hash_index(&self, key: &str) -> u32 {
-        let mut hasher = DefaultHasher::new();
-        key.hash(&mut hasher);
-        let hash_res = hasher.finish();
-        let len = self.locks_addrs.len() as u64;
-        let ind = (hash_res % len) as u32;
-        return ind;
+        self.ring.locate(key) as u32
     }
 
+    async fn start_heartbeat(&self) {
+        let mut heartbeat_stop = self.heartbeat_stop.write().await;
+        if heartbeat_stop.is_some() {
+            return;
+        }
+        let (tx, mut rx) = mpsc::channel::<()>(1);
+        *heartbeat_stop = Some(tx);
+        drop(heartbeat_stop);
+
+        let client_id = self.client_id.to_string();
+        let is_keeper = self.is_keeper;
+        let locks_addrs = self.locks_addrs.clone();
+        let ring = self.ring.clone();
+        let channel_cache = self.channel_cache.clone();
+        let read_held_cache = self.read_held_cache.clone();
+        let write_held_cache = self.write_held_cache.clone();
+        let expired_cache = self.expired_cache.clone();
+        tokio::spawn(async move {
+            let mut interval = time::interval(time::Duration::from_secs(LOCK_HEARTBEAT_INTERVAL));
+            loop {
+                tokio::select! {
+                    _ = rx.recv() => break,
+                    _ = interval.tick() => {
+                        let read_keys = read_held_cache.read().await.iter().cloned().collect::<Vec<String>>();
+                        let write_keys = write_held_cache.read().await.iter().cloned().collect::<Vec<String>>();
+                        let mut read_bins: HashMap<usize, Vec<String>> = HashMap::new();
+                        let mut write_bins: HashMap<usize, Vec<String>> = HashMap::new();
+                        for key in read_keys.into_iter() {
+                            let ind = ring.locate(&key);
+                            read_bins.entry(ind).or_default().push(key);
+                        }
+                        for key in write_keys.into_iter() {
+                            let ind = ring.locate(&key);
+                            write_bins.entry(ind).or_default().push(key);
+                        }
+                        let bins = read_bins.keys().chain(write_bins.keys()).cloned().collect::<HashSet<usize>>();
+                        // the backups are renewed as well, so they can take
+                        // over the leases when the primary fails
+                        for ind in bins {
+                            let read_keys = read_bins.remove(&ind).unwrap_or_default();
+                            let write_keys = write_bins.remove(&ind).unwrap_or_default();
+                            for server in lock_chain(ind, locks_addrs.len()) {
+                                let chan_res = update_channel_cache(channel_cache.clone(), locks_addrs[server].clone()).await;
+                                let chan = match chan_res {
+                                    Ok(chan) => chan,
+                                    Err(_) => continue,
+                                };
+                                let mut client = LockServiceClient::new(chan);
+                                let res = client
+                                    .heartbeat(AcquireLocksInfo {
+                                        client_id: client_id.to_string(),
+                                        read_keys: read_keys.clone(),
+                                        write_keys: write_keys.clone(),
+                                        is_keeper,
+                                        timestamp: 0,
+                                    })
+                                    .await;
+                                if let Ok(success) = res {
+                                    if !success.into_inner().flag {
+                                        log::warn!("lock lease of {} expired on {}", client_id, locks_addrs[server]);
+                                        // the server dropped these locks: stop
+                                        // renewing them, the next release
+                                        // reports the expiry to the holder
+                                        let mut expired = expired_cache.write().await;
+                                        for key in read_keys.iter().chain(write_keys.iter()) {
+                                            expired.insert(key.to_string());
+                                        }
+                                        drop(expired);
+                                        let mut read_held = read_held_cache.write().await;
+                                        let mut write_held = write_held_cache.write().await;
+                                        for key in read_keys.iter() {
+                                            read_held.remove(key);
+                                        }
+                                        for key in write_keys.iter() {
+                                            write_held.remove(key);
+                                        }
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Acquires the keys of partition `ind` from its primary. The chain of
+    /// the partition is walked when a server is unreachable or not the
+    /// primary, and walked again with exponential backoff until the retries
+    /// run out.
     async fn acquire_locks_with_server_index(
         &self,
         ind: usize,
         read_keys: Vec<String>,
         write_keys: Vec<String>,
+        timestamp: u64,
     ) -> TribResult<()> {
-        let chan_res =
-            update_channel_cache(self.channel_cache.clone(), self.locks_addrs[ind].clone()).await;
-        if chan_res.is_err() {
-            panic!("Connection failed");
-        }
-        let mut client = LockServiceClient::new(chan_res.unwrap());
-        // println!("r {:?} w {:?}", read_keys, write_keys);
-        client
-            .acquire(AcquireLocksInfo {
-                client_id: self.client_id.to_string(),
-                read_keys,
-                write_keys,
-                is_keeper: self.is_keeper,
-            })
-            .await?;
-        Ok(())
+        let mut attempt = 0;
+        loop {
+            for server in self.chain_from_primary(ind).await {
+                let mut client = match self.connect(server).await {
+                    Some(client) => client,
+                    None => continue,
+                };
+                let res = client
+                    .acquire(AcquireLocksInfo {
+                        client_id: self.client_id.to_string(),
+                        read_keys: read_keys.clone(),
+                        write_keys: write_keys.clone(),
+                        is_keeper: self.is_keeper,
+                        timestamp,
+                    })
+                    .await;
+                match res {
+                    Ok(_) => {
+                        self.primaries.write().await.insert(ind, server);
+                        return Ok(());
+                    }
+                    Err(status) if status.code() == Code::Aborted => {
+                        return Err(Box::new(TribblerError::LockConflict(
+                            status.message().to_string(),
+                        )))
+                    }
+                    // not the primary, or a cached channel to a server that
+                    // went away
+                    Err(status)
+                        if status.code() == Code::PermissionDenied
+                            || status.code() == Code::Unavailable =>
+                    {
+                        continue
+                    }
+                    Err(status) => return Err(Box::new(status)),
+                }
+            }
+            if attempt >= self.max_retries {
+                return Err(Box::new(TribblerError::LockUnavailable(
+                    self.locks_addrs[ind].clone(),
+                )));
+            }
+            time::sleep(self.retry_backoff * 2u32.pow(attempt)).await;
+            attempt += 1;
+        }
     }
 
     async fn release_locks_with_server_index(
@@ -84,29 +264,85 @@ impl LockClient {
         read_keys: Vec<String>,
         write_keys: Vec<String>,
     ) -> TribResult<()> {
-        let chan_res =
-            update_channel_cache(self.channel_cache.clone(), self.locks_addrs[ind].clone()).await;
-        if chan_res.is_err() {
-            panic!("Connection failed");
-        }
-        let mut client = LockServiceClient::new(chan_res.unwrap());
-        client
-            .release(ReleaseLocksInfo {
-                client_id: self.client_id.to_string(),
-                read_keys,
-                write_keys,
-            })
-            .await?;
-        Ok(())
+        let mut attempt = 0;
+        loop {
+            for server in self.chain_from_primary(ind).await {
+                let mut client = match self.connect(server).await {
+                    Some(client) => client,
+                    None => continue,
+                };
+                let res = client
+                    .release(ReleaseLocksInfo {
+                        client_id: self.client_id.to_string(),
+                        read_keys: read_keys.clone(),
+                        write_keys: write_keys.clone(),
+                    })
+                    .await;
+                match res {
+                    Ok(_) => {
+                        self.primaries.write().await.insert(ind, server);
+                        return Ok(());
+                    }
+                    Err(status) if status.code() == Code::FailedPrecondition => {
+                        return Err(Box::new(TribblerError::LeaseExpired(
+                            self.client_id.to_string(),
+                        )))
+                    }
+                    Err(status)
+                        if status.code() == Code::PermissionDenied
+                            || status.code() == Code::Unavailable =>
+                    {
+                        continue
+                    }
+                    Err(status) => return Err(Box::new(status)),
+                }
+            }
+            if attempt >= self.max_retries {
+                return Err(Box::new(TribblerError::LockUnavailable(
+                    self.locks_addrs[ind].clone(),
+                )));
+            }
+            time::sleep(self.retry_backoff * 2u32.pow(attempt)).await;
+            attempt += 1;
+        }
     }
 
     pub async fn acquire_locks(
         &self,
         read_keys: Vec<String>,
         write_keys: Vec<String>,
+    ) -> TribResult<()> {
+        self.acquire_locks_with_timestamp(read_keys, write_keys, 0)
+            .await
+    }
+
+    /// Acquires the locks on behalf of a transaction started at `timestamp`.
+    /// Lock servers are visited in index order. When an older transaction
+    /// holds one of the keys the call fails with
+    /// [TribblerError::LockConflict] after giving back the locks it took, and
+    /// the transaction can be retried with the same `timestamp`. The locks
+    /// are given back the same way when a lock server is unreachable or the
+    /// call runs past its acquire timeout.
+    pub async fn acquire_locks_with_timestamp(
+        &self,
+        read_keys: Vec<String>,
+        write_keys: Vec<String>,
+        timestamp: u64,
     ) -> TribResult<()> {
         let client_id = format!("{}", self.client_id);
 
+        // the server keeps refusing a holder whose lease expired until it
+        // released its locks
+        let expired_cache = self.expired_cache.read().await;
+        if read_keys
+            .iter()
+            .chain(write_keys.iter())
+            .any(|key| expired_cache.contains(key))
+        {
+            return Err(Box::new(TribblerError::LeaseExpired(client_id)));
+        }
+        drop(expired_cache);
+
         let read_held_cache = self.read_held_cache.read().await;
         let write_held_cache = self.write_held_cache.read().await;
 
@@ -135,19 +371,54 @@ impl LockClient {
         }
         drop(read_held_cache);
         drop(write_held_cache);
-        for ind in bins {
+        if !bins.is_empty() {
+            self.start_heartbeat().await;
+        }
+        let mut bins = bins.into_iter().collect::<Vec<usize>>();
+        bins.sort();
+        let deadline = time::Instant::now() + self.acquire_timeout;
+        for (acquired, ind) in bins.iter().enumerate() {
             let mut purified_read_keys = vec![];
             let mut purified_write_keys = vec![];
-            let read_keys_option = read_bins.get(&ind);
+            let read_keys_option = read_bins.get(ind);
             if read_keys_option.is_some() {
                 purified_read_keys = read_keys_option.unwrap().clone();
             }
-            let write_keys_option = write_bins.get(&ind);
+            let write_keys_option = write_bins.get(ind);
             if write_keys_option.is_some() {
                 purified_write_keys = write_keys_option.unwrap().clone();
             }
-            self.acquire_locks_with_server_index(ind, purified_read_keys, purified_write_keys)
-                .await?;
+            let acquire = self.acquire_locks_with_server_index(
+                *ind,
+                purified_read_keys,
+                purified_write_keys,
+                timestamp,
+            );
+            let res = if self.acquire_timeout.is_zero() {
+                acquire.await
+            } else {
+                match time::timeout_at(deadline, acquire).await {
+                    Ok(res) => res,
+                    Err(_) => {
+                        Err(TribblerError::LockTimeout(self.locks_addrs[*ind].clone()).into())
+                    }
+                }
+            };
+            if let Err(err) = res {
+                // give back what this call already took, a dying transaction
+                // must not keep older ones waiting. The failed server is
+                // included, a timed out request may have been granted.
+                for prev in bins[..=acquired].iter() {
+                    let _ = self
+                        .release_locks_with_server_index(
+                            *prev,
+                            read_bins.get(prev).cloned().unwrap_or_default(),
+                            write_bins.get(prev).cloned().unwrap_or_default(),
+                        )
+                        .await;
+                }
+                return Err(err);
+            }
         }
         let mut read_held_cache = self.read_held_cache.write().await;
         let mut write_held_cache = self.write_held_cache.write().await;
@@ -180,6 +451,9 @@ impl LockClient {
         for key in write_held_cache.iter() {
             write_keys.push(key.to_string());
         }
+        for key in self.expired_cache.read().await.iter() {
+            write_keys.push(key.to_string());
+        }
         drop(read_held_cache);
         drop(write_held_cache);
         self.release_locks(read_keys, write_keys).await?;
@@ -195,12 +469,13 @@ impl LockClient {
 
         let read_held_cache = self.read_held_cache.read().await;
         let write_held_cache = self.write_held_cache.read().await;
+        let expired_cache = self.expired_cache.read().await;
 
         let mut read_bins: HashMap<usize, Vec<String>> = HashMap::new();
         let mut write_bins: HashMap<usize, Vec<String>> = HashMap::new();
         let mut bins: HashSet<usize> = HashSet::new();
         for key in read_keys.iter() {
-            if read_held_cache.contains(key) {
+            if read_held_cache.contains(key) || expired_cache.contains(key) {
                 let ind = self.get_hash_index(key) as usize;
                 if !read_bins.contains_key(&ind) {
                     read_bins.insert(ind, vec![]);
@@ -210,7 +485,7 @@ impl LockClient {
             }
         }
         for key in write_keys.iter() {
-            if write_held_cache.contains(key) {
+            if write_held_cache.contains(key) || expired_cache.contains(key) {
                 let ind = self.get_hash_index(key) as usize;
                 if !write_bins.contains_key(&ind) {
                     write_bins.insert(ind, vec![]);
@@ -221,6 +496,8 @@ impl LockClient {
         }
         drop(read_held_cache);
         drop(write_held_cache);
+        drop(expired_cache);
+        let mut first_err = None;
         for ind in bins {
             let mut purified_read_keys = vec![];
             let mut purified_write_keys = vec![];
@@ -232,12 +509,22 @@ impl LockClient {
             if write_keys_option.is_some() {
                 purified_write_keys = write_keys_option.unwrap().clone();
             }
-            self.release_locks_with_server_index(ind, purified_read_keys, purified_write_keys)
-                .await?;
+            // keep releasing on the other servers, the locks there are
+            // still held even if this server reclaimed its lease
+            let res = self
+                .release_locks_with_server_index(ind, purified_read_keys, purified_write_keys)
+                .await;
+            if res.is_err() && first_err.is_none() {
+                first_err = res.err();
+            }
         }
         let mut read_held_cache = self.read_held_cache.write().await;
         let mut write_held_cache = self.write_held_cache.write().await;
+        let mut expired_cache = self.expired_cache.write().await;
 
+        for key in read_keys.iter().chain(write_keys.iter()) {
+            expired_cache.remove(key);
+        }
         for key in read_keys.iter() {
             if read_held_cache.contains(key) {
                 read_held_cache.remove(key);
@@ -250,6 +537,17 @@ impl LockClient {
                 write_held_cache.remove(key);
             }
         }
-        Ok(())
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 }
+
+/// Lock servers keeping partition `ind`: its home server followed by the
+/// next ones, [LOCK_REPLICATION_FACTOR] servers at most.
+pub(crate) fn lock_chain(ind: usize, len: usize) -> Vec<usize> {
+    (0..LOCK_REPLICATION_FACTOR.min(len))
+        .map(|i| (ind + i) % len)
+        .collect()
+}
@@ -0,0 +1,829 @@
+use super::bin_client::update_channel_cache;
+use super::constants::LOCK_RING_VNODES;
+use super::constants::{
+    EXPIRED_LEASE_RETENTION, LEASE_EXPIRED_MESSAGE, LOCK_LEASE_INTERVAL, MIXED_PARTITIONS_MESSAGE,
+    NOT_PRIMARY_MESSAGE,
+};
+use super::hash_ring::HashRing;
+use super::lock_client::lock_chain;
+use crate::lockserver::lock_service_client::LockServiceClient;
+use crate::lockserver::lock_service_server::LockService;
+use crate::lockserver::{
+    AcquireLocksInfo, PartitionRequest, PartitionState, PingRequest, ReleaseLocksInfo, Success,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{Notify, RwLock};
+use tonic::transport::Channel;
+use tonic::{Request, Response, Status};
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Holders of a single lock key. A key can be shared by many readers or held
+/// by exactly one writer. A client holding the write lock may also read.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct LockEntry {
+    readers: HashSet<String>,
+    writer: Option<String>,
+}
+
+impl LockEntry {
+    fn can_read(&self, client_id: &str) -> bool {
+        match &self.writer {
+            Some(writer) => writer == client_id,
+            None => true,
+        }
+    }
+
+    fn can_write(&self, client_id: &str) -> bool {
+        self.can_read(client_id) && self.readers.iter().all(|reader| reader == client_id)
+    }
+
+    fn is_free(&self) -> bool {
+        self.writer.is_none() && self.readers.is_empty()
+    }
+}
+
+/// Keys held by one client, so that every lock of a client can be found
+/// without scanning the whole table.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ClientLocks {
+    read_keys: HashSet<String>,
+    write_keys: HashSet<String>,
+}
+
+/// The keys that the server `owner` of `peers` took over.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Handoff {
+    peers: Vec<String>,
+    owner: usize,
+    // ring of `peers`, built once instead of on every acquire. Not
+    // replicated, a received table builds it again.
+    #[serde(skip)]
+    ring: Option<HashRing>,
+}
+
+impl Handoff {
+    fn new(peers: Vec<String>, owner: usize, ring: HashRing) -> Self {
+        Self {
+            peers,
+            owner,
+            ring: Some(ring),
+        }
+    }
+
+    fn same_view(&self, other: &Handoff) -> bool {
+        self.peers == other.peers && self.owner == other.owner
+    }
+
+    fn owner_addr(&self) -> &str {
+        &self.peers[self.owner]
+    }
+
+    fn build_ring(&mut self) {
+        if self.ring.is_none() {
+            self.ring = Some(HashRing::new(&self.peers, LOCK_RING_VNODES));
+        }
+    }
+
+    fn covers(&self, key: &str) -> bool {
+        match &self.ring {
+            Some(ring) => ring.locate(key) == self.owner,
+            None => HashRing::new(&self.peers, LOCK_RING_VNODES).locate(key) == self.owner,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LockTable {
+    entries: HashMap<String, LockEntry>,
+    clients: HashMap<String, ClientLocks>,
+    /// lease deadline (unix millis) of every client holding a lock
+    leases: HashMap<String, u64>,
+    /// clients whose lease was reclaimed, with the time it was reclaimed.
+    /// Their next `Release` is answered with a lease expired error.
+    expired: HashMap<String, u64>,
+    /// transaction start time of the holders that acquired with a timestamp
+    timestamps: HashMap<String, u64>,
+    /// keys taken away from this table by other servers, not granted here
+    /// anymore
+    handed_off: Vec<Handoff>,
+    /// bumped on every change pushed to the backups, so that a backup never
+    /// replaces its state with an older one
+    version: u64,
+}
+
+impl LockTable {
+    fn grantable(&self, client_id: &str, read_keys: &[String], write_keys: &[String]) -> bool {
+        let readable = read_keys.iter().all(|key| match self.entries.get(key) {
+            Some(entry) => entry.can_read(client_id),
+            None => true,
+        });
+        let writable = write_keys.iter().all(|key| match self.entries.get(key) {
+            Some(entry) => entry.can_write(client_id),
+            None => true,
+        });
+        readable && writable
+    }
+
+    /// Wait-die check for a request that is not grantable: returns the first
+    /// conflicting key held by an older transaction than `(timestamp,
+    /// client_id)`, in which case the requester has to die instead of waiting.
+    /// Holders without a timestamp never make a requester die.
+    fn older_conflict(
+        &self,
+        client_id: &str,
+        timestamp: u64,
+        read_keys: &[String],
+        write_keys: &[String],
+    ) -> Option<String> {
+        let is_older = |holder: &String| match self.timestamps.get(holder) {
+            Some(holder_ts) => {
+                holder != client_id && (*holder_ts, holder.as_str()) < (timestamp, client_id)
+            }
+            None => false,
+        };
+        for key in read_keys.iter() {
+            if let Some(entry) = self.entries.get(key) {
+                if entry.writer.as_ref().is_some_and(is_older) {
+                    return Some(key.to_string());
+                }
+            }
+        }
+        for key in write_keys.iter() {
+            if let Some(entry) = self.entries.get(key) {
+                if entry.writer.as_ref().is_some_and(is_older) || entry.readers.iter().any(is_older)
+                {
+                    return Some(key.to_string());
+                }
+            }
+        }
+        None
+    }
+
+    fn grant(
+        &mut self,
+        client_id: &str,
+        timestamp: u64,
+        read_keys: &[String],
+        write_keys: &[String],
+    ) {
+        self.expired.remove(client_id);
+        self.renew_lease(client_id);
+        if timestamp != 0 {
+            let held_ts = self
+                .timestamps
+                .entry(client_id.to_string())
+                .or_insert(timestamp);
+            *held_ts = (*held_ts).min(timestamp);
+        }
+        let client_locks = self.clients.entry(client_id.to_string()).or_default();
+        for key in read_keys.iter() {
+            let entry = self.entries.entry(key.to_string()).or_default();
+            entry.readers.insert(client_id.to_string());
+            client_locks.read_keys.insert(key.to_string());
+        }
+        for key in write_keys.iter() {
+            let entry = self.entries.entry(key.to_string()).or_default();
+            entry.writer = Some(client_id.to_string());
+            client_locks.write_keys.insert(key.to_string());
+        }
+    }
+
+    fn release(&mut self, client_id: &str, read_keys: &[String], write_keys: &[String]) {
+        for key in read_keys.iter() {
+            if let Some(entry) = self.entries.get_mut(key) {
+                entry.readers.remove(client_id);
+                if entry.is_free() {
+                    self.entries.remove(key);
+                }
+            }
+        }
+        for key in write_keys.iter() {
+            if let Some(entry) = self.entries.get_mut(key) {
+                if entry.writer.as_deref() == Some(client_id) {
+                    entry.writer = None;
+                }
+                if entry.is_free() {
+                    self.entries.remove(key);
+                }
+            }
+        }
+        if let Some(client_locks) = self.clients.get_mut(client_id) {
+            for key in read_keys.iter() {
+                client_locks.read_keys.remove(key);
+            }
+            for key in write_keys.iter() {
+                client_locks.write_keys.remove(key);
+            }
+            if client_locks.read_keys.is_empty() && client_locks.write_keys.is_empty() {
+                self.clients.remove(client_id);
+                self.leases.remove(client_id);
+                self.timestamps.remove(client_id);
+            }
+        }
+    }
+
+    fn renew_lease(&mut self, client_id: &str) {
+        let deadline = now_millis() + LOCK_LEASE_INTERVAL * 1000;
+        self.leases.insert(client_id.to_string(), deadline);
+    }
+
+    /// Revokes every lock of the clients whose lease deadline passed. Returns
+    /// true when any lock was freed.
+    fn reap_expired_leases(&mut self) -> bool {
+        let now = now_millis();
+        let expired_clients = self
+            .leases
+            .iter()
+            .filter(|(_, deadline)| **deadline < now)
+            .map(|(client_id, _)| client_id.to_string())
+            .collect::<Vec<String>>();
+        for client_id in expired_clients.iter() {
+            if let Some(client_locks) = self.clients.get(client_id) {
+                let read_keys = client_locks.read_keys.iter().cloned().collect::<Vec<_>>();
+                let write_keys = client_locks.write_keys.iter().cloned().collect::<Vec<_>>();
+                self.release(client_id, &read_keys, &write_keys);
+            }
+            self.leases.remove(client_id);
+            self.timestamps.remove(client_id);
+            self.expired.insert(client_id.to_string(), now);
+        }
+        self.expired
+            .retain(|_, reclaimed_at| *reclaimed_at + EXPIRED_LEASE_RETENTION * 1000 >= now);
+        !expired_clients.is_empty()
+    }
+
+    /// Copies the locks of the keys accepted by `owns`, with the leases of
+    /// their holders.
+    fn extract(&self, owns: impl Fn(&str) -> bool) -> LockTable {
+        let mut extracted = LockTable::default();
+        for (key, entry) in self.entries.iter() {
+            if owns(key) {
+                extracted.entries.insert(key.to_string(), entry.clone());
+            }
+        }
+        for (client_id, client_locks) in self.clients.iter() {
+            let owned = ClientLocks {
+                read_keys: client_locks
+                    .read_keys
+                    .iter()
+                    .filter(|key| owns(key))
+                    .cloned()
+                    .collect(),
+                write_keys: client_locks
+                    .write_keys
+                    .iter()
+                    .filter(|key| owns(key))
+                    .cloned()
+                    .collect(),
+            };
+            if owned.read_keys.is_empty() && owned.write_keys.is_empty() {
+                continue;
+            }
+            extracted.clients.insert(client_id.to_string(), owned);
+            if let Some(deadline) = self.leases.get(client_id) {
+                extracted.leases.insert(client_id.to_string(), *deadline);
+            }
+            if let Some(timestamp) = self.timestamps.get(client_id) {
+                extracted
+                    .timestamps
+                    .insert(client_id.to_string(), *timestamp);
+            }
+        }
+        extracted
+    }
+
+    /// Drops the locks of the keys accepted by `owns`, and the leases of the
+    /// holders left without any lock.
+    fn remove(&mut self, owns: impl Fn(&str) -> bool) {
+        self.entries.retain(|key, _| !owns(key));
+        for client_locks in self.clients.values_mut() {
+            client_locks.read_keys.retain(|key| !owns(key));
+            client_locks.write_keys.retain(|key| !owns(key));
+        }
+        self.clients
+            .retain(|_, locks| !locks.read_keys.is_empty() || !locks.write_keys.is_empty());
+        let clients = &self.clients;
+        self.leases
+            .retain(|client_id, _| clients.contains_key(client_id));
+        self.timestamps
+            .retain(|client_id, _| clients.contains_key(client_id));
+    }
+
+    /// Returns the first of `keys` that was handed off to another server.
+    fn handed_off_key<'a>(&self, mut keys: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+        keys.find(|key| self.handed_off.iter().any(|handoff| handoff.covers(key)))
+    }
+
+    /// Records a handoff, unless it is already known. The handoffs it makes
+    /// obsolete are dropped: those of the same server under an older list of
+    /// peers, and those of servers that left the new list, whose keys are
+    /// handed off again to the servers owning them now.
+    fn hand_off(&mut self, handoff: Handoff) -> bool {
+        if self
+            .handed_off
+            .iter()
+            .any(|known| known.same_view(&handoff))
+        {
+            return false;
+        }
+        self.handed_off.retain(|known| {
+            known.owner_addr() != handoff.owner_addr()
+                && handoff.peers.iter().any(|peer| peer == known.owner_addr())
+        });
+        self.handed_off.push(handoff);
+        true
+    }
+
+    /// Builds the rings of the handoffs of a table received from a peer,
+    /// taking those this server already built from `previous`.
+    fn build_handoff_rings(&mut self, previous: Option<&LockTable>) {
+        for handoff in self.handed_off.iter_mut() {
+            if let Some(known) = previous.and_then(|previous| {
+                previous
+                    .handed_off
+                    .iter()
+                    .find(|known| known.same_view(handoff))
+            }) {
+                handoff.ring = known.ring.clone();
+            }
+            handoff.build_ring();
+        }
+    }
+
+    /// Adds the locks of `other` to this table.
+    fn merge(&mut self, other: LockTable) {
+        for (key, other_entry) in other.entries.into_iter() {
+            let entry = self.entries.entry(key).or_default();
+            entry.readers.extend(other_entry.readers);
+            if entry.writer.is_none() {
+                entry.writer = other_entry.writer;
+            }
+        }
+        for (client_id, other_locks) in other.clients.into_iter() {
+            let client_locks = self.clients.entry(client_id).or_default();
+            client_locks.read_keys.extend(other_locks.read_keys);
+            client_locks.write_keys.extend(other_locks.write_keys);
+        }
+        for (client_id, deadline) in other.leases.into_iter() {
+            let held = self.leases.entry(client_id).or_insert(deadline);
+            *held = (*held).max(deadline);
+        }
+        for (client_id, timestamp) in other.timestamps.into_iter() {
+            let held = self.timestamps.entry(client_id).or_insert(timestamp);
+            *held = (*held).min(timestamp);
+        }
+        self.expired.extend(other.expired);
+    }
+
+    fn bump_version(&mut self) -> String {
+        self.version += 1;
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// A reader/writer lock manager serving the `LockService` RPCs.
+///
+/// An `Acquire` call is granted all-or-nothing: the request waits until every
+/// requested key is available to its client and then takes all of them at
+/// once, so a single call never holds a partial set of keys.
+///
+/// Requests carrying a transaction timestamp follow wait-die: a request that
+/// conflicts with a lock held by an older transaction is aborted right away
+/// instead of waiting, so waits only go from older to younger transactions
+/// and can never form a cycle.
+///
+/// Locks are granted as leases of [LOCK_LEASE_INTERVAL] seconds per client.
+/// Holders renew them through `Heartbeat`; [LockServer::reap_expired_leases]
+/// reclaims the locks of clients that stopped renewing.
+///
+/// The lock keys are placed on the lock servers by a consistent hash ring,
+/// the keys owned by one server forming its partition. Every partition is
+/// kept on a chain of `LOCK_REPLICATION_FACTOR` servers starting at its home
+/// server. The first alive server of the chain is the primary: it
+/// serves the requests of the partition and pushes every change to the rest
+/// of the chain before answering, so the grants survive a primary crash.
+/// Other servers of the chain answer with [NOT_PRIMARY_MESSAGE].
+#[derive(Clone)]
+pub struct LockServer {
+    this: usize,
+    peers: Vec<String>,
+    ring: HashRing,
+    // lock tables of the partitions whose chain this server belongs to
+    lock_tables: Arc<RwLock<HashMap<usize, LockTable>>>,
+    // liveness of the peers as seen by the last ping round
+    peers_alive: Arc<RwLock<Vec<bool>>>,
+    released: Arc<Notify>,
+    channel_cache: Arc<RwLock<HashMap<String, Channel>>>,
+}
+
+impl LockServer {
+    pub fn new(this: usize, peers: Vec<String>) -> Self {
+        let mut lock_tables = HashMap::new();
+        for partition in 0..peers.len() {
+            if lock_chain(partition, peers.len()).contains(&this) {
+                lock_tables.insert(partition, LockTable::default());
+            }
+        }
+        Self {
+            this,
+            peers_alive: Arc::new(RwLock::new(vec![false; peers.len()])),
+            ring: HashRing::new(&peers, LOCK_RING_VNODES),
+            peers,
+            lock_tables: Arc::new(RwLock::new(lock_tables)),
+            released: Arc::new(Notify::new()),
+            channel_cache: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    async fn peer_client(&self, peer: usize) -> Option<LockServiceClient<Channel>> {
+        let chan = update_channel_cache(self.channel_cache.clone(), self.peers[peer].clone())
+            .await
+            .ok()?;
+        Some(LockServiceClient::new(chan))
+    }
+
+    async fn ping_peer(&self, peer: usize) -> bool {
+        let mut client = match self.peer_client(peer).await {
+            Some(client) => client,
+            None => return false,
+        };
+        client
+            .ping(PingRequest {
+                client_id: self.peers[self.this].to_string(),
+            })
+            .await
+            .is_ok()
+    }
+
+    /// Pings every peer and wakes up the waiting requests when the set of
+    /// alive peers changed, as the primary of their partition may have too.
+    pub async fn refresh_peers(&self) {
+        let mut alive = vec![false; self.peers.len()];
+        for (peer, peer_alive) in alive.iter_mut().enumerate() {
+            *peer_alive = peer == self.this || self.ping_peer(peer).await;
+        }
+        let mut peers_alive = self.peers_alive.write().await;
+        let changed = *peers_alive != alive;
+        *peers_alive = alive;
+        drop(peers_alive);
+        if changed {
+            self.released.notify_waiters();
+        }
+    }
+
+    /// Pulls the partitions of this server from the rest of their chains, so
+    /// that a restarted server does not come back with empty lock tables.
+    pub async fn recover(&self) {
+        let partitions = self
+            .lock_tables
+            .read()
+            .await
+            .keys()
+            .cloned()
+            .collect::<Vec<usize>>();
+        for partition in partitions {
+            for peer in lock_chain(partition, self.peers.len()) {
+                if peer == self.this {
+                    continue;
+                }
+                let mut client = match self.peer_client(peer).await {
+                    Some(client) => client,
+                    None => continue,
+                };
+                let res = client
+                    .fetch(PartitionRequest {
+                        partition: partition as u64,
+                        peers: vec![],
+                    })
+                    .await;
+                if let Ok(state) = res {
+                    if let Ok(mut lock_table) =
+                        serde_json::from_str::<LockTable>(&state.into_inner().state)
+                    {
+                        lock_table.build_handoff_rings(None);
+                        self.lock_tables.write().await.insert(partition, lock_table);
+                        break;
+                    }
+                }
+            }
+        }
+        self.take_over_moved_keys().await;
+    }
+
+    /// Handoff after the set of lock servers changed: asks every peer for the
+    /// locks it keeps on keys that this server owns on the new ring, and adds
+    /// them to the partition of this server. The peers hand those keys off:
+    /// they drop them and refuse to grant them afterwards. Lock servers are
+    /// restarted one at a time with the new list, so each one collects the
+    /// keys that moved to it from servers that may still run with the old
+    /// list.
+    async fn take_over_moved_keys(&self) {
+        let mut taken_over = LockTable::default();
+        for peer in 0..self.peers.len() {
+            if peer == self.this {
+                continue;
+            }
+            let mut client = match self.peer_client(peer).await {
+                Some(client) => client,
+                None => continue,
+            };
+            let res = client
+                .fetch(PartitionRequest {
+                    partition: self.this as u64,
+                    peers: self.peers.clone(),
+                })
+                .await;
+            if let Ok(state) = res {
+                if let Ok(lock_table) = serde_json::from_str(&state.into_inner().state) {
+                    taken_over.merge(lock_table);
+                }
+            }
+        }
+        if taken_over.entries.is_empty() {
+            return;
+        }
+        let mut lock_tables = self.lock_tables.write().await;
+        let lock_table = lock_tables.entry(self.this).or_default();
+        lock_table.merge(taken_over);
+        let state = lock_table.bump_version();
+        drop(lock_tables);
+        self.push_to_backups(self.this, state).await;
+    }
+
+    async fn is_primary_in_view(&self, partition: usize) -> bool {
+        let peers_alive = self.peers_alive.read().await;
+        for peer in lock_chain(partition, self.peers.len()) {
+            if peer == self.this {
+                return true;
+            }
+            if peers_alive[peer] {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Checks that this server is the primary of `partition`. The servers in
+    /// front of it in the chain are pinged again before refusing, so a
+    /// request does not bounce off a primary that just crashed.
+    async fn check_primary(&self, partition: usize) -> Result<(), Status> {
+        if self.is_primary_in_view(partition).await {
+            return Ok(());
+        }
+        let chain = lock_chain(partition, self.peers.len());
+        if !chain.contains(&self.this) {
+            return Err(Status::permission_denied(NOT_PRIMARY_MESSAGE));
+        }
+        for peer in chain {
+            if peer == self.this {
+                break;
+            }
+            let alive = self.ping_peer(peer).await;
+            self.peers_alive.write().await[peer] = alive;
+        }
+        match self.is_primary_in_view(partition).await {
+            true => Ok(()),
+            false => Err(Status::permission_denied(NOT_PRIMARY_MESSAGE)),
+        }
+    }
+
+    /// Returns the partition of the keys of one request, a request only
+    /// carries keys of a single partition.
+    fn partition_of(&self, read_keys: &[String], write_keys: &[String]) -> Option<usize> {
+        let mut partitions = read_keys
+            .iter()
+            .chain(write_keys.iter())
+            .map(|key| self.ring.locate(key));
+        let partition = partitions.next().unwrap_or(self.this);
+        match partitions.all(|other| other == partition) {
+            true => Some(partition),
+            false => None,
+        }
+    }
+
+    /// Pushes the state of `partition` to the other alive servers of its
+    /// chain. Unreachable backups are skipped, they pull the state back with
+    /// [LockServer::recover] when they restart.
+    async fn push_to_backups(&self, partition: usize, state: String) {
+        for peer in lock_chain(partition, self.peers.len()) {
+            if peer == self.this || !self.peers_alive.read().await[peer] {
+                continue;
+            }
+            let mut client = match self.peer_client(peer).await {
+                Some(client) => client,
+                None => continue,
+            };
+            let res = client
+                .replicate(PartitionState {
+                    partition: partition as u64,
+                    state: state.to_string(),
+                })
+                .await;
+            if res.is_err() {
+                self.peers_alive.write().await[peer] = false;
+            }
+        }
+    }
+
+    pub async fn reap_expired_leases(&self) {
+        let mut freed_states = vec![];
+        let mut lock_tables = self.lock_tables.write().await;
+        for (partition, lock_table) in lock_tables.iter_mut() {
+            if !self.is_primary_in_view(*partition).await {
+                continue;
+            }
+            if lock_table.reap_expired_leases() {
+                freed_states.push((*partition, lock_table.bump_version()));
+            }
+        }
+        drop(lock_tables);
+        if freed_states.is_empty() {
+            return;
+        }
+        for (partition, state) in freed_states {
+            self.push_to_backups(partition, state).await;
+        }
+        self.released.notify_waiters();
+    }
+}
+
+#[async_trait]
+impl LockService for LockServer {
+    async fn acquire(
+        &self,
+        request: Request<AcquireLocksInfo>,
+    ) -> Result<Response<Success>, Status> {
+        let info = request.into_inner();
+        let partition = match self.partition_of(&info.read_keys, &info.write_keys) {
+            Some(partition) => partition,
+            None => return Err(Status::invalid_argument(MIXED_PARTITIONS_MESSAGE)),
+        };
+        loop {
+            // register interest before checking, so a release between the
+            // check and the await still wakes this request up
+            let released = self.released.notified();
+            self.check_primary(partition).await?;
+            let mut lock_tables = self.lock_tables.write().await;
+            let lock_table = lock_tables.entry(partition).or_default();
+            let keys = info.read_keys.iter().chain(info.write_keys.iter());
+            if lock_table.handed_off_key(keys).is_some() {
+                return Err(Status::permission_denied(NOT_PRIMARY_MESSAGE));
+            }
+            if lock_table.grantable(&info.client_id, &info.read_keys, &info.write_keys) {
+                lock_table.grant(
+                    &info.client_id,
+                    info.timestamp,
+                    &info.read_keys,
+                    &info.write_keys,
+                );
+                let state = lock_table.bump_version();
+                drop(lock_tables);
+                self.push_to_backups(partition, state).await;
+                return Ok(Response::new(Success { flag: true }));
+            }
+            if info.timestamp != 0 {
+                if let Some(key) = lock_table.older_conflict(
+                    &info.client_id,
+                    info.timestamp,
+                    &info.read_keys,
+                    &info.write_keys,
+                ) {
+                    return Err(Status::aborted(key));
+                }
+            }
+            drop(lock_tables);
+            released.await;
+        }
+    }
+
+    async fn release(
+        &self,
+        request: Request<ReleaseLocksInfo>,
+    ) -> Result<Response<Success>, Status> {
+        let info = request.into_inner();
+        let partition = match self.partition_of(&info.read_keys, &info.write_keys) {
+            Some(partition) => partition,
+            None => return Err(Status::invalid_argument(MIXED_PARTITIONS_MESSAGE)),
+        };
+        self.check_primary(partition).await?;
+        let mut lock_tables = self.lock_tables.write().await;
+        let lock_table = lock_tables.entry(partition).or_default();
+        if lock_table.expired.remove(&info.client_id).is_some() {
+            let state = lock_table.bump_version();
+            drop(lock_tables);
+            self.push_to_backups(partition, state).await;
+            return Err(Status::failed_precondition(LEASE_EXPIRED_MESSAGE));
+        }
+        lock_table.release(&info.client_id, &info.read_keys, &info.write_keys);
+        let state = lock_table.bump_version();
+        drop(lock_tables);
+        self.push_to_backups(partition, state).await;
+        self.released.notify_waiters();
+        Ok(Response::new(Success { flag: true }))
+    }
+
+    /// Renews the lease of `client_id` in every partition it holds locks of
+    /// on this server. Backups are renewed by the heartbeats too, so a new
+    /// primary does not reclaim leases that were kept alive. Answers false
+    /// when the lease has already been reclaimed.
+    async fn heartbeat(
+        &self,
+        request: Request<AcquireLocksInfo>,
+    ) -> Result<Response<Success>, Status> {
+        let info = request.into_inner();
+        let mut lock_tables = self.lock_tables.write().await;
+        let mut flag = true;
+        for lock_table in lock_tables.values_mut() {
+            if lock_table.expired.contains_key(&info.client_id) {
+                flag = false;
+            } else if lock_table.leases.contains_key(&info.client_id) {
+                lock_table.renew_lease(&info.client_id);
+            }
+        }
+        Ok(Response::new(Success { flag }))
+    }
+
+    async fn ping(&self, _request: Request<PingRequest>) -> Result<Response<Success>, Status> {
+        Ok(Response::new(Success { flag: true }))
+    }
+
+    async fn replicate(
+        &self,
+        request: Request<PartitionState>,
+    ) -> Result<Response<Success>, Status> {
+        let info = request.into_inner();
+        let mut incoming: LockTable = match serde_json::from_str(&info.state) {
+            Ok(lock_table) => lock_table,
+            Err(_) => return Err(Status::invalid_argument("malformed partition state")),
+        };
+        let mut lock_tables = self.lock_tables.write().await;
+        let lock_table = lock_tables.entry(info.partition as usize).or_default();
+        if incoming.version > lock_table.version {
+            incoming.build_handoff_rings(Some(lock_table));
+            *lock_table = incoming;
+        }
+        Ok(Response::new(Success { flag: true }))
+    }
+
+    /// Returns the lock table of a partition, or with `peers` set, the locks
+    /// this server keeps on the keys that `partition` owns on the ring of
+    /// `peers`. When `peers` differs from the list of this server, those
+    /// keys moved away: they are removed from this server, which refuses to
+    /// grant them from then on, so a key is never granted by two servers.
+    async fn fetch(
+        &self,
+        request: Request<PartitionRequest>,
+    ) -> Result<Response<PartitionState>, Status> {
+        let info = request.into_inner();
+        if !info.peers.is_empty() {
+            let partition = info.partition as usize;
+            let ring = HashRing::new(&info.peers, LOCK_RING_VNODES);
+            let owns = |key: &str| ring.locate(key) == partition;
+            let handoff = Handoff::new(info.peers.clone(), partition, ring.clone());
+            let mut moved = LockTable::default();
+            let mut states = vec![];
+            let mut lock_tables = self.lock_tables.write().await;
+            for (partition, lock_table) in lock_tables.iter_mut() {
+                moved.merge(lock_table.extract(owns));
+                // backups drop the keys with the next state of their primary
+                if info.peers == self.peers
+                    || !self.is_primary_in_view(*partition).await
+                    || !lock_table.hand_off(handoff.clone())
+                {
+                    continue;
+                }
+                lock_table.remove(owns);
+                states.push((*partition, lock_table.bump_version()));
+            }
+            drop(lock_tables);
+            for (partition, state) in states {
+                self.push_to_backups(partition, state).await;
+            }
+            // requests waiting on the moved keys are refused from now on
+            self.released.notify_waiters();
+            return Ok(Response::new(PartitionState {
+                partition: info.partition,
+                state: serde_json::to_string(&moved).unwrap(),
+            }));
+        }
+        let lock_tables = self.lock_tables.read().await;
+        let state = match lock_tables.get(&(info.partition as usize)) {
+            Some(lock_table) => serde_json::to_string(lock_table).unwrap(),
+            None => return Err(Status::not_found("partition not kept on this server")),
+        };
+        Ok(Response::new(PartitionState {
+            partition: info.partition,
+            state,
+        }))
+    }
+}
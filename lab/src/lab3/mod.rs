@@ -167,6 +167,7 @@
 //! if they feel the need.
 //!
 //! Happy Lab 3. :-)
+mod anti_entropy;
 mod backend_server;
 mod bin_client;
 mod bin_prefix_adapter;
@@ -174,15 +175,32 @@ mod bin_replicator_adapter;
 mod client;
 mod constants;
 mod frontend_server;
+mod hash_ring;
+mod keeper_client;
+mod keeper_election;
 mod keeper_helper;
 mod keeper_migration_helper;
 mod keeper_rpc_receiver;
 mod keeper_server;
 mod lab;
 mod lock_client;
-pub use crate::lab3::bin_client::TxnClient;
+mod lock_server;
+mod snapshot;
+mod txn;
+pub use crate::lab3::bin_client::BinStorageClient;
+pub use crate::lab3::bin_client::{TxnClient, TxnState};
+pub use crate::lab3::bin_replicator_adapter::{Consistency, WriteReport};
+pub use crate::lab3::hash_ring::{in_arc, stable_hash, HashRing};
+pub use crate::lab3::keeper_client::{KeeperClient, KeeperStatus};
+pub use crate::lab3::keeper_election::{KeeperElection, LeaderRecord};
+pub use crate::lab3::keeper_helper::{MigrationCursor, MigrationThrottle};
+pub use crate::lab3::keeper_server::{MigrationLog, MigrationStep};
+pub use crate::lab3::lab::lock_servers_or_default;
 pub use crate::lab3::lab::new_bin_client;
 pub use crate::lab3::lab::new_bin_client_for_txn;
+pub use crate::lab3::lab::new_bin_client_with_locks;
+pub use crate::lab3::lab::new_bin_client_with_placement;
+pub use crate::lab3::lab::new_bin_client_with_replicas;
 pub use crate::lab3::lab::new_client;
 pub use crate::lab3::lab::new_front;
 pub use crate::lab3::lab::new_lock_client;
@@ -190,3 +208,7 @@ pub use crate::lab3::lab::new_lockserver_ping_test;
 pub use crate::lab3::lab::new_txn_client;
 pub use crate::lab3::lab::serve_back;
 pub use crate::lab3::lab::serve_keeper;
+pub use crate::lab3::lab::serve_lock_server;
+pub use crate::lab3::lock_client::LockClient;
+pub use crate::lab3::snapshot::Snapshot;
+pub use crate::lab3::txn::{Txn, TxnKeys, TxnMode};
@@ -0,0 +1,104 @@
+use super::bin_client::BinStorageClient;
+use super::constants::HISTORY_LOG_PREFIX;
+use tribbler::err::TribResult;
+use tribbler::storage::{self, KeyList, Pattern};
+
+/// A read-only view of every bin as of a logical clock. Reads replay the
+/// clock-stamped history of a key instead of taking locks, so a snapshot
+/// never blocks or is blocked by writers.
+pub struct Snapshot<'a> {
+    clock: u64,
+    bin_storage: &'a BinStorageClient,
+}
+
+impl<'a> Snapshot<'a> {
+    /// the logical clock this snapshot reads at.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// returns the value of `key` in `bin` as of the snapshot clock.
+    pub async fn get(&self, bin: &str, key: &str) -> TribResult<Option<String>> {
+        self.bin_storage
+            .replicator(bin)
+            .await
+            .get_at(key, self.clock)
+            .await
+    }
+
+    /// returns the list `key` in `bin` as of the snapshot clock.
+    pub async fn list_get(&self, bin: &str, key: &str) -> TribResult<storage::List> {
+        self.bin_storage
+            .replicator(bin)
+            .await
+            .list_get_at(key, self.clock)
+            .await
+    }
+}
+
+impl BinStorageClient {
+    /// takes a snapshot at the current clock. Every write that returned
+    /// before this call is visible in it, and none issued after.
+    pub async fn snapshot(&self) -> TribResult<Snapshot<'_>> {
+        let clock = self.sync_clocks().await?;
+        Ok(self.snapshot_at(clock))
+    }
+
+    /// opens a snapshot at a clock returned by an earlier [`Self::snapshot`].
+    pub fn snapshot_at(&self, clock: u64) -> Snapshot<'_> {
+        Snapshot {
+            clock,
+            bin_storage: self,
+        }
+    }
+
+    /// compacts the history of every key up to `safe_clock`, after which
+    /// snapshots older than `safe_clock` can no longer be read. Keepers run
+    /// it with the clock of their previous round, so a snapshot stays
+    /// readable for at least one `HISTORY_COMPACTION_INTERVAL`.
+    pub async fn compact_histories(&self, safe_clock: u64) -> TribResult<()> {
+        let mut history_keys = vec![];
+        for client in self.reachable_backs().await {
+            let raw_keys = match client
+                .list_keys(&Pattern {
+                    prefix: "".to_string(),
+                    suffix: "".to_string(),
+                })
+                .await
+            {
+                Ok(raw_keys) => raw_keys.0,
+                Err(_) => continue,
+            };
+            for raw_key in raw_keys {
+                // raw keys look like {bin}::HISTORY::LIST::{key}
+                let (bin, key) = match raw_key.split_once("::") {
+                    Some(split) => split,
+                    None => continue,
+                };
+                let entry = match key.strip_prefix(HISTORY_LOG_PREFIX) {
+                    Some(wrapped_key) => (bin.to_string(), wrapped_key.to_string()),
+                    None => continue,
+                };
+                // replicas keep the same histories
+                if !history_keys.contains(&entry) {
+                    history_keys.push(entry);
+                }
+            }
+        }
+        let mut first_err = None;
+        for (bin, wrapped_key) in history_keys {
+            let res = self
+                .replicator(&bin)
+                .await
+                .compact_history(&wrapped_key, safe_clock)
+                .await;
+            if let Err(err) = res {
+                first_err.get_or_insert(err);
+            }
+        }
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,518 @@
+use super::bin_client::{BinStorageClient, TxnClient, TxnState};
+use super::constants::{
+    LIST_LOG_PREFIX, LOCK_RETRY_BACKOFF_MILLIS, STR_LOG_PREFIX, TXN_MAX_RETRIES,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tribbler::err::{TribResult, TribblerError};
+use tribbler::storage::{KeyValue, List};
+
+/// The keys a transaction reads and writes, declared before it starts so
+/// that all of its locks can be taken up front.
+#[derive(Debug, Default, Clone)]
+pub struct TxnKeys {
+    read_keys_map: HashMap<String, Vec<String>>,
+    write_keys_map: HashMap<String, Vec<String>>,
+}
+
+impl TxnKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read_str(mut self, bin: &str, key: &str) -> Self {
+        Self::insert(&mut self.read_keys_map, bin, STR_LOG_PREFIX, key);
+        self
+    }
+
+    pub fn read_list(mut self, bin: &str, key: &str) -> Self {
+        Self::insert(&mut self.read_keys_map, bin, LIST_LOG_PREFIX, key);
+        self
+    }
+
+    pub fn write_str(mut self, bin: &str, key: &str) -> Self {
+        Self::insert(&mut self.write_keys_map, bin, STR_LOG_PREFIX, key);
+        self
+    }
+
+    pub fn write_list(mut self, bin: &str, key: &str) -> Self {
+        Self::insert(&mut self.write_keys_map, bin, LIST_LOG_PREFIX, key);
+        self
+    }
+
+    fn insert(keys_map: &mut HashMap<String, Vec<String>>, bin: &str, prefix: &str, key: &str) {
+        let keys = keys_map.entry(bin.to_string()).or_default();
+        let key = format!("{}{}", prefix, key);
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    fn declares(keys_map: &HashMap<String, Vec<String>>, bin: &str, key: &str) -> bool {
+        keys_map
+            .get(bin)
+            .is_some_and(|keys| keys.iter().any(|declared| declared == key))
+    }
+
+    fn check_read(&self, bin: &str, key: &str) -> TribResult<()> {
+        if Self::declares(&self.read_keys_map, bin, key)
+            || Self::declares(&self.write_keys_map, bin, key)
+        {
+            return Ok(());
+        }
+        Err(Box::new(TribblerError::UndeclaredTxnKey(format!(
+            "{}::{}",
+            bin, key
+        ))))
+    }
+
+    fn check_write(&self, bin: &str, key: &str) -> TribResult<()> {
+        if Self::declares(&self.write_keys_map, bin, key) {
+            return Ok(());
+        }
+        Err(Box::new(TribblerError::UndeclaredTxnKey(format!(
+            "{}::{}",
+            bin, key
+        ))))
+    }
+}
+
+/// How [TxnClient::run] isolates transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxnMode {
+    /// locks every declared key before the body runs and holds them until
+    /// commit.
+    Locking,
+    /// runs the body without locks, buffering its writes. At commit the keys
+    /// it touched are locked, the versions of the keys it read are checked,
+    /// and the buffered writes are applied only if none of them changed. A
+    /// read-only transaction only checks the versions, without locks.
+    Optimistic,
+}
+
+enum ListOp {
+    Append(String),
+    Remove(String),
+}
+
+/// Reads and buffered writes of one attempt of an optimistic transaction.
+#[derive(Default)]
+struct OptimisticState {
+    // version of each key when it was first read, by (bin, STR::/LIST:: key)
+    read_versions: HashMap<(String, String), u64>,
+    // buffered string writes by (bin, key), an empty value deletes the key
+    str_writes: HashMap<(String, String), String>,
+    // buffered list updates in the order they were made
+    list_ops: Vec<(String, String, ListOp)>,
+    done: bool,
+}
+
+/// The version of a key is the clock of the latest write recorded in its
+/// history.
+async fn version_of(
+    bin_storage: &BinStorageClient,
+    bin: &str,
+    wrapped_key: &str,
+) -> TribResult<u64> {
+    bin_storage
+        .replicator(bin)
+        .await
+        .latest_clock(wrapped_key)
+        .await
+}
+
+impl OptimisticState {
+    /// records the version of a key the first time it is read, before the
+    /// value is: a write landing in between then fails the validation
+    /// instead of going unnoticed.
+    async fn record_read(
+        &mut self,
+        bin_storage: &BinStorageClient,
+        bin: &str,
+        wrapped_key: String,
+    ) -> TribResult<()> {
+        let read = (bin.to_string(), wrapped_key);
+        if !self.read_versions.contains_key(&read) {
+            let version = version_of(bin_storage, bin, &read.1).await?;
+            self.read_versions.insert(read, version);
+        }
+        Ok(())
+    }
+
+    fn apply_list_ops(&self, bin: &str, key: &str, list: &mut Vec<String>) {
+        for (op_bin, op_key, op) in self.list_ops.iter() {
+            if op_bin != bin || op_key != key {
+                continue;
+            }
+            match op {
+                ListOp::Append(value) => list.push(value.to_string()),
+                ListOp::Remove(value) => list.retain(|element| element != value),
+            }
+        }
+    }
+
+    /// the read and write keys to lock at commit, in the layout of
+    /// [TxnClient::transaction_start].
+    fn keys_maps(&self) -> (HashMap<String, Vec<String>>, HashMap<String, Vec<String>>) {
+        let mut write_keys_map: HashMap<String, Vec<String>> = HashMap::new();
+        let mut written = |bin: &str, wrapped_key: String| {
+            let keys = write_keys_map.entry(bin.to_string()).or_default();
+            if !keys.contains(&wrapped_key) {
+                keys.push(wrapped_key);
+            }
+        };
+        for (bin, key) in self.str_writes.keys() {
+            written(bin, format!("{}{}", STR_LOG_PREFIX, key));
+        }
+        for (bin, key, _) in self.list_ops.iter() {
+            written(bin, format!("{}{}", LIST_LOG_PREFIX, key));
+        }
+        let mut read_keys_map: HashMap<String, Vec<String>> = HashMap::new();
+        for (bin, wrapped_key) in self.read_versions.keys() {
+            if TxnKeys::declares(&write_keys_map, bin, wrapped_key) {
+                continue;
+            }
+            read_keys_map
+                .entry(bin.to_string())
+                .or_default()
+                .push(wrapped_key.to_string());
+        }
+        (read_keys_map, write_keys_map)
+    }
+}
+
+/// Handle given to the body of [TxnClient::run]. Every access must stay
+/// within the declared [TxnKeys]. In [TxnMode::Locking] the locks are
+/// already held, so the calls go straight to the backends; in
+/// [TxnMode::Optimistic] reads are versioned and writes are buffered until
+/// commit. The handle stops working once its transaction is committed or
+/// aborted.
+#[derive(Clone)]
+pub struct Txn {
+    keys: Arc<TxnKeys>,
+    trans_key: String,
+    state: Arc<RwLock<TxnState>>,
+    bin_storage: Arc<BinStorageClient>,
+    optimistic: Option<Arc<Mutex<OptimisticState>>>,
+}
+
+impl Txn {
+    async fn check_active(&self) -> TribResult<()> {
+        if let Some(optimistic) = &self.optimistic {
+            if optimistic.lock().await.done {
+                return Err(Box::new(TribblerError::InvalidTxnState(
+                    "optimistic transaction already finished".to_string(),
+                )));
+            }
+            return Ok(());
+        }
+        let state = self.state.read().await;
+        if *state != TxnState::Active(self.trans_key.to_string()) {
+            return Err(Box::new(TribblerError::InvalidTxnState(format!(
+                "transaction {} is not active, client is {:?}",
+                self.trans_key, *state
+            ))));
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, bin: &str, key: &str) -> TribResult<Option<String>> {
+        self.check_active().await?;
+        let wrapped_key = format!("{}{}", STR_LOG_PREFIX, key);
+        self.keys.check_read(bin, &wrapped_key)?;
+        let storage = self.bin_storage.bin_with_locks(bin).await?;
+        let optimistic = match &self.optimistic {
+            Some(optimistic) => optimistic,
+            None => return storage.get(key).await,
+        };
+        let mut optimistic = optimistic.lock().await;
+        if let Some(value) = optimistic
+            .str_writes
+            .get(&(bin.to_string(), key.to_string()))
+        {
+            return Ok(Some(value.to_string()).filter(|value| !value.is_empty()));
+        }
+        optimistic
+            .record_read(&self.bin_storage, bin, wrapped_key)
+            .await?;
+        storage.get(key).await
+    }
+
+    pub async fn set(&self, bin: &str, key: &str, value: &str) -> TribResult<bool> {
+        self.check_active().await?;
+        self.keys
+            .check_write(bin, &format!("{}{}", STR_LOG_PREFIX, key))?;
+        if let Some(optimistic) = &self.optimistic {
+            optimistic
+                .lock()
+                .await
+                .str_writes
+                .insert((bin.to_string(), key.to_string()), value.to_string());
+            return Ok(true);
+        }
+        self.bin_storage
+            .bin_with_locks(bin)
+            .await?
+            .set(&KeyValue::new(key, value))
+            .await
+    }
+
+    pub async fn list_get(&self, bin: &str, key: &str) -> TribResult<List> {
+        self.check_active().await?;
+        let wrapped_key = format!("{}{}", LIST_LOG_PREFIX, key);
+        self.keys.check_read(bin, &wrapped_key)?;
+        let storage = self.bin_storage.bin_with_locks(bin).await?;
+        let optimistic = match &self.optimistic {
+            Some(optimistic) => optimistic,
+            None => return storage.list_get(key).await,
+        };
+        let mut optimistic = optimistic.lock().await;
+        optimistic
+            .record_read(&self.bin_storage, bin, wrapped_key)
+            .await?;
+        let mut list = storage.list_get(key).await?.0;
+        optimistic.apply_list_ops(bin, key, &mut list);
+        Ok(List(list))
+    }
+
+    pub async fn list_append(&self, bin: &str, key: &str, value: &str) -> TribResult<bool> {
+        self.check_active().await?;
+        self.keys
+            .check_write(bin, &format!("{}{}", LIST_LOG_PREFIX, key))?;
+        if let Some(optimistic) = &self.optimistic {
+            optimistic.lock().await.list_ops.push((
+                bin.to_string(),
+                key.to_string(),
+                ListOp::Append(value.to_string()),
+            ));
+            return Ok(true);
+        }
+        self.bin_storage
+            .bin_with_locks(bin)
+            .await?
+            .list_append(&KeyValue::new(key, value))
+            .await
+    }
+
+    pub async fn list_remove(&self, bin: &str, key: &str, value: &str) -> TribResult<u32> {
+        self.check_active().await?;
+        let wrapped_key = format!("{}{}", LIST_LOG_PREFIX, key);
+        self.keys.check_write(bin, &wrapped_key)?;
+        let storage = self.bin_storage.bin_with_locks(bin).await?;
+        let optimistic = match &self.optimistic {
+            Some(optimistic) => optimistic,
+            None => return storage.list_remove(&KeyValue::new(key, value)).await,
+        };
+        // the count depends on the current list, so it is a read as well
+        let mut optimistic = optimistic.lock().await;
+        optimistic
+            .record_read(&self.bin_storage, bin, wrapped_key)
+            .await?;
+        let mut list = storage.list_get(key).await?.0;
+        optimistic.apply_list_ops(bin, key, &mut list);
+        optimistic.list_ops.push((
+            bin.to_string(),
+            key.to_string(),
+            ListOp::Remove(value.to_string()),
+        ));
+        Ok(list.iter().filter(|element| *element == value).count() as u32)
+    }
+}
+
+fn is_retryable(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    matches!(
+        err.downcast_ref::<TribblerError>(),
+        Some(TribblerError::LockConflict(_)) | Some(TribblerError::TxnValidationFailed(_))
+    )
+}
+
+impl TxnClient {
+    pub fn update_mode(&mut self, mode: TxnMode) {
+        self.mode = mode;
+    }
+
+    /// runs `body` as a transaction over `keys` and commits it when `body`
+    /// returns `Ok`. In [TxnMode::Locking] the locks are taken before `body`
+    /// runs and its writes are rolled back from the undo logs when it returns
+    /// `Err`; in [TxnMode::Optimistic] nothing is written before commit. When
+    /// a lock is held by an older transaction, or an optimistic read turns
+    /// out stale, the whole transaction is retried, up to `TXN_MAX_RETRIES`
+    /// times, so `body` may run more than once.
+    pub async fn run<T, F, Fut>(&mut self, keys: TxnKeys, mut body: F) -> TribResult<T>
+    where
+        F: FnMut(Txn) -> Fut,
+        Fut: Future<Output = TribResult<T>>,
+    {
+        let keys = Arc::new(keys);
+        let mut retries = 0;
+        loop {
+            let res = match self.mode {
+                TxnMode::Locking => self.run_once(&keys, &mut body).await,
+                TxnMode::Optimistic => self.run_once_optimistic(&keys, &mut body).await,
+            };
+            match res {
+                Err(err) if is_retryable(err.as_ref()) && retries < TXN_MAX_RETRIES => {
+                    retries += 1;
+                    tokio::time::sleep(Duration::from_millis(
+                        LOCK_RETRY_BACKOFF_MILLIS * retries as u64,
+                    ))
+                    .await;
+                }
+                Err(err) => {
+                    *self.start_ts.write().await = 0;
+                    return Err(err);
+                }
+                Ok(value) => return Ok(value),
+            }
+        }
+    }
+
+    async fn run_once<T, F, Fut>(&mut self, keys: &Arc<TxnKeys>, body: &mut F) -> TribResult<T>
+    where
+        F: FnMut(Txn) -> Fut,
+        Fut: Future<Output = TribResult<T>>,
+    {
+        let trans_key = self
+            .transaction_start(keys.read_keys_map.clone(), keys.write_keys_map.clone())
+            .await?;
+        let txn = Txn {
+            keys: keys.clone(),
+            trans_key: trans_key.to_string(),
+            state: self.state.clone(),
+            bin_storage: self.bin_storage.clone(),
+            optimistic: None,
+        };
+        match body(txn).await {
+            Ok(value) => {
+                self.transaction_end(
+                    trans_key,
+                    keys.read_keys_map.clone(),
+                    keys.write_keys_map.clone(),
+                )
+                .await?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.abort_after(
+                    err,
+                    trans_key,
+                    keys.read_keys_map.clone(),
+                    keys.write_keys_map.clone(),
+                )
+                .await
+            }
+        }
+    }
+
+    async fn run_once_optimistic<T, F, Fut>(
+        &mut self,
+        keys: &Arc<TxnKeys>,
+        body: &mut F,
+    ) -> TribResult<T>
+    where
+        F: FnMut(Txn) -> Fut,
+        Fut: Future<Output = TribResult<T>>,
+    {
+        let optimistic = Arc::new(Mutex::new(OptimisticState::default()));
+        let txn = Txn {
+            keys: keys.clone(),
+            trans_key: "".to_string(),
+            state: self.state.clone(),
+            bin_storage: self.bin_storage.clone(),
+            optimistic: Some(optimistic.clone()),
+        };
+        let res = body(txn).await;
+        let mut optimistic = optimistic.lock().await;
+        optimistic.done = true;
+        let value = res?;
+        self.commit_optimistic(&optimistic).await?;
+        Ok(value)
+    }
+
+    /// locks the keys the transaction touched, checks that the keys it read
+    /// still have the version it saw, then applies the buffered writes. The
+    /// writes go through [TxnClient::transaction_start] so that they are
+    /// undo-logged like those of a locking transaction. A read-only
+    /// transaction has nothing to apply and is validated without locks.
+    async fn commit_optimistic(&mut self, optimistic: &OptimisticState) -> TribResult<()> {
+        let (read_keys_map, write_keys_map) = optimistic.keys_maps();
+        if write_keys_map.is_empty() {
+            self.validate_unlocked(optimistic).await?;
+            *self.start_ts.write().await = 0;
+            return Ok(());
+        }
+        let trans_key = self
+            .transaction_start(read_keys_map.clone(), write_keys_map.clone())
+            .await?;
+        let mut res = self.validate(optimistic).await;
+        if res.is_ok() {
+            res = self.apply(optimistic).await;
+        }
+        match res {
+            Ok(()) => {
+                self.transaction_end(trans_key, read_keys_map, write_keys_map)
+                    .await
+            }
+            Err(err) => {
+                self.abort_after(err, trans_key, read_keys_map, write_keys_map)
+                    .await
+            }
+        }
+    }
+
+    /// validates the reads of a read-only transaction without its locks.
+    /// The keys must not be under the undo log of a running transaction,
+    /// whose writes may still be rolled back, and then still have the version
+    /// seen by the reads: checked in that order, a write rolled back after
+    /// the reads changes the version before its log goes away. The values
+    /// read were then all current and committed at validation.
+    async fn validate_unlocked(&self, optimistic: &OptimisticState) -> TribResult<()> {
+        for (bin, wrapped_key) in optimistic.read_versions.keys() {
+            if self.bin_storage.has_open_txn_log(bin, wrapped_key).await? {
+                return Err(Box::new(TribblerError::TxnValidationFailed(format!(
+                    "{}::{}",
+                    bin, wrapped_key
+                ))));
+            }
+        }
+        self.validate(optimistic).await
+    }
+
+    async fn validate(&self, optimistic: &OptimisticState) -> TribResult<()> {
+        for ((bin, wrapped_key), version) in optimistic.read_versions.iter() {
+            let current = version_of(&self.bin_storage, bin, wrapped_key).await?;
+            if current != *version {
+                return Err(Box::new(TribblerError::TxnValidationFailed(format!(
+                    "{}::{}",
+                    bin, wrapped_key
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply(&self, optimistic: &OptimisticState) -> TribResult<()> {
+        for ((bin, key), value) in optimistic.str_writes.iter() {
+            self.bin_storage
+                .bin_with_locks(bin)
+                .await?
+                .set(&KeyValue::new(key, value))
+                .await?;
+        }
+        for (bin, key, op) in optimistic.list_ops.iter() {
+            let storage = self.bin_storage.bin_with_locks(bin).await?;
+            match op {
+                ListOp::Append(value) => {
+                    storage.list_append(&KeyValue::new(key, value)).await?;
+                }
+                ListOp::Remove(value) => {
+                    storage.list_remove(&KeyValue::new(key, value)).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -212,6 +212,10 @@
 //! - `cmd/src/bins_back.rs` is a bin storage service back-end launcher.
 //! - `cmd/src/bins_keep.rs` is a bin storage service keeper launcher.
 //! - `cmd/src/bins_mkcfg.rs` generates a bin storage configuration file.
+//! - `cmd/src/lock_server.rs` is a lock server launcher. The lock servers are
+//!   used by the bin storage clients, keepers and transactions.
+//! - `cmd/src/keeper_ctl.rs` shows the status of the keepers, and pauses,
+//!   resumes or forces their migrations.
 //! - `www/` contains the static files (html, css, js, etc.) for the web
 //!   front-end.
 //!
@@ -286,3 +290,4 @@ pub mod lab1;
 pub mod lab2;
 pub mod lab3;
 mod lockserver;
+pub mod test_helper;
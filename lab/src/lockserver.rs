@@ -8,6 +8,10 @@ pub struct AcquireLocksInfo {
     pub write_keys: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
     #[prost(bool, tag = "4")]
     pub is_keeper: bool,
+    /// start time of the requesting transaction, used for wait-die ordering.
+    /// 0 means the request never dies and always waits.
+    #[prost(uint64, tag = "5")]
+    pub timestamp: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ReleaseLocksInfo {
@@ -28,6 +32,25 @@ pub struct Success {
     #[prost(bool, tag = "1")]
     pub flag: bool,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PartitionRequest {
+    #[prost(uint64, tag = "1")]
+    pub partition: u64,
+    /// when set, asks for the locks whose keys belong to peers\[partition\] on
+    /// the hash ring of these peers, taken from every partition kept by the
+    /// responder. Used to hand locks over after the set of lock servers
+    /// changed.
+    #[prost(string, repeated, tag = "2")]
+    pub peers: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PartitionState {
+    #[prost(uint64, tag = "1")]
+    pub partition: u64,
+    /// json encoded lock table of the partition
+    #[prost(string, tag = "2")]
+    pub state: ::prost::alloc::string::String,
+}
 #[doc = r" Generated client implementations."]
 pub mod lock_service_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -144,6 +167,36 @@ pub mod lock_service_client {
             let path = http::uri::PathAndQuery::from_static("/lockserver.LockService/Ping");
             self.inner.unary(request.into_request(), path, codec).await
         }
+        #[doc = " pushes the lock state of a partition from its primary to the backups"]
+        pub async fn replicate(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PartitionState>,
+        ) -> Result<tonic::Response<super::Success>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/lockserver.LockService/Replicate");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        #[doc = " pulls the lock state of a partition, used by a restarted lock server"]
+        pub async fn fetch(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PartitionRequest>,
+        ) -> Result<tonic::Response<super::PartitionState>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/lockserver.LockService/Fetch");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
     }
 }
 #[doc = r" Generated server implementations."]
@@ -169,6 +222,16 @@ pub mod lock_service_server {
             &self,
             request: tonic::Request<super::PingRequest>,
         ) -> Result<tonic::Response<super::Success>, tonic::Status>;
+        #[doc = " pushes the lock state of a partition from its primary to the backups"]
+        async fn replicate(
+            &self,
+            request: tonic::Request<super::PartitionState>,
+        ) -> Result<tonic::Response<super::Success>, tonic::Status>;
+        #[doc = " pulls the lock state of a partition, used by a restarted lock server"]
+        async fn fetch(
+            &self,
+            request: tonic::Request<super::PartitionRequest>,
+        ) -> Result<tonic::Response<super::PartitionState>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct LockServiceServer<T: LockService> {
@@ -333,6 +396,68 @@ pub mod lock_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/lockserver.LockService/Replicate" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReplicateSvc<T: LockService>(pub Arc<T>);
+                    impl<T: LockService> tonic::server::UnaryService<super::PartitionState> for ReplicateSvc<T> {
+                        type Response = super::Success;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PartitionState>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).replicate(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ReplicateSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/lockserver.LockService/Fetch" => {
+                    #[allow(non_camel_case_types)]
+                    struct FetchSvc<T: LockService>(pub Arc<T>);
+                    impl<T: LockService> tonic::server::UnaryService<super::PartitionRequest> for FetchSvc<T> {
+                        type Response = super::PartitionState;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PartitionRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).fetch(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = FetchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
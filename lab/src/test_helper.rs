@@ -0,0 +1,80 @@
+//! Setup shared by the integration tests that serve their own lock servers
+//! and backends in process.
+use super::lab3;
+use std::time::Duration;
+use tokio::sync::mpsc::Sender as MpscSender;
+use tribbler::{
+    config::BackConfig,
+    err::TribResult,
+    storage::{BinStorage, KeyString, KeyValue, MemStorage, Storage},
+};
+
+/// serves a lock server at `addr` in the group of `peers`. Send on the
+/// returned channel to shut it down.
+pub fn spawn_lock_server(addr: &str, peers: &[String]) -> MpscSender<()> {
+    let (shut_tx, shut_rx) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(lab3::serve_lock_server(
+        addr.to_string(),
+        peers.to_vec(),
+        None,
+        Some(shut_rx),
+    ));
+    shut_tx
+}
+
+/// serves an empty backend at `addr`, already marked valid when `valid` is
+/// set so keepers and clients use it without a migration first.
+pub async fn spawn_back(addr: &str, valid: bool) -> TribResult<MpscSender<()>> {
+    let storage = MemStorage::default();
+    if valid {
+        storage
+            .set(&KeyValue::new("VALIDATION-BIT", "true"))
+            .await?;
+    }
+    let (shut_tx, shut_rx) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(lab3::serve_back(BackConfig {
+        addr: addr.to_string(),
+        storage: Box::new(storage),
+        ready: None,
+        shutdown: Some(shut_rx),
+    }));
+    Ok(shut_tx)
+}
+
+/// serves a lock server alone at `lock_addr` and a valid backend at each of
+/// `back_addrs`, and waits for them to listen. The lock server's shutdown
+/// channel comes first.
+pub async fn setup(lock_addr: &str, back_addrs: &[String]) -> TribResult<Vec<MpscSender<()>>> {
+    let mut shut_txs = vec![spawn_lock_server(lock_addr, &[lock_addr.to_string()])];
+    for back_addr in back_addrs.iter() {
+        shut_txs.push(spawn_back(back_addr, true).await?);
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    Ok(shut_txs)
+}
+
+/// drops the latest history record of `history_key` on `raw`, as if the
+/// backend missed the write that made it.
+pub async fn drop_latest_record(raw: &dyn Storage, history_key: &str) -> TribResult<()> {
+    let history = raw.list_get(history_key).await?.0;
+    raw.list_remove(&KeyValue::new(history_key, history.last().unwrap()))
+        .await?;
+    Ok(())
+}
+
+/// the backend status the keepers last recorded in their store.
+pub async fn recorded_back_status(bin_client: &dyn BinStorage) -> TribResult<Vec<bool>> {
+    let keeper_store = bin_client.bin("KEEPER-STORE").await?;
+    Ok(serde_json::from_str(
+        &keeper_store
+            .get("BACK-STATUS")
+            .await?
+            .ok_or("no back status recorded")?,
+    )?)
+}
+
+pub async fn shutdown(shut_txs: Vec<MpscSender<()>>) {
+    for shut_tx in shut_txs {
+        let _ = shut_tx.send(()).await;
+    }
+}
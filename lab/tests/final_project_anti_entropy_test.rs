@@ -0,0 +1,89 @@
+use lab::lab3::{self, new_bin_client_for_txn};
+use lab::test_helper;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    config::BackConfig,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyList, KeyString, KeyValue, MemStorage, Storage},
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_anti_entropy_syncs_differing_keys() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2150".to_string();
+    let back_addrs = vec!["127.0.0.1:2151".to_string(), "127.0.0.1:2152".to_string()];
+    let shut_txs = test_helper::setup(&lock_addr, &back_addrs).await?;
+
+    let bin_storage = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    for name in ["alice", "bob", "carol"] {
+        let bin = bin_storage.bin(name).await?;
+        bin.set(&KeyValue::new("name", "old")).await?;
+        bin.set(&KeyValue::new("name", "new")).await?;
+        bin.list_append(&KeyValue::new("tribs", "a")).await?;
+        bin.list_append(&KeyValue::new("tribs", "b")).await?;
+    }
+    // replicas that agree are left alone
+    assert_eq!(bin_storage.anti_entropy().await?, 0);
+
+    // the second backend missed the last writes of alice and bob
+    let raw = lab3::new_client(&back_addrs[1]).await?;
+    test_helper::drop_latest_record(raw.as_ref(), "alice::HISTORY::STR::name").await?;
+    raw.set(&KeyValue::new("alice::STR::name", "old")).await?;
+    test_helper::drop_latest_record(raw.as_ref(), "bob::HISTORY::LIST::tribs").await?;
+    raw.list_remove(&KeyValue::new("bob::LIST::tribs", "b"))
+        .await?;
+
+    assert_eq!(bin_storage.anti_entropy().await?, 2);
+    assert_eq!(raw.get("alice::STR::name").await?, Some("new".to_string()));
+    assert_eq!(raw.list_get("bob::LIST::tribs").await?.0, vec!["a", "b"]);
+    let healthy = lab3::new_client(&back_addrs[0]).await?;
+    for history_key in ["alice::HISTORY::STR::name", "bob::HISTORY::LIST::tribs"] {
+        let mut expected = healthy.list_get(history_key).await?.0;
+        let mut repaired = raw.list_get(history_key).await?.0;
+        expected.sort();
+        repaired.sort();
+        assert_eq!(repaired, expected);
+    }
+
+    // once repaired, the digests agree again
+    assert_eq!(bin_storage.anti_entropy().await?, 0);
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_anti_entropy_converges_keys_without_history() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2169".to_string();
+    let back_addrs = vec!["127.0.0.1:2170".to_string(), "127.0.0.1:2171".to_string()];
+    let shut_txs = test_helper::setup(&lock_addr, &back_addrs).await?;
+
+    let bin_storage = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    let bin = bin_storage.bin("carol").await?;
+    bin.list_append(&KeyValue::new("tribs", "a")).await?;
+    bin.list_append(&KeyValue::new("tribs", "b")).await?;
+
+    // the second backend holds the same elements in another order, and a key
+    // no history explains
+    let raw = lab3::new_client(&back_addrs[1]).await?;
+    raw.list_remove(&KeyValue::new("carol::LIST::tribs", "a"))
+        .await?;
+    raw.list_append(&KeyValue::new("carol::LIST::tribs", "a"))
+        .await?;
+    raw.set(&KeyValue::new("dave::STR::name", "stray")).await?;
+
+    assert_eq!(bin_storage.anti_entropy().await?, 2);
+    let healthy = lab3::new_client(&back_addrs[0]).await?;
+    assert_eq!(
+        raw.list_get("carol::LIST::tribs").await?.0,
+        healthy.list_get("carol::LIST::tribs").await?.0
+    );
+    assert_eq!(
+        raw.get("dave::STR::name").await?,
+        healthy.get("dave::STR::name").await?
+    );
+    assert_eq!(bin_storage.anti_entropy().await?, 0);
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
@@ -9,7 +9,7 @@ use std::{
 use rand::Rng;
 use lab::{self, lab2, lab1, lab3};
 use tokio::{sync::mpsc::Sender as MpscSender, time};
-use tribbler::{config::KeeperConfig, storage::BinStorage};
+use tribbler::{config::{KeeperConfig, DEFAULT_MIGRATION_CONCURRENCY, DEFAULT_REPLICAS, DEFAULT_VNODES}, storage::BinStorage};
 #[allow(unused_imports)]
 use tribbler::{
     self,
@@ -267,6 +267,11 @@ async fn setup_single_keeper(i: usize, keepers: Vec<String>, backs: Vec<String>)
     let kfg = KeeperConfig {
         backs: backs.clone(),
         addrs: keepers.clone(),
+        locks: vec![],
+        replicas: DEFAULT_REPLICAS,
+        vnodes: DEFAULT_VNODES,
+        migration_rate: 0,
+        migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
         this: i,
         id: rand::thread_rng().gen_range(0..300),
         ready: None,
@@ -295,6 +300,11 @@ async fn setup_single_keeper2(i: usize, keepers: Vec<String>, backs: Vec<String>
     let kfg = KeeperConfig {
         backs: backs.clone(),
         addrs: keepers.clone(),
+        locks: vec![],
+        replicas: DEFAULT_REPLICAS,
+        vnodes: DEFAULT_VNODES,
+        migration_rate: 0,
+        migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
         this: i,
         id: rand::thread_rng().gen_range(0..300),
         ready: None,
@@ -460,30 +470,31 @@ async fn test_random_operations() -> TribResult<()> {
         keeper_interval.tick().await;
         match keeper_cp {
             0 => {},
+            // revive a keeper before killing the next, so a majority of the
+            // three keepers is always up to elect a leader
             1 => {
-                let keeper_to_die = 0;
-                let _ = keeper_shut_vec3_keep[keeper_to_die].as_ref().unwrap().send(()).await;
-                keeper_shut_vec3_keep[keeper_to_die] = None;
-                println!("KEEPER {} DIE!!!!!!!", keeper_to_die);
-            },
-            2 => {
                 let keeper_to_revive = 2;
                 let shut_tx = setup_single_keeper(keeper_to_revive, keeper_addresses3_keep.clone(), backend_addresses3_keep.clone()).await;
                 keeper_shut_vec3_keep[keeper_to_revive] = Some(shut_tx);
                 println!("keeper {} REVIVE!!!!!!!", keeper_to_revive);
             },
-            3 => {
-                let keeper_to_die = 1;
+            2 => {
+                let keeper_to_die = 0;
                 let _ = keeper_shut_vec3_keep[keeper_to_die].as_ref().unwrap().send(()).await;
                 keeper_shut_vec3_keep[keeper_to_die] = None;
                 println!("KEEPER {} DIE!!!!!!!", keeper_to_die);
             },
-            4 => {
+            3 => {
                 let keeper_to_revive = 0;
                 let shut_tx = setup_single_keeper(keeper_to_revive, keeper_addresses3_keep.clone(), backend_addresses3_keep.clone()).await;
                 keeper_shut_vec3_keep[keeper_to_revive] = Some(shut_tx);
                 println!("keeper {} REVIVE!!!!!!!", keeper_to_revive);
-                
+            },
+            4 => {
+                let keeper_to_die = 1;
+                let _ = keeper_shut_vec3_keep[keeper_to_die].as_ref().unwrap().send(()).await;
+                keeper_shut_vec3_keep[keeper_to_die] = None;
+                println!("KEEPER {} DIE!!!!!!!", keeper_to_die);
             },
             _ => {
                 continue;
@@ -0,0 +1,42 @@
+use lab::big_fucking_tester::{BigFuckingTester, BigFuckingTesterTrait};
+use lab::lab3::{self, LeaderRecord};
+use std::time::Duration;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyString},
+};
+
+async fn leader_record(bin_client: &dyn BinStorage) -> TribResult<Option<LeaderRecord>> {
+    let keeper_store = bin_client.bin("KEEPER-STORE").await?;
+    match keeper_store.get("KEEPER-LEADER").await? {
+        Some(record) => Ok(Some(serde_json::from_str(&record)?)),
+        None => Ok(None),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_keepers_elect_one_leader_per_term() -> TribResult<()> {
+    let mut bft = BigFuckingTester::new(22, 2, vec![0, 1], 3, vec![0, 1, 2]).await;
+    let bin_client =
+        lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone())
+            .await?;
+    tokio::time::sleep(Duration::from_secs(10)).await;
+    let first = leader_record(bin_client.as_ref()).await?.unwrap();
+
+    // the leader dies, the two others elect a new one in a later term
+    bft.keeper_node_leave(first.leader).await;
+    tokio::time::sleep(Duration::from_secs(20)).await;
+    let second = leader_record(bin_client.as_ref()).await?.unwrap();
+    assert!(second.term > first.term, "{:?} after {:?}", second, first);
+    assert_ne!(second.leader, first.leader);
+
+    // the last keeper alone is no majority and never takes over
+    bft.keeper_node_leave(second.leader).await;
+    tokio::time::sleep(Duration::from_secs(20)).await;
+    assert_eq!(leader_record(bin_client.as_ref()).await?, Some(second));
+
+    bft.cleanup().await;
+    Ok(())
+}
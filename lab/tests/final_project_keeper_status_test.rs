@@ -0,0 +1,194 @@
+use lab::big_fucking_tester::{BigFuckingTester, BigFuckingTesterTrait};
+use lab::lab3::{self, KeeperClient, MigrationStep};
+use lab::test_helper;
+use std::time::{Duration, Instant};
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    config::{KeeperConfig, DEFAULT_VNODES},
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyString, KeyValue},
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_keeper_status_and_paused_migration() -> TribResult<()> {
+    let mut bft = BigFuckingTester::new(23, 3, vec![0, 1, 2], 1, vec![0]).await;
+    let bin_client =
+        lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone())
+            .await?;
+    let keeper = KeeperClient::new(&bft.keeper_addresses[0], None);
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    let status = keeper.status().await?;
+    assert_eq!(status.this, 0);
+    assert_eq!(status.leader, Some(0));
+    assert!(status.term > 0);
+    assert_eq!(status.backs, vec![true, true, true]);
+    assert_eq!(status.migration, None);
+    assert!(!status.paused);
+    assert!(status.last_broadcast > 0);
+
+    // a paused keeper sees the backend leave but does not migrate
+    keeper.pause_migration().await?;
+    bft.back_node_leave(2).await;
+    tokio::time::sleep(Duration::from_secs(10)).await;
+    let status = keeper.status().await?;
+    assert!(status.paused);
+    assert_eq!(status.backs, vec![true, true, false]);
+    assert_eq!(
+        test_helper::recorded_back_status(bin_client.as_ref()).await?,
+        vec![true, true, true]
+    );
+
+    // once resumed, a forced rescan migrates without waiting for the next
+    // round
+    keeper.resume_migration().await?;
+    keeper.force_rescan().await?;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let status = keeper.status().await?;
+    assert!(!status.paused);
+    assert_eq!(
+        status.migration.map(|log| log.steps),
+        Some(vec![MigrationStep {
+            back_ids: vec![2],
+            leave: true
+        }])
+    );
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    assert_eq!(keeper.status().await?.migration, None);
+    assert_eq!(
+        test_helper::recorded_back_status(bin_client.as_ref()).await?,
+        vec![true, true, false]
+    );
+
+    bft.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_pause_through_any_keeper_stops_migration_at_checkpoint() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2172".to_string();
+    let back_addrs: Vec<String> = (2173..2176)
+        .map(|port| format!("127.0.0.1:{}", port))
+        .collect();
+    let keeper_addrs = vec!["127.0.0.1:2176".to_string(), "127.0.0.1:2177".to_string()];
+    let mut shut_txs = vec![test_helper::spawn_lock_server(
+        &lock_addr,
+        std::slice::from_ref(&lock_addr),
+    )];
+    for back_addr in back_addrs.iter().take(2) {
+        shut_txs.push(test_helper::spawn_back(back_addr, true).await?);
+    }
+    let mut keeper_shut_txs = vec![];
+    for this in 0..keeper_addrs.len() {
+        let (keeper_shut_tx, keeper_shut_rx) = tokio::sync::mpsc::channel(1);
+        keeper_shut_txs.push(keeper_shut_tx);
+        tokio::spawn(lab3::serve_keeper(KeeperConfig {
+            backs: back_addrs.clone(),
+            addrs: keeper_addrs.clone(),
+            locks: vec![lock_addr.clone()],
+            replicas: 2,
+            vnodes: DEFAULT_VNODES,
+            migration_rate: 10,
+            migration_concurrency: 1,
+            this,
+            id: this as u128 + 1,
+            ready: None,
+            shutdown: Some(keeper_shut_rx),
+        }));
+    }
+    let bin_client =
+        lab3::new_bin_client_with_replicas(back_addrs.clone(), vec![lock_addr.clone()], 2).await?;
+    // the leader records the backends it starts from before any joins
+    let deadline = Instant::now() + Duration::from_secs(60);
+    while test_helper::recorded_back_status(bin_client.as_ref())
+        .await
+        .ok()
+        != Some(vec![true, true, false])
+    {
+        assert!(
+            Instant::now() < deadline,
+            "no leader recorded the back status"
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    for i in 0..60 {
+        let bin = bin_client.bin(&format!("user{}", i)).await?;
+        bin.set(&KeyValue::new("name", &format!("name{}", i)))
+            .await?;
+    }
+    // only the leader reports its status, the follower names the leader
+    let leader = match KeeperClient::new(&keeper_addrs[0], None).status().await {
+        Ok(status) => status.this,
+        Err(e) => match e.downcast_ref::<TribblerError>() {
+            Some(TribblerError::NotLeader(Some(leader))) => *leader,
+            _ => return Err(e),
+        },
+    };
+    let leader_keeper = KeeperClient::new(&keeper_addrs[leader], None);
+    let follower_keeper = KeeperClient::new(&keeper_addrs[1 - leader], None);
+    for res in [
+        follower_keeper.status().await.map(|_| ()),
+        follower_keeper.force_rescan().await,
+    ] {
+        match res {
+            Err(e) => assert!(
+                matches!(
+                    e.downcast_ref::<TribblerError>(),
+                    Some(TribblerError::NotLeader(Some(l))) if *l == leader
+                ),
+                "{}",
+                e
+            ),
+            Ok(_) => panic!("the follower answered as the leader"),
+        }
+    }
+
+    // the joining backend takes over part of the bins, slowly enough to pause
+    // the migration halfway through the follower
+    shut_txs.push(test_helper::spawn_back(&back_addrs[2], false).await?);
+    let deadline = Instant::now() + Duration::from_secs(60);
+    loop {
+        assert!(Instant::now() < deadline, "migration never made progress");
+        if let Some(log) = leader_keeper.status().await?.migration {
+            if log.done > 0 {
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    follower_keeper.pause_migration().await?;
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let paused = leader_keeper.status().await?;
+    assert!(paused.paused);
+    let log = paused
+        .migration
+        .expect("the paused migration is still shown");
+    assert!(log.done < log.total);
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(leader_keeper.status().await?.migration, Some(log));
+    let keeper_store = bin_client.bin("KEEPER-STORE").await?;
+    assert!(keeper_store.get("MIGRATION-LOG").await?.is_some());
+
+    // resumed through the follower, the leader carries on from the checkpoint
+    follower_keeper.resume_migration().await?;
+    leader_keeper.force_rescan().await?;
+    let deadline = Instant::now() + Duration::from_secs(60);
+    while leader_keeper.status().await?.migration.is_some() {
+        assert!(Instant::now() < deadline, "migration never finished");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    assert!(!leader_keeper.status().await?.paused);
+    assert_eq!(
+        test_helper::recorded_back_status(bin_client.as_ref()).await?,
+        vec![true, true, true]
+    );
+    for i in 0..60 {
+        let bin = bin_client.bin(&format!("user{}", i)).await?;
+        assert_eq!(bin.get("name").await?, Some(format!("name{}", i)));
+    }
+
+    test_helper::shutdown(keeper_shut_txs).await;
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
@@ -0,0 +1,386 @@
+use lab::{
+    self,
+    lab3::{self, HashRing, LockClient},
+    test_helper,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc::Sender as MpscSender;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    err::{TribResult, TribblerError},
+};
+
+async fn setup_lock_server(addr: &str) -> MpscSender<()> {
+    let shut_tx = test_helper::spawn_lock_server(addr, &[addr.to_string()]);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shut_tx
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_readers_share() -> TribResult<()> {
+    let addr = "127.0.0.1:2101";
+    let shut_tx = setup_lock_server(addr).await;
+    let alice = LockClient::new(vec![addr.to_string()], false);
+    let bob = LockClient::new(vec![addr.to_string()], false);
+    alice.acquire_locks(vec!["k".to_string()], vec![]).await?;
+    let shared = tokio::time::timeout(
+        Duration::from_secs(2),
+        bob.acquire_locks(vec!["k".to_string()], vec![]),
+    )
+    .await;
+    assert!(shared.is_ok(), "readers should not block each other");
+    shared.unwrap()?;
+    alice.release_locks(vec!["k".to_string()], vec![]).await?;
+    bob.release_locks(vec!["k".to_string()], vec![]).await?;
+    let _ = shut_tx.send(()).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_writer_blocks_until_release() -> TribResult<()> {
+    let addr = "127.0.0.1:2102";
+    let shut_tx = setup_lock_server(addr).await;
+    let alice = Arc::new(LockClient::new(vec![addr.to_string()], false));
+    let bob = Arc::new(LockClient::new(vec![addr.to_string()], false));
+    alice.acquire_locks(vec![], vec!["k".to_string()]).await?;
+    let bob_cp = bob.clone();
+    let waiter =
+        tokio::spawn(async move { bob_cp.acquire_locks(vec!["k".to_string()], vec![]).await });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(
+        !waiter.is_finished(),
+        "reader acquired a key held by a writer"
+    );
+    alice.release_locks(vec![], vec!["k".to_string()]).await?;
+    let granted = tokio::time::timeout(Duration::from_secs(2), waiter).await;
+    assert!(granted.is_ok(), "reader was not woken up by the release");
+    granted.unwrap()?.unwrap();
+    bob.release_locks(vec!["k".to_string()], vec![]).await?;
+    let _ = shut_tx.send(()).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_acquire_is_all_or_nothing() -> TribResult<()> {
+    let addr = "127.0.0.1:2103";
+    let shut_tx = setup_lock_server(addr).await;
+    let alice = Arc::new(LockClient::new(vec![addr.to_string()], false));
+    let bob = Arc::new(LockClient::new(vec![addr.to_string()], false));
+    let carol = LockClient::new(vec![addr.to_string()], false);
+    alice.acquire_locks(vec!["k2".to_string()], vec![]).await?;
+    let bob_cp = bob.clone();
+    let waiter = tokio::spawn(async move {
+        bob_cp
+            .acquire_locks(vec![], vec!["k1".to_string(), "k2".to_string()])
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    // bob is waiting for k2, so k1 must still be free
+    let free = tokio::time::timeout(
+        Duration::from_secs(2),
+        carol.acquire_locks(vec![], vec!["k1".to_string()]),
+    )
+    .await;
+    assert!(free.is_ok(), "a waiting request held part of its keys");
+    free.unwrap()?;
+    carol.release_locks(vec![], vec!["k1".to_string()]).await?;
+    alice.release_locks(vec!["k2".to_string()], vec![]).await?;
+    let granted = tokio::time::timeout(Duration::from_secs(2), waiter).await;
+    assert!(granted.is_ok(), "writer was not woken up by the releases");
+    granted.unwrap()?.unwrap();
+    let _ = shut_tx.send(()).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_lease_expires_without_heartbeat() -> TribResult<()> {
+    let addr = "127.0.0.1:2104";
+    let shut_tx = setup_lock_server(addr).await;
+    let alice = LockClient::new(vec![addr.to_string()], false);
+    let bob = LockClient::new(vec![addr.to_string()], false);
+    let carol = LockClient::new(vec![addr.to_string()], false);
+    alice
+        .acquire_locks(vec![], vec!["alive".to_string()])
+        .await?;
+    carol
+        .acquire_locks(vec![], vec!["crashed".to_string()])
+        .await?;
+    // carol crashes: her heartbeats stop but the server still holds her lock
+    drop(carol);
+    // wait longer than the lease, alice keeps renewing hers meanwhile
+    tokio::time::sleep(Duration::from_secs(13)).await;
+    let reclaimed = tokio::time::timeout(
+        Duration::from_secs(2),
+        bob.acquire_locks(vec![], vec!["crashed".to_string()]),
+    )
+    .await;
+    assert!(
+        reclaimed.is_ok(),
+        "lease of a crashed holder was not reclaimed"
+    );
+    reclaimed.unwrap()?;
+    let renewed = tokio::time::timeout(
+        Duration::from_secs(2),
+        bob.acquire_locks(vec![], vec!["alive".to_string()]),
+    )
+    .await;
+    assert!(renewed.is_err(), "lease of a live holder was reclaimed");
+    alice
+        .release_locks(vec![], vec!["alive".to_string()])
+        .await?;
+    bob.release_locks(vec![], vec!["crashed".to_string()])
+        .await?;
+    let _ = shut_tx.send(()).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_release_after_lease_expired() -> TribResult<()> {
+    let addr = "127.0.0.1:2157";
+    let shut_tx = setup_lock_server(addr).await;
+    let alice = LockClient::new(vec![addr.to_string()], false);
+    alice.acquire_locks(vec![], vec!["k".to_string()]).await?;
+    // alice hangs: her lease lapses and the server reclaims her lock
+    alice.stop_heartbeat().await;
+    tokio::time::sleep(Duration::from_secs(13)).await;
+    match alice.release_locks(vec![], vec!["k".to_string()]).await {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::LeaseExpired(_)) => (),
+            _ => panic!("expected an expired lease, got {}", err),
+        },
+        Ok(_) => panic!("release of a reclaimed lock did not report the expiry"),
+    }
+    // the expiry is reported once, the key can be taken again afterwards
+    alice.acquire_locks(vec![], vec!["k".to_string()]).await?;
+    alice.release_locks(vec![], vec!["k".to_string()]).await?;
+    let _ = shut_tx.send(()).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_wait_die() -> TribResult<()> {
+    let addr = "127.0.0.1:2105";
+    let shut_tx = setup_lock_server(addr).await;
+    let older = Arc::new(LockClient::new(vec![addr.to_string()], false));
+    let younger = Arc::new(LockClient::new(vec![addr.to_string()], false));
+    older
+        .acquire_locks_with_timestamp(vec![], vec!["a".to_string()], 1)
+        .await?;
+    younger
+        .acquire_locks_with_timestamp(vec![], vec!["b".to_string()], 2)
+        .await?;
+    // the younger transaction dies instead of waiting for the older one
+    let died = younger
+        .acquire_locks_with_timestamp(vec![], vec!["a".to_string()], 2)
+        .await;
+    match died {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::LockConflict(key)) => assert_eq!(key, "a"),
+            _ => panic!("expected a lock conflict, got {}", err),
+        },
+        Ok(_) => panic!("younger transaction acquired a lock held by an older one"),
+    }
+    // the older transaction waits for the younger one
+    let older_cp = older.clone();
+    let waiter = tokio::spawn(async move {
+        older_cp
+            .acquire_locks_with_timestamp(vec![], vec!["b".to_string()], 1)
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!waiter.is_finished(), "older transaction did not wait");
+    younger.release_locks(vec![], vec!["b".to_string()]).await?;
+    let granted = tokio::time::timeout(Duration::from_secs(2), waiter).await;
+    assert!(granted.is_ok(), "older transaction was not woken up");
+    granted.unwrap()?.unwrap();
+    older
+        .release_locks(vec![], vec!["a".to_string(), "b".to_string()])
+        .await?;
+    let _ = shut_tx.send(()).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_server_unavailable() -> TribResult<()> {
+    // nothing listens on this address
+    let addr = "127.0.0.1:2106";
+    let mut alice = LockClient::new(vec![addr.to_string()], false);
+    alice.update_retry_policy(2, Duration::from_millis(10));
+    let res = alice.acquire_locks(vec!["k".to_string()], vec![]).await;
+    match res {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::LockUnavailable(x)) => assert_eq!(x, addr),
+            _ => panic!("expected an unavailable lock server, got {}", err),
+        },
+        Ok(_) => panic!("acquired a lock from an unreachable server"),
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_acquire_timeout() -> TribResult<()> {
+    let addr = "127.0.0.1:2107";
+    let shut_tx = setup_lock_server(addr).await;
+    let alice = LockClient::new(vec![addr.to_string()], false);
+    let mut bob = LockClient::new(vec![addr.to_string()], false);
+    bob.update_acquire_timeout(Duration::from_millis(500));
+    alice.acquire_locks(vec![], vec!["k2".to_string()]).await?;
+    let res = bob
+        .acquire_locks(vec![], vec!["k1".to_string(), "k2".to_string()])
+        .await;
+    match res {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::LockTimeout(x)) => assert_eq!(x, addr),
+            _ => panic!("expected a lock timeout, got {}", err),
+        },
+        Ok(_) => panic!("acquired a key held by another writer"),
+    }
+    // bob gave up, so nothing of his request is left on the server
+    alice.acquire_locks(vec![], vec!["k1".to_string()]).await?;
+    alice
+        .release_locks(vec![], vec!["k1".to_string(), "k2".to_string()])
+        .await?;
+    let _ = shut_tx.send(()).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_grants_survive_primary_crash() -> TribResult<()> {
+    let addrs = vec!["127.0.0.1:2108".to_string(), "127.0.0.1:2109".to_string()];
+    let shut_txs: Vec<_> = addrs
+        .iter()
+        .map(|addr| test_helper::spawn_lock_server(addr, &addrs))
+        .collect();
+    // let the servers see each other
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let keys = (0..4).map(|i| format!("k{}", i)).collect::<Vec<String>>();
+    let alice = LockClient::new(addrs.clone(), false);
+    let mut bob = LockClient::new(addrs.clone(), false);
+    bob.update_acquire_timeout(Duration::from_millis(500));
+    alice.acquire_locks(vec![], keys.clone()).await?;
+
+    // crash the first lock server, its partition moves to the second one
+    let _ = shut_txs[0].send(()).await;
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    for key in keys.iter() {
+        let res = bob.acquire_locks(vec![], vec![key.to_string()]).await;
+        assert!(res.is_err(), "lock of {} was lost with its primary", key);
+    }
+    alice.release_locks(vec![], keys.clone()).await?;
+    bob.acquire_locks(vec![], keys.clone()).await?;
+    bob.release_locks(vec![], keys.clone()).await?;
+    let _ = shut_txs[1].send(()).await;
+    Ok(())
+}
+
+#[test]
+fn test_lock_ring_moves_few_keys() {
+    let mut nodes: Vec<String> = (0..4).map(|i| format!("127.0.0.1:{}", 3000 + i)).collect();
+    let before = HashRing::new(&nodes, 64);
+    assert_eq!(
+        before.locate("key"),
+        HashRing::new(&nodes, 64).locate("key")
+    );
+    nodes.push("127.0.0.1:3004".to_string());
+    let after = HashRing::new(&nodes, 64);
+    let keys: Vec<String> = (0..10000).map(|i| format!("key{}", i)).collect();
+    let mut moved = 0;
+    for key in keys.iter() {
+        let (old, new) = (before.locate(key), after.locate(key));
+        if old != new {
+            // keys only move to the node that joined
+            assert_eq!(new, 4);
+            moved += 1;
+        }
+    }
+    // about a fifth of the keys go to the new node
+    assert!(moved > 1000 && moved < 3500, "{} keys moved", moved);
+}
+
+#[test]
+fn test_ring_spreads_a_dead_node_over_the_others() {
+    let nodes: Vec<String> = (0..5).map(|i| format!("127.0.0.1:{}", 3000 + i)).collect();
+    let ring = HashRing::new(&nodes, 64);
+    let all_alive = vec![true; 5];
+    let mut alive = all_alive.clone();
+    alive[2] = false;
+    let mut takers = [0; 5];
+    for i in 0..10000 {
+        let bin = format!("bin{}", i);
+        let before = ring.replicas(&bin, &all_alive, 3);
+        let after = ring.replicas(&bin, &alive, 3);
+        assert_eq!(before.len(), 3);
+        assert_eq!(after.len(), 3);
+        assert!(!after.contains(&2));
+        let mut distinct = after.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 3);
+        // the surviving replicas keep the bin, one new replica takes over
+        for replica in after.iter().filter(|replica| !before.contains(replica)) {
+            assert!(before.contains(&2));
+            takers[*replica] += 1;
+        }
+    }
+    // the load of the dead node lands on every live node, not one successor
+    for (node, taken) in takers.iter().enumerate() {
+        if node != 2 {
+            assert!(*taken > 500, "node {} took {} bins", node, taken);
+        }
+    }
+    // the arcs of the live nodes cover the whole ring
+    let arcs = ring.arcs(&alive, 3);
+    assert!(arcs.iter().all(|(_, _, replicas)| !replicas.contains(&2)));
+    for i in 0..1000 {
+        let bin = format!("bin{}", i);
+        let hash = lab3::stable_hash(&bin);
+        let (_, _, replicas) = arcs
+            .iter()
+            .find(|(start, end, _)| lab3::in_arc(hash, *start, *end))
+            .unwrap();
+        assert_eq!(*replicas, ring.replicas(&bin, &alive, 3));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_lock_handoff_to_joined_server() -> TribResult<()> {
+    let addrs = vec!["127.0.0.1:2110".to_string(), "127.0.0.1:2111".to_string()];
+    let key = (0..)
+        .map(|i| format!("key{}", i))
+        .find(|key| HashRing::new(&addrs, 64).locate(key) == 1)
+        .unwrap();
+    let first_tx = setup_lock_server(&addrs[0]).await;
+    let alice = LockClient::new(vec![addrs[0].clone()], false);
+    alice
+        .acquire_locks_with_timestamp(vec![], vec![key.clone()], 1)
+        .await?;
+    // the second server joins and takes over the lock from the first one
+    let second_tx = test_helper::spawn_lock_server(&addrs[1], &addrs);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let bob = LockClient::new(addrs.clone(), false);
+    match bob
+        .acquire_locks_with_timestamp(vec![], vec![key.clone()], 2)
+        .await
+    {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::LockConflict(conflict)) => assert_eq!(conflict, &key),
+            _ => panic!("expected a lock conflict, got {}", err),
+        },
+        Ok(_) => panic!("lock was not handed over to the joined server"),
+    }
+    // a client still on the old list is not granted the key by the first
+    // server anymore
+    let mut carol = LockClient::new(vec![addrs[0].clone()], false);
+    carol.update_retry_policy(0, Duration::from_millis(10));
+    match carol.acquire_locks(vec![], vec![key.clone()]).await {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::LockUnavailable(_)) => (),
+            _ => panic!("expected the lock to be unavailable, got {}", err),
+        },
+        Ok(_) => panic!("handed off key was granted by its old owner"),
+    }
+    let _ = first_tx.send(()).await;
+    let _ = second_tx.send(()).await;
+    Ok(())
+}
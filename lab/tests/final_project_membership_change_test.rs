@@ -0,0 +1,92 @@
+use lab::big_fucking_tester::{BigFuckingTester, BigFuckingTesterTrait};
+use lab::lab3::{self, MigrationLog, MigrationStep};
+use lab::test_helper;
+use std::time::Duration;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyList, KeyString, KeyValue},
+};
+
+async fn check_users(bin_client: &dyn BinStorage, users: usize) -> TribResult<()> {
+    for i in 0..users {
+        let bin = bin_client.bin(&format!("user{}", i)).await?;
+        assert_eq!(bin.get("name").await?, Some(format!("name{}", i)));
+        assert_eq!(bin.list_get("posts").await?.0, vec![format!("post{}", i)]);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_migration_plan_puts_leaves_before_joins() {
+    let log = MigrationLog::new(
+        vec![true, true, false, true, false],
+        vec![false, true, true, false, true],
+        7,
+    );
+    let step = |back_ids, leave| MigrationStep { back_ids, leave };
+    assert_eq!(
+        log.steps,
+        vec![
+            step(vec![0, 3], true),
+            step(vec![2], false),
+            step(vec![4], false)
+        ]
+    );
+    assert_eq!(log.next, 0);
+    assert_eq!(log.term, 7);
+    assert_eq!(log.status_after(0), log.from);
+    assert_eq!(log.status_after(2), vec![false, true, true, false, false]);
+    assert_eq!(log.status_after(log.steps.len()), log.to);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_keeper_migrates_several_changes_in_one_round() -> TribResult<()> {
+    let mut bft = BigFuckingTester::new_with_replicas(24, 5, vec![0, 1, 2, 3], 1, vec![0], 3).await;
+    let bin_client = lab3::new_bin_client_with_replicas(
+        bft.back_addresses.clone(),
+        bft.lock_addresses.clone(),
+        3,
+    )
+    .await?;
+    let users = 20;
+    for i in 0..users {
+        let bin = bin_client.bin(&format!("user{}", i)).await?;
+        bin.set(&KeyValue {
+            key: "name".to_string(),
+            value: format!("name{}", i),
+        })
+        .await?;
+        bin.list_append(&KeyValue {
+            key: "posts".to_string(),
+            value: format!("post{}", i),
+        })
+        .await?;
+    }
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    // a backend joins while another leaves, both seen in the same scan
+    bft.back_node_leave(1).await;
+    bft.back_join(4).await;
+    tokio::time::sleep(Duration::from_secs(25)).await;
+    assert_eq!(
+        test_helper::recorded_back_status(bin_client.as_ref()).await?,
+        vec![true, false, true, true, true]
+    );
+    check_users(bin_client.as_ref(), users).await?;
+
+    // two backends leave at once, the last replica of their bins is enough
+    // to restore the others
+    bft.back_node_leave(2).await;
+    bft.back_node_leave(3).await;
+    tokio::time::sleep(Duration::from_secs(25)).await;
+    assert_eq!(
+        test_helper::recorded_back_status(bin_client.as_ref()).await?,
+        vec![true, false, false, false, true]
+    );
+    check_users(bin_client.as_ref(), users).await?;
+
+    bft.cleanup().await;
+    Ok(())
+}
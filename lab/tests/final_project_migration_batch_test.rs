@@ -0,0 +1,101 @@
+use lab::lab3::{self, MigrationThrottle};
+use lab::test_helper;
+use std::time::Duration;
+use tokio::time::Instant;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    config::{BackConfig, KeeperConfig, DEFAULT_VNODES},
+    err::{TribResult, TribblerError},
+    rpc::{
+        trib_storage_client::TribStorageClient, Batch, BatchKeys, KeyValue as RpcKeyValue,
+        KeyValueList,
+    },
+    storage::{BinStorage, KeyList, KeyString, KeyValue, MemStorage, Storage},
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_batch_rpc_reads_and_writes_many_keys() -> TribResult<()> {
+    let addr = "127.0.0.1:2156";
+    let shut_tx = test_helper::spawn_back(addr, false).await?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut client = TribStorageClient::connect(format!("http://{}", addr)).await?;
+    client
+        .set_batch(Batch {
+            values: vec![
+                RpcKeyValue {
+                    key: "a".to_string(),
+                    value: "1".to_string(),
+                },
+                RpcKeyValue {
+                    key: "b".to_string(),
+                    value: "2".to_string(),
+                },
+            ],
+            lists: vec![KeyValueList {
+                key: "l".to_string(),
+                list: vec!["x".to_string(), "y".to_string()],
+            }],
+        })
+        .await?;
+    let batch = client
+        .get_batch(BatchKeys {
+            keys: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            list_keys: vec!["l".to_string(), "m".to_string()],
+        })
+        .await?
+        .into_inner();
+    let values: Vec<_> = batch.values.iter().map(|kv| kv.value.as_str()).collect();
+    assert_eq!(values, vec!["1", "2", ""]);
+    let lists: Vec<_> = batch.lists.iter().map(|kv| kv.list.clone()).collect();
+    assert_eq!(lists, vec![vec!["x".to_string(), "y".to_string()], vec![]]);
+
+    // unset keys read back empty and are written back the same way
+    client.set_batch(batch.clone()).await?;
+    client
+        .set_batch(Batch {
+            values: vec![RpcKeyValue {
+                key: "a".to_string(),
+                value: "".to_string(),
+            }],
+            lists: vec![],
+        })
+        .await?;
+    let raw = lab3::new_client(addr).await?;
+    assert_eq!(raw.get("a").await?, None);
+    assert_eq!(raw.get("b").await?, Some("2".to_string()));
+    assert_eq!(raw.list_get("l").await?.0, vec!["x", "y"]);
+    assert!(raw.list_get("m").await?.0.is_empty());
+
+    let _ = shut_tx.send(()).await;
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_migration_throttle_keeps_to_its_rate() {
+    let throttle = MigrationThrottle::new(10);
+    let elapsed = |start: Instant| start.elapsed().as_millis();
+
+    // a second's worth of keys goes through at once, the rest at the rate
+    let start = Instant::now();
+    throttle.admit(10).await;
+    assert_eq!(elapsed(start), 0);
+    let start = Instant::now();
+    throttle.admit(5).await;
+    assert!((500..510).contains(&elapsed(start)));
+
+    // time spent idle does not add up to a larger burst
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    let start = Instant::now();
+    throttle.admit(30).await;
+    assert!((2000..2010).contains(&elapsed(start)));
+    let start = Instant::now();
+    throttle.admit(1).await;
+    assert!((100..110).contains(&elapsed(start)));
+
+    // no limit at a rate of 0
+    let start = Instant::now();
+    MigrationThrottle::new(0).admit(1000).await;
+    assert_eq!(elapsed(start), 0);
+}
@@ -0,0 +1,112 @@
+use lab::big_fucking_tester::{BigFuckingTester, BigFuckingTesterTrait};
+use lab::lab3::{self, HashRing, MigrationCursor, MigrationLog};
+use std::time::Duration;
+use tribbler::config::DEFAULT_VNODES;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyString, KeyValue},
+};
+
+#[test]
+fn test_migration_percent_counts_steps_and_keys() {
+    let mut log = MigrationLog::new(vec![true, true, false], vec![false, true, true], 1);
+    assert_eq!(log.steps.len(), 2);
+    assert_eq!(log.percent(), 0);
+    log.done = 30;
+    log.total = 60;
+    assert_eq!(log.percent(), 25);
+    log.next = 1;
+    log.done = 0;
+    log.total = 0;
+    assert_eq!(log.percent(), 50);
+    log.done = 60;
+    log.total = 60;
+    assert_eq!(log.percent(), 100);
+    assert_eq!(MigrationLog::new(vec![true], vec![true], 1).percent(), 100);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_keeper_resumes_migration_from_checkpoint() -> TribResult<()> {
+    // a single replica per bin, so only the migration copies bins around
+    let mut bft = BigFuckingTester::new_with_replicas(25, 4, vec![0, 1, 2, 3], 1, vec![], 1).await;
+    let old_status = vec![true, true, true, false];
+    let new_status = vec![true, true, true, true];
+
+    // two bins that move to the joining backend from the same source
+    let ring = HashRing::new(&bft.back_addresses, DEFAULT_VNODES);
+    let mut moved: Vec<(usize, String)> = (0..200)
+        .map(|i| format!("bin{}", i))
+        .filter(|bin| ring.replicas(bin, &new_status, 1) == vec![3])
+        .map(|bin| {
+            (
+                ring.replicas(&bin, &old_status, 1)[0],
+                format!("{}::STR::k", bin),
+            )
+        })
+        .collect();
+    moved.sort();
+    let source = moved[0].0;
+    let (skipped, copied) = (moved[0].1.clone(), moved[1].1.clone());
+    assert_eq!(moved[1].0, source);
+    for i in 0..4 {
+        lab3::new_client(&bft.back_addresses[i])
+            .await?
+            .set(&KeyValue {
+                key: "VALIDATION-BIT".to_string(),
+                value: "true".to_string(),
+            })
+            .await?;
+    }
+    let bin_client = lab3::new_bin_client_with_replicas(
+        bft.back_addresses.clone(),
+        bft.lock_addresses.clone(),
+        1,
+    )
+    .await?;
+    let source_client = lab3::new_client(&bft.back_addresses[source]).await?;
+    for key in [&skipped, &copied] {
+        source_client
+            .set(&KeyValue {
+                key: key.to_string(),
+                value: "v".to_string(),
+            })
+            .await?;
+    }
+
+    // a keeper died after checkpointing the first of them
+    let mut log = MigrationLog::new(old_status.clone(), new_status.clone(), 1);
+    log.cursors = vec![MigrationCursor {
+        back_id: source,
+        is_list: false,
+        key: skipped.clone(),
+    }];
+    let keeper_store = bin_client.bin("KEEPER-STORE").await?;
+    keeper_store
+        .set(&KeyValue {
+            key: "BACK-STATUS".to_string(),
+            value: serde_json::to_string(&old_status)?,
+        })
+        .await?;
+    keeper_store
+        .set(&KeyValue {
+            key: "MIGRATION-LOG".to_string(),
+            value: serde_json::to_string(&log)?,
+        })
+        .await?;
+
+    bft.keeper_join(0).await;
+    tokio::time::sleep(Duration::from_secs(10)).await;
+    assert_eq!(keeper_store.get("MIGRATION-LOG").await?, None);
+    assert_eq!(
+        keeper_store.get("BACK-STATUS").await?,
+        Some(serde_json::to_string(&new_status)?)
+    );
+    let joined_client = lab3::new_client(&bft.back_addresses[3]).await?;
+    assert_eq!(joined_client.get(&skipped).await?, None);
+    assert_eq!(joined_client.get(&copied).await?, Some("v".to_string()));
+
+    bft.cleanup().await;
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use lab::lab3::{self, new_bin_client_for_txn, Consistency};
+use lab::test_helper;
+use std::time::Duration;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    config::BackConfig,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyList, KeyString, KeyValue, MemStorage},
+};
+
+fn assert_quorum_not_reached<T: std::fmt::Debug>(res: TribResult<T>) {
+    match res {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::QuorumNotReached(_, _)) => (),
+            _ => panic!("expected a quorum error, got {}", err),
+        },
+        Ok(value) => panic!("expected a quorum error, got {:?}", value),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_quorum_writes_tolerate_a_dead_replica() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2139";
+    let back_addrs = vec![
+        "127.0.0.1:2140".to_string(),
+        "127.0.0.1:2141".to_string(),
+        "127.0.0.1:2142".to_string(),
+    ];
+    let shut_txs = test_helper::setup(lock_addr, &back_addrs).await?;
+
+    let mut all = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    all.update_replicas(3);
+    all.update_consistency(Consistency::All);
+    let mut quorum = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    quorum.update_replicas(3);
+    quorum.update_consistency(Consistency::Quorum);
+
+    let alice = all.bin("alice").await?;
+    alice.set(&KeyValue::new("k", "v1")).await?;
+    alice.list_append(&KeyValue::new("l", "a")).await?;
+
+    let _ = shut_txs[1].send(()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // every replica has to acknowledge under ALL, two of three under QUORUM
+    let alice = all.bin("alice").await?;
+    assert_quorum_not_reached(alice.set(&KeyValue::new("k", "v2")).await);
+    assert_quorum_not_reached(alice.get("k").await);
+    let alice = quorum.bin("alice").await?;
+    alice.set(&KeyValue::new("k", "v2")).await?;
+    alice.list_append(&KeyValue::new("l", "b")).await?;
+    assert_eq!(alice.get("k").await?, Some("v2".to_string()));
+    assert_eq!(alice.list_get("l").await?.0, vec!["a", "b"]);
+
+    let _ = shut_txs[2].send(()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let alice = quorum.bin("alice").await?;
+    assert_quorum_not_reached(alice.set(&KeyValue::new("k", "v3")).await);
+    assert_quorum_not_reached(alice.list_get("l").await);
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_quorum_reads_skip_a_stale_replica() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2143";
+    let back_addrs = vec![
+        "127.0.0.1:2144".to_string(),
+        "127.0.0.1:2145".to_string(),
+        "127.0.0.1:2146".to_string(),
+    ];
+    let shut_txs = test_helper::setup(lock_addr, &back_addrs).await?;
+
+    let mut bin_storage = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    bin_storage.update_replicas(3);
+    bin_storage.update_consistency(Consistency::Quorum);
+    let alice = bin_storage.bin("alice").await?;
+
+    // each backend in turn misses the last write of a key
+    for (i, back_addr) in back_addrs.iter().enumerate() {
+        let key = format!("k{}", i);
+        alice.set(&KeyValue::new(&key, "old")).await?;
+        alice.set(&KeyValue::new(&key, "new")).await?;
+
+        let raw = lab3::new_client(back_addr).await?;
+        let history_key = format!("alice::HISTORY::STR::{}", key);
+        let history = raw.list_get(&history_key).await?.0;
+        raw.list_remove(&KeyValue::new(&history_key, history.last().unwrap()))
+            .await?;
+        raw.set(&KeyValue::new(&format!("alice::STR::{}", key), "old"))
+            .await?;
+
+        assert_eq!(alice.get(&key).await?, Some("new".to_string()));
+    }
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_write_report_names_the_acked_replicas() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2153";
+    let back_addrs = vec!["127.0.0.1:2154".to_string(), "127.0.0.1:2155".to_string()];
+    let shut_txs = test_helper::setup(lock_addr, &back_addrs).await?;
+
+    let mut bin_storage = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    bin_storage.update_consistency(Consistency::All);
+    assert_eq!(bin_storage.last_write_report().await, None);
+
+    let alice = bin_storage.bin("alice").await?;
+    alice.set(&KeyValue::new("k", "v")).await?;
+    let report = bin_storage.last_write_report().await.unwrap();
+    assert_eq!(report.bin, "alice");
+    assert_eq!(report.key, "STR::k");
+    let mut acked = report.acked.clone();
+    acked.sort();
+    assert_eq!(acked, back_addrs);
+    assert!(report.failed.is_empty());
+
+    let _ = shut_txs[1].send(()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // the write lands on the live replica only, which is not enough
+    let alice = bin_storage.bin("alice").await?;
+    assert_quorum_not_reached(alice.list_append(&KeyValue::new("l", "a")).await);
+    let report = bin_storage.last_write_report().await.unwrap();
+    assert_eq!(report.key, "LIST::l");
+    assert_eq!(report.acked, vec![back_addrs[1].to_string()]);
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
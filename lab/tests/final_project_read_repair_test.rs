@@ -0,0 +1,78 @@
+use lab::lab3::{self, new_bin_client_for_txn, BinStorageClient, Consistency};
+use lab::test_helper;
+use std::time::Duration;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    config::BackConfig,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyList, KeyString, KeyValue, MemStorage, Storage},
+};
+
+async fn wait_for_repairs(bin_storage: &BinStorageClient, repairs: u64) {
+    for _ in 0..50 {
+        if bin_storage.read_repairs() >= repairs {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!(
+        "expected {} read repairs, got {}",
+        repairs,
+        bin_storage.read_repairs()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_read_repair_fixes_a_lagging_replica() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2147".to_string();
+    let back_addrs = vec!["127.0.0.1:2148".to_string(), "127.0.0.1:2149".to_string()];
+    let shut_txs = test_helper::setup(&lock_addr, &back_addrs).await?;
+
+    // a quorum of two replicas reads both, so a lagging one answers too
+    let mut bin_storage = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    bin_storage.update_consistency(Consistency::Quorum);
+    let alice = bin_storage.bin("alice").await?;
+    alice.set(&KeyValue::new("name", "old")).await?;
+    alice.set(&KeyValue::new("name", "new")).await?;
+    alice.list_append(&KeyValue::new("tribs", "a")).await?;
+    alice.list_append(&KeyValue::new("tribs", "b")).await?;
+    assert_eq!(alice.get("name").await?, Some("new".to_string()));
+    assert_eq!(alice.list_get("tribs").await?.0, vec!["a", "b"]);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    // replicas that agree are left alone
+    assert_eq!(bin_storage.read_repairs(), 0);
+
+    // the second backend missed the last write of each key
+    let raw = lab3::new_client(&back_addrs[1]).await?;
+    test_helper::drop_latest_record(raw.as_ref(), "alice::HISTORY::STR::name").await?;
+    raw.set(&KeyValue::new("alice::STR::name", "old")).await?;
+    test_helper::drop_latest_record(raw.as_ref(), "alice::HISTORY::LIST::tribs").await?;
+    raw.list_remove(&KeyValue::new("alice::LIST::tribs", "b"))
+        .await?;
+
+    let _ = alice.get("name").await?;
+    wait_for_repairs(&bin_storage, 1).await;
+    let _ = alice.list_get("tribs").await?;
+    wait_for_repairs(&bin_storage, 2).await;
+
+    assert_eq!(raw.get("alice::STR::name").await?, Some("new".to_string()));
+    assert_eq!(raw.list_get("alice::LIST::tribs").await?.0, vec!["a", "b"]);
+    let healthy = lab3::new_client(&back_addrs[0]).await?;
+    for history_key in ["alice::HISTORY::STR::name", "alice::HISTORY::LIST::tribs"] {
+        let mut expected = healthy.list_get(history_key).await?.0;
+        let mut repaired = raw.list_get(history_key).await?.0;
+        expected.sort();
+        repaired.sort();
+        assert_eq!(repaired, expected);
+    }
+
+    // once repaired, reads find nothing more to fix
+    let _ = alice.get("name").await?;
+    let _ = alice.list_get("tribs").await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(bin_storage.read_repairs(), 2);
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
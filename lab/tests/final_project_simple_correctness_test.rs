@@ -22,13 +22,12 @@ use lab::big_fucking_tester::generate_random_username;
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
 async fn test_simple_list() -> TribResult<()> {
-    lab3::new_lockserver_ping_test().await?;
     let mut bft = BigFuckingTester::new(5, 
         5, 
         vec![0, 1, 2, 3, 4], 
         1, 
         vec![0]).await;
-    let bin_client = lab3::new_bin_client(bft.back_addresses.clone()).await?;
+    let bin_client = lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone()).await?;
     let target_bin = bin_client.bin("alice").await?;
     let _ = target_bin.list_append(&KeyValue { key: "key1".to_string(), value: "val1".to_string() }).await?;
     let get_res = target_bin.list_get("key1").await?.0;
@@ -41,14 +40,13 @@ async fn test_simple_list() -> TribResult<()> {
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
 async fn test_single_keeper_two_backs_dead() -> TribResult<()> {
-    lab3::new_lockserver_ping_test().await?;
     let mut bft = BigFuckingTester::new(6, 5, vec![0, 1, 2, 3, 4], 1, vec![0]).await;
     let mut key_val_map = HashMap::new();
     let mut key_bin_map = HashMap::new();
 
     let STRING_LEN = 30;
     let NUM_KEYS = 12;
-    let bin_client = lab3::new_bin_client(bft.back_addresses.clone()).await?;
+    let bin_client = lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone()).await?;
 
     for i in 0..NUM_KEYS {
         let bin_name = generate_random_username(STRING_LEN);
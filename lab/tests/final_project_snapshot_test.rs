@@ -0,0 +1,176 @@
+use lab::lab3::{self, new_bin_client_for_txn};
+use lab::test_helper;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    config::BackConfig,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyList, KeyString, KeyValue, MemStorage},
+};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_snapshot_reads_across_bins() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2130".to_string();
+    let back_addrs = vec!["127.0.0.1:2131".to_string(), "127.0.0.1:2132".to_string()];
+    let shut_txs = test_helper::setup(&lock_addr, &back_addrs).await?;
+
+    let bin_storage = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    let alice = bin_storage.bin("alice").await?;
+    let bob = bin_storage.bin("bob").await?;
+    alice.set(&KeyValue::new("balance", "10")).await?;
+    alice.set(&KeyValue::new("gone", "soon")).await?;
+    bob.list_append(&KeyValue::new("history", "a")).await?;
+    bob.list_append(&KeyValue::new("history", "b")).await?;
+
+    let snapshot = bin_storage.snapshot().await?;
+
+    alice.set(&KeyValue::new("balance", "0")).await?;
+    alice.set(&KeyValue::new("gone", "")).await?;
+    alice.set(&KeyValue::new("later", "x")).await?;
+    bob.list_remove(&KeyValue::new("history", "a")).await?;
+    bob.list_append(&KeyValue::new("history", "c")).await?;
+
+    assert_eq!(
+        snapshot.get("alice", "balance").await?,
+        Some("10".to_string())
+    );
+    assert_eq!(
+        snapshot.get("alice", "gone").await?,
+        Some("soon".to_string())
+    );
+    assert_eq!(snapshot.get("alice", "later").await?, None);
+    assert_eq!(snapshot.list_get("bob", "history").await?.0, vec!["a", "b"]);
+
+    assert_eq!(alice.get("balance").await?, Some("0".to_string()));
+    assert_eq!(alice.get("gone").await?, None);
+    assert_eq!(bob.list_get("history").await?.0, vec!["b", "c"]);
+
+    // a snapshot reopened at the same clock sees the same state
+    let reopened = bin_storage.snapshot_at(snapshot.clock());
+    assert_eq!(
+        reopened.get("alice", "balance").await?,
+        Some("10".to_string())
+    );
+    let latest = bin_storage.snapshot().await?;
+    assert!(latest.clock() > snapshot.clock());
+    assert_eq!(latest.get("alice", "balance").await?, Some("0".to_string()));
+    assert_eq!(latest.list_get("bob", "history").await?.0, vec!["b", "c"]);
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_snapshot_history_compaction() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2137".to_string();
+    let back_addr = "127.0.0.1:2138".to_string();
+    let shut_txs = test_helper::setup(&lock_addr, &[back_addr.to_string()]).await?;
+
+    let bin_storage =
+        new_bin_client_for_txn(vec![back_addr.to_string()], vec![lock_addr.to_string()]);
+    let alice = bin_storage.bin("alice").await?;
+    for i in 0..5 {
+        alice.set(&KeyValue::new("balance", &i.to_string())).await?;
+        alice
+            .list_append(&KeyValue::new("history", &i.to_string()))
+            .await?;
+    }
+    alice.list_remove(&KeyValue::new("history", "1")).await?;
+    let old = bin_storage.snapshot().await?;
+    alice.set(&KeyValue::new("balance", "5")).await?;
+    alice.list_append(&KeyValue::new("history", "5")).await?;
+    let kept = bin_storage.snapshot().await?;
+    alice.set(&KeyValue::new("balance", "6")).await?;
+    alice.list_append(&KeyValue::new("history", "6")).await?;
+
+    bin_storage.compact_histories(kept.clock()).await?;
+
+    // everything up to the safe clock is folded into one checkpoint
+    let raw = lab3::new_client(&back_addr).await?;
+    assert_eq!(
+        raw.list_get("alice::HISTORY::STR::balance").await?.0.len(),
+        2
+    );
+    assert_eq!(
+        raw.list_get("alice::HISTORY::LIST::history").await?.0.len(),
+        2
+    );
+
+    assert_eq!(kept.get("alice", "balance").await?, Some("5".to_string()));
+    assert_eq!(
+        kept.list_get("alice", "history").await?.0,
+        vec!["0", "2", "3", "4", "5"]
+    );
+    match old.get("alice", "balance").await {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::SnapshotCompacted(_)) => (),
+            _ => panic!("expected a compacted snapshot error, got {}", err),
+        },
+        Ok(value) => panic!("read {:?} from a compacted snapshot", value),
+    }
+    let latest = bin_storage.snapshot().await?;
+    assert_eq!(latest.get("alice", "balance").await?, Some("6".to_string()));
+    assert_eq!(
+        latest.list_get("alice", "history").await?.0,
+        vec!["0", "2", "3", "4", "5", "6"]
+    );
+    assert_eq!(alice.get("balance").await?, Some("6".to_string()));
+
+    // compacting again at the same clock changes nothing
+    bin_storage.compact_histories(kept.clock()).await?;
+    assert_eq!(
+        raw.list_get("alice::HISTORY::STR::balance").await?.0.len(),
+        2
+    );
+    assert_eq!(kept.get("alice", "balance").await?, Some("5".to_string()));
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_snapshot_reads_from_a_replica_with_full_history() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2166".to_string();
+    let back_addrs = vec!["127.0.0.1:2167".to_string(), "127.0.0.1:2168".to_string()];
+    let shut_txs = test_helper::setup(&lock_addr, &back_addrs).await?;
+
+    let bin_storage = new_bin_client_for_txn(back_addrs.clone(), vec![lock_addr.to_string()]);
+    let alice = bin_storage.bin("alice").await?;
+    for i in 0..4 {
+        alice.set(&KeyValue::new("balance", &i.to_string())).await?;
+    }
+    let old = bin_storage.snapshot().await?;
+    alice.set(&KeyValue::new("balance", "4")).await?;
+
+    // the second replica alone folds its whole history into a checkpoint
+    let history_key = "alice::HISTORY::STR::balance";
+    let raw = lab3::new_client(&back_addrs[1]).await?;
+    let history = raw.list_get(history_key).await?.0;
+    let latest: serde_json::Value = serde_json::from_str(history.last().unwrap())?;
+    let checkpoint = serde_json::json!({
+        "wrapped_string": "4",
+        "clock_id": latest["clock_id"],
+        "action": "CHECKPOINT",
+    });
+    raw.list_append(&KeyValue::new(history_key, &checkpoint.to_string()))
+        .await?;
+    for record in history.iter() {
+        raw.list_remove(&KeyValue::new(history_key, record)).await?;
+    }
+    assert_eq!(old.get("alice", "balance").await?, Some("3".to_string()));
+
+    // once every replica compacted past it, the snapshot is gone
+    let latest = bin_storage.snapshot().await?;
+    bin_storage.compact_histories(latest.clock()).await?;
+    match old.get("alice", "balance").await {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::SnapshotCompacted(_)) => (),
+            _ => panic!("expected a compacted snapshot error, got {}", err),
+        },
+        Ok(value) => panic!("read {:?} from a compacted snapshot", value),
+    }
+    assert_eq!(latest.get("alice", "balance").await?, Some("4".to_string()));
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
@@ -5,7 +5,7 @@ use rand::{Rng, SeedableRng};
 use rand::rngs::StdRng;
 use lab::{self, lab3};
 use tokio::{sync::mpsc::Sender as MpscSender, time};
-use tribbler::{config::KeeperConfig, storage::BinStorage};
+use tribbler::{config::{KeeperConfig, DEFAULT_MIGRATION_CONCURRENCY, DEFAULT_REPLICAS, DEFAULT_VNODES}, storage::BinStorage};
 #[allow(unused_imports)]
 use tribbler::{
     self,
@@ -116,6 +116,11 @@ async fn setup_single_keeper(i: usize, keepers: Vec<String>, backs: Vec<String>)
     let kfg = KeeperConfig {
         backs: backs.clone(),
         addrs: keepers.clone(),
+        locks: vec![],
+        replicas: DEFAULT_REPLICAS,
+        vnodes: DEFAULT_VNODES,
+        migration_rate: 0,
+        migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
         this: i,
         id: rand::thread_rng().gen_range(0..300),
         ready: None,
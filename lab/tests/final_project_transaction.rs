@@ -20,77 +20,59 @@ use tribbler::{
 use lab::big_fucking_tester::BigFuckingTester;
 use lab::big_fucking_tester::generate_random_username;
 use futures::future::{join_all, ok, err};
-use lab3::TxnClient;
+use lab3::{TxnClient, TxnKeys};
 
 async fn random_read_txn(mut txn_client: TxnClient) -> TribResult<()> {
     let NUM_KEYS_PER_BIN = 1;
     let NUM_BIN = 10;
-    let mut read_keys_map = HashMap::new();
-    let mut write_keys_map = HashMap::new();
+    let mut keys = TxnKeys::new();
     for j in 0..NUM_BIN {
         let rand_bin = generate_random_username(30);
-        let mut bin_set = vec![];
         for i in 0..NUM_KEYS_PER_BIN {
-            bin_set.push(generate_random_username(10));
+            keys = keys.read_str(&rand_bin, &generate_random_username(10));
         }
-        read_keys_map.insert(rand_bin, bin_set);
     }
-    let trans_key = txn_client.transaction_start(read_keys_map.clone(), write_keys_map.clone()).await?;
-    txn_client.transaction_end(trans_key, read_keys_map.clone(), write_keys_map.clone());
-    Ok(())
+    txn_client.run(keys, |_txn| async { Ok(()) }).await
 }
 
 async fn random_write_txn(mut txn_client: TxnClient) -> TribResult<()> {
     let NUM_KEYS_PER_BIN = 1;
     let NUM_BIN = 10;
-    let mut read_keys_map = HashMap::new();
-    let mut write_keys_map = HashMap::new();
+    let mut keys = TxnKeys::new();
     for j in 0..NUM_BIN {
         let rand_bin = generate_random_username(30);
-        let mut bin_set = vec![];
         for i in 0..NUM_KEYS_PER_BIN {
-            bin_set.push(format!("STR::{}", generate_random_username(10)));
+            keys = keys.write_str(&rand_bin, &generate_random_username(10));
         }
-        write_keys_map.insert(rand_bin, bin_set);
     }
-    let trans_key = txn_client.transaction_start(read_keys_map.clone(), write_keys_map.clone()).await?;
-    txn_client.transaction_end(trans_key, read_keys_map.clone(), write_keys_map.clone());
-    Ok(())
+    txn_client.run(keys, |_txn| async { Ok(()) }).await
 }
 
 async fn random_rw_txn(mut txn_client: TxnClient) -> TribResult<()> {
     let mut rng = rand::thread_rng();
     let NUM_KEYS_PER_BIN = 1;
     let NUM_BIN = 50;
-    let mut read_keys_map = HashMap::new();
-    let mut write_keys_map = HashMap::new();
+    let mut keys = TxnKeys::new();
     for j in 0..NUM_BIN {
         let rand_bin = generate_random_username(30);
-        let mut read_bin_set = vec![];
-        let mut write_bin_set = vec![];
         for i in 0..NUM_KEYS_PER_BIN {
             let action_type: usize = rng.gen_range(0..2);
             if action_type == 0 {
-                read_bin_set.push(generate_random_username(10));
+                keys = keys.read_str(&rand_bin, &generate_random_username(10));
             } else {
-                write_bin_set.push(format!("STR::{}", generate_random_username(10)));
+                keys = keys.write_str(&rand_bin, &generate_random_username(10));
             }
         }
-        read_keys_map.insert(rand_bin.to_string(), read_bin_set);
-        write_keys_map.insert(rand_bin.to_string(), write_bin_set);
     }
-    let trans_key = txn_client.transaction_start(read_keys_map.clone(), write_keys_map.clone()).await?;
-    txn_client.transaction_end(trans_key, read_keys_map.clone(), write_keys_map.clone());
-    Ok(())
+    txn_client.run(keys, |_txn| async { Ok(()) }).await
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
 async fn test_txn_read_write_throughput() -> TribResult<()> {
-    lab3::new_lockserver_ping_test().await?;
     let mut bft = BigFuckingTester::new(15, 5, vec![0, 1, 2, 3, 4], 1, vec![0]).await;
     let channel_cache = Arc::new(RwLock::new(HashMap::new()));
-    let bin_storage = Arc::new(new_bin_client_for_txn(bft.back_addresses));
-    let lock_client = Arc::new(new_lock_client());
+    let bin_storage = Arc::new(new_bin_client_for_txn(bft.back_addresses, bft.lock_addresses.clone()));
+    let lock_client = Arc::new(new_lock_client(bft.lock_addresses.clone()));
     let mut futures = vec![];
     let NUM_TXN_CLIENT = 10;
     for i in 0..NUM_TXN_CLIENT {
@@ -104,11 +86,10 @@ async fn test_txn_read_write_throughput() -> TribResult<()> {
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
 async fn test_txn_write_throughput() -> TribResult<()> {
-    lab3::new_lockserver_ping_test().await?;
     let mut bft = BigFuckingTester::new(15, 5, vec![0, 1, 2, 3, 4], 1, vec![0]).await;
     let channel_cache = Arc::new(RwLock::new(HashMap::new()));
-    let bin_storage = Arc::new(new_bin_client_for_txn(bft.back_addresses));
-    let lock_client = Arc::new(new_lock_client());
+    let bin_storage = Arc::new(new_bin_client_for_txn(bft.back_addresses, bft.lock_addresses.clone()));
+    let lock_client = Arc::new(new_lock_client(bft.lock_addresses.clone()));
     let mut futures = vec![];
     let NUM_TXN_CLIENT = 50;
     for i in 0..NUM_TXN_CLIENT {
@@ -124,11 +105,10 @@ async fn test_txn_write_throughput() -> TribResult<()> {
 
 
 async fn test_txn_read_throughput() -> TribResult<()> {
-    lab3::new_lockserver_ping_test().await?;
     let mut bft = BigFuckingTester::new(15, 5, vec![0, 1, 2, 3, 4], 1, vec![0]).await;
     let channel_cache = Arc::new(RwLock::new(HashMap::new()));
-    let bin_storage = Arc::new(new_bin_client_for_txn(bft.back_addresses));
-    let lock_client = Arc::new(new_lock_client());
+    let bin_storage = Arc::new(new_bin_client_for_txn(bft.back_addresses, bft.lock_addresses.clone()));
+    let lock_client = Arc::new(new_lock_client(bft.lock_addresses.clone()));
     let mut futures = vec![];
     let NUM_TXN_CLIENT = 10;
     for i in 0..NUM_TXN_CLIENT {
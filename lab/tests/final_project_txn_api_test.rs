@@ -0,0 +1,445 @@
+use lab::lab3::{
+    new_bin_client_for_txn, new_lock_client, new_txn_client, BinStorageClient, LockClient,
+    TxnClient, TxnKeys, TxnMode, TxnState,
+};
+use lab::test_helper;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{mpsc::Sender as MpscSender, RwLock};
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    config::BackConfig,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyList, KeyString, KeyValue, MemStorage},
+};
+
+async fn setup(
+    lock_addr: &str,
+    back_addr: &str,
+) -> TribResult<(Vec<MpscSender<()>>, Arc<BinStorageClient>)> {
+    let shut_txs = test_helper::setup(lock_addr, &[back_addr.to_string()]).await?;
+    let bin_storage = Arc::new(new_bin_client_for_txn(
+        vec![back_addr.to_string()],
+        vec![lock_addr.to_string()],
+    ));
+    Ok((shut_txs, bin_storage))
+}
+
+fn txn_client(lock_addr: &str, bin_storage: &Arc<BinStorageClient>) -> TxnClient {
+    new_txn_client(
+        Arc::new(RwLock::new(HashMap::new())),
+        bin_storage.clone(),
+        Arc::new(new_lock_client(vec![lock_addr.to_string()])),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_run_commits() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2114";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2115").await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    let keys = TxnKeys::new()
+        .read_str("alice", "balance")
+        .write_str("bob", "balance")
+        .write_list("bob", "history");
+    let moved = client
+        .run(keys, |txn| async move {
+            let balance = txn.get("alice", "balance").await?.unwrap_or_default();
+            txn.set("bob", "balance", "10").await?;
+            txn.list_append("bob", "history", "from alice").await?;
+            Ok(balance)
+        })
+        .await?;
+    assert_eq!(moved, "");
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    assert_eq!(bob.get("balance").await?, Some("10".to_string()));
+    assert_eq!(bob.list_get("history").await?.0, vec!["from alice"]);
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_run_rolls_back_on_error() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2116";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2117").await?;
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    bob.set(&KeyValue::new("balance", "5")).await?;
+    bob.list_append(&KeyValue::new("history", "opened")).await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    let keys = TxnKeys::new()
+        .write_str("bob", "balance")
+        .write_list("bob", "history");
+    let res: TribResult<()> = client
+        .run(keys, |txn| async move {
+            txn.set("bob", "balance", "15").await?;
+            txn.list_append("bob", "history", "deposit").await?;
+            Err(TribblerError::Unknown("insufficient funds".to_string()).into())
+        })
+        .await;
+    assert!(res.is_err());
+    assert_eq!(bob.get("balance").await?, Some("5".to_string()));
+    assert_eq!(bob.list_get("history").await?.0, vec!["opened"]);
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_run_rejects_undeclared_keys() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2118";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2119").await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    let keys = TxnKeys::new().read_str("bob", "balance");
+    let res = client
+        .run(
+            keys,
+            |txn| async move { txn.set("bob", "balance", "1").await },
+        )
+        .await;
+    match res {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::UndeclaredTxnKey(key)) => assert_eq!(key, "bob::STR::balance"),
+            _ => panic!("expected an undeclared key error, got {}", err),
+        },
+        Ok(_) => panic!("wrote a key that was only declared for reading"),
+    }
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_run_retries_lock_conflicts() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2120";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2121").await?;
+    // an older transaction holds the lock for a while
+    let older = Arc::new(LockClient::new(vec![lock_addr.to_string()], false));
+    older
+        .acquire_locks_with_timestamp(vec![], vec!["bob::STR::balance".to_string()], 1)
+        .await?;
+    let older_cp = older.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        older_cp
+            .release_locks(vec![], vec!["bob::STR::balance".to_string()])
+            .await
+    });
+    let mut client = txn_client(lock_addr, &bin_storage);
+    let mut attempts = 0;
+    client
+        .run(TxnKeys::new().write_str("bob", "balance"), |txn| {
+            attempts += 1;
+            async move { txn.set("bob", "balance", "1").await }
+        })
+        .await?;
+    assert_eq!(attempts, 1);
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    assert_eq!(bob.get("balance").await?, Some("1".to_string()));
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_abort_restores_old_values() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2122";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2123").await?;
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    bob.set(&KeyValue::new("balance", "5")).await?;
+    bob.list_append(&KeyValue::new("history", "opened")).await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    let write_keys_map = HashMap::from([(
+        "bob".to_string(),
+        vec![
+            "STR::balance".to_string(),
+            "STR::nickname".to_string(),
+            "LIST::history".to_string(),
+        ],
+    )]);
+    let trans_key = client
+        .transaction_start(HashMap::new(), write_keys_map.clone())
+        .await?;
+    assert_eq!(
+        client.state().await,
+        TxnState::Active(trans_key.to_string())
+    );
+    bob.set(&KeyValue::new("balance", "15")).await?;
+    bob.set(&KeyValue::new("nickname", "bobby")).await?;
+    bob.list_append(&KeyValue::new("history", "deposit"))
+        .await?;
+    client
+        .transaction_abort(
+            trans_key.to_string(),
+            HashMap::new(),
+            write_keys_map.clone(),
+        )
+        .await?;
+    assert_eq!(
+        client.state().await,
+        TxnState::Aborted(trans_key.to_string())
+    );
+    assert_eq!(bob.get("balance").await?, Some("5".to_string()));
+    assert_eq!(bob.get("nickname").await?, None);
+    assert_eq!(bob.list_get("history").await?.0, vec!["opened"]);
+    assert_eq!(bob.get("TRANS-LOG-STR::balance").await?, None);
+    assert_eq!(bob.get("TRANS-LOG-LIST::history").await?, None);
+
+    // an aborted transaction can neither commit nor abort again
+    let commit = client
+        .transaction_end(
+            trans_key.to_string(),
+            HashMap::new(),
+            write_keys_map.clone(),
+        )
+        .await;
+    match commit {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::InvalidTxnState(_)) => (),
+            _ => panic!("expected an invalid state error, got {}", err),
+        },
+        Ok(_) => panic!("committed an aborted transaction"),
+    }
+    assert!(client
+        .transaction_abort(trans_key, HashMap::new(), write_keys_map.clone())
+        .await
+        .is_err());
+
+    // the locks were released: a new transaction on the same keys goes through
+    let trans_key = client
+        .transaction_start(HashMap::new(), write_keys_map.clone())
+        .await?;
+    assert!(client
+        .transaction_start(HashMap::new(), write_keys_map.clone())
+        .await
+        .is_err());
+    client
+        .transaction_end(trans_key.to_string(), HashMap::new(), write_keys_map)
+        .await?;
+    assert_eq!(client.state().await, TxnState::Committed(trans_key));
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_handle_stops_after_commit() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2124";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2125").await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    let leaked = client
+        .run(
+            TxnKeys::new().write_str("bob", "balance"),
+            |txn| async move {
+                txn.set("bob", "balance", "1").await?;
+                Ok(txn)
+            },
+        )
+        .await?;
+    assert!(leaked.set("bob", "balance", "2").await.is_err());
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    assert_eq!(bob.get("balance").await?, Some("1".to_string()));
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_optimistic_buffers_writes() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2126";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2127").await?;
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    bob.list_append(&KeyValue::new("history", "opened")).await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    client.update_mode(TxnMode::Optimistic);
+    let keys = TxnKeys::new()
+        .write_str("bob", "balance")
+        .write_list("bob", "history");
+    let res: TribResult<()> = client
+        .run(keys.clone(), |txn| async move {
+            txn.set("bob", "balance", "15").await?;
+            assert_eq!(txn.get("bob", "balance").await?, Some("15".to_string()));
+            txn.list_append("bob", "history", "deposit").await?;
+            assert_eq!(
+                txn.list_get("bob", "history").await?.0,
+                vec!["opened", "deposit"]
+            );
+            Err(TribblerError::Unknown("insufficient funds".to_string()).into())
+        })
+        .await;
+    assert!(res.is_err());
+    // nothing reaches the backends before commit
+    assert_eq!(bob.get("balance").await?, None);
+    assert_eq!(bob.list_get("history").await?.0, vec!["opened"]);
+
+    let removed = client
+        .run(keys, |txn| async move {
+            txn.set("bob", "balance", "15").await?;
+            txn.list_append("bob", "history", "deposit").await?;
+            txn.list_remove("bob", "history", "opened").await
+        })
+        .await?;
+    assert_eq!(removed, 1);
+    assert_eq!(bob.get("balance").await?, Some("15".to_string()));
+    assert_eq!(bob.list_get("history").await?.0, vec!["deposit"]);
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_optimistic_retries_stale_reads() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2128";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2129").await?;
+    let bob = Arc::new(bin_storage.bin_with_locks("bob").await?);
+    bob.set(&KeyValue::new("balance", "1")).await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    client.update_mode(TxnMode::Optimistic);
+    let mut attempts = 0;
+    client
+        .run(TxnKeys::new().write_str("bob", "balance"), |txn| {
+            attempts += 1;
+            let first = attempts == 1;
+            let bob = bob.clone();
+            async move {
+                let balance: u32 = txn.get("bob", "balance").await?.unwrap().parse()?;
+                if first {
+                    // another client deposits after our read
+                    bob.set(&KeyValue::new("balance", "11")).await?;
+                }
+                txn.set("bob", "balance", &(balance + 5).to_string()).await
+            }
+        })
+        .await?;
+    assert_eq!(attempts, 2);
+    assert_eq!(bob.get("balance").await?, Some("16".to_string()));
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_optimistic_reads_take_no_locks() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2158";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2159").await?;
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    bob.set(&KeyValue::new("balance", "1")).await?;
+    // a writer holds the lock of the key without having written it yet
+    let writer = LockClient::new(vec![lock_addr.to_string()], false);
+    writer
+        .acquire_locks_with_timestamp(vec![], vec!["bob::STR::balance".to_string()], 1)
+        .await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    client.update_mode(TxnMode::Optimistic);
+    let read = tokio::time::timeout(
+        Duration::from_secs(2),
+        client.run(
+            TxnKeys::new().read_str("bob", "balance"),
+            |txn| async move { txn.get("bob", "balance").await },
+        ),
+    )
+    .await;
+    assert!(read.is_ok(), "read-only commit waited for the lock");
+    assert_eq!(read.unwrap()?, Some("1".to_string()));
+    writer
+        .release_locks(vec![], vec!["bob::STR::balance".to_string()])
+        .await?;
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_commit_aborts_when_prepare_fails() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2135";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2136").await?;
+    let alice = bin_storage.bin_with_locks("alice").await?;
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    alice.set(&KeyValue::new("balance", "10")).await?;
+    bob.set(&KeyValue::new("balance", "5")).await?;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    let write_keys_map = HashMap::from([
+        ("alice".to_string(), vec!["STR::balance".to_string()]),
+        ("bob".to_string(), vec!["STR::balance".to_string()]),
+    ]);
+    let trans_key = client
+        .transaction_start(HashMap::new(), write_keys_map.clone())
+        .await?;
+    alice.set(&KeyValue::new("balance", "0")).await?;
+    bob.set(&KeyValue::new("balance", "15")).await?;
+    // bob's undo log is gone, as when recovery rolled back bob after the
+    // locks of the transaction were lost
+    bob.set(&KeyValue::new("TRANS-LOG-STR::balance", ""))
+        .await?;
+    let commit = client
+        .transaction_end(trans_key.to_string(), HashMap::new(), write_keys_map)
+        .await;
+    match commit {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::TxnPrepareFailed(_)) => (),
+            _ => panic!("expected a prepare failure, got {}", err),
+        },
+        Ok(_) => panic!("committed a transaction that could not be prepared"),
+    }
+    assert_eq!(
+        client.state().await,
+        TxnState::Aborted(trans_key.to_string())
+    );
+    assert_eq!(alice.get("balance").await?, Some("10".to_string()));
+    assert_eq!(alice.get("TRANS-LOG-STR::balance").await?, None);
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_start_releases_locks_when_undo_log_fails() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2162";
+    let (mut shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2163").await?;
+    // the backend dies, the locks are granted but no undo log can be written
+    let _ = shut_txs.remove(1).send(()).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let mut client = txn_client(lock_addr, &bin_storage);
+    let write_keys_map = HashMap::from([("bob".to_string(), vec!["STR::balance".to_string()])]);
+    assert!(client
+        .transaction_start(HashMap::new(), write_keys_map)
+        .await
+        .is_err());
+    assert!(matches!(client.state().await, TxnState::Aborted(_)));
+    let mut other = LockClient::new(vec![lock_addr.to_string()], false);
+    other.update_acquire_timeout(Duration::from_secs(2));
+    other
+        .acquire_locks(vec![], vec!["bob::STR::balance".to_string()])
+        .await?;
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_commit_deletes_its_decision() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2178";
+    let (shut_txs, bin_storage) = setup(lock_addr, "127.0.0.1:2179").await?;
+    let mut client = TxnClient::new(
+        Arc::new(new_lock_client(vec![lock_addr.to_string()])),
+        "frank".to_string(),
+        Arc::new(RwLock::new(HashMap::new())),
+        bin_storage.clone(),
+    );
+    let write_keys_map = HashMap::from([("bob".to_string(), vec!["STR::balance".to_string()])]);
+    let trans_key = client
+        .transaction_start(HashMap::new(), write_keys_map.clone())
+        .await?;
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    bob.set(&KeyValue::new("balance", "20")).await?;
+    client
+        .transaction_end(trans_key.to_string(), HashMap::new(), write_keys_map)
+        .await?;
+    assert_eq!(bob.get("balance").await?, Some("20".to_string()));
+    // every bin is finished, nothing is left to read the decision
+    let decisions = bin_storage
+        .bin("frank")
+        .await?
+        .list_get(&format!("TXN-DECISION::{}", trans_key))
+        .await?
+        .0;
+    assert!(decisions.is_empty());
+    let open = bin_storage
+        .bin("TXN-INDEX")
+        .await?
+        .list_get("OPEN-TXNS")
+        .await?
+        .0;
+    assert!(open.is_empty());
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
@@ -0,0 +1,263 @@
+use lab::lab3::{
+    self, new_bin_client_for_txn, new_lock_client, new_txn_client, TxnClient, TxnState,
+};
+use lab::test_helper;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+#[allow(unused_imports)]
+use tribbler::{
+    self,
+    config::BackConfig,
+    err::{TribResult, TribblerError},
+    storage::{BinStorage, KeyList, KeyString, KeyValue, MemStorage},
+};
+
+/// asserts recovery left no open transaction in the index, and no history
+/// of the undo log of `k` in `bins` on the backend at `back_addr`.
+async fn assert_collected(
+    bin_storage: &dyn BinStorage,
+    back_addr: &str,
+    bins: &[&str],
+) -> TribResult<()> {
+    let open = bin_storage
+        .bin("TXN-INDEX")
+        .await?
+        .list_get("OPEN-TXNS")
+        .await?
+        .0;
+    assert!(open.is_empty(), "transactions left open: {:?}", open);
+    let raw = lab3::new_client(back_addr).await?;
+    for bin in bins {
+        let history_key = format!("{}::HISTORY::STR::TRANS-LOG-STR::k", bin);
+        assert!(raw.list_get(&history_key).await?.0.is_empty());
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_recovery_rolls_back_crashed_transaction() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2112".to_string();
+    let back_addr = "127.0.0.1:2113".to_string();
+    let shut_txs = test_helper::setup(&lock_addr, &[back_addr.to_string()]).await?;
+
+    let channel_cache = Arc::new(RwLock::new(HashMap::new()));
+    let bin_storage = Arc::new(new_bin_client_for_txn(
+        vec![back_addr.to_string()],
+        vec![lock_addr.to_string()],
+    ));
+    let write_keys_map = |bin: &str| HashMap::from([(bin.to_string(), vec!["STR::k".to_string()])]);
+    bin_storage
+        .bin("alice")
+        .await?
+        .set(&KeyValue::new("k", "before"))
+        .await?;
+
+    // alice's client crashes in the middle of a transaction
+    let mut crashed = new_txn_client(
+        channel_cache.clone(),
+        bin_storage.clone(),
+        Arc::new(new_lock_client(vec![lock_addr.to_string()])),
+    );
+    crashed
+        .transaction_start(HashMap::new(), write_keys_map("alice"))
+        .await?;
+    bin_storage
+        .bin_with_locks("alice")
+        .await?
+        .set(&KeyValue::new("k", "during"))
+        .await?;
+    drop(crashed);
+
+    // bob's transaction commits
+    let mut committed = new_txn_client(
+        channel_cache.clone(),
+        bin_storage.clone(),
+        Arc::new(new_lock_client(vec![lock_addr.to_string()])),
+    );
+    let trans_key = committed
+        .transaction_start(HashMap::new(), write_keys_map("bob"))
+        .await?;
+    bin_storage
+        .bin_with_locks("bob")
+        .await?
+        .set(&KeyValue::new("k", "after"))
+        .await?;
+    committed
+        .transaction_end(trans_key, HashMap::new(), write_keys_map("bob"))
+        .await?;
+
+    // recovery waits for the lease of the crashed client to run out
+    let restarted = new_txn_client(
+        channel_cache.clone(),
+        bin_storage.clone(),
+        Arc::new(new_lock_client(vec![lock_addr.to_string()])),
+    );
+    restarted.recover().await?;
+
+    let alice = bin_storage.bin_with_locks("alice").await?;
+    assert_eq!(alice.get("k").await?, Some("before".to_string()));
+    assert_eq!(alice.get("TRANS-LOG-STR::k").await?, None);
+    let bob = bin_storage.bin_with_locks("bob").await?;
+    assert_eq!(bob.get("k").await?, Some("after".to_string()));
+    assert_eq!(bob.get("TRANS-LOG-STR::k").await?, None);
+    assert_collected(bin_storage.as_ref(), &back_addr, &["alice", "bob"]).await?;
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_recovery_finishes_prepared_transactions() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2133".to_string();
+    let back_addr = "127.0.0.1:2134".to_string();
+    let shut_txs = test_helper::setup(&lock_addr, &[back_addr.to_string()]).await?;
+
+    let bin_storage = Arc::new(new_bin_client_for_txn(
+        vec![back_addr.to_string()],
+        vec![lock_addr.to_string()],
+    ));
+    // leaves what a coordinator dying in the commit phase leaves behind:
+    // the new value, its undo log and a prepare record
+    let prepared = |coordinator: &str, trans_key: &str, bin: &str, old: &str, new: &str| {
+        let bin_storage = bin_storage.clone();
+        let (coordinator, trans_key, bin, old, new) = (
+            coordinator.to_string(),
+            trans_key.to_string(),
+            bin.to_string(),
+            old.to_string(),
+            new.to_string(),
+        );
+        async move {
+            let storage = bin_storage.bin_with_locks(&bin).await?;
+            storage.set(&KeyValue::new("k", &new)).await?;
+            storage
+                .set(&KeyValue::new(
+                    "TRANS-LOG-STR::k",
+                    &format!(
+                        r#"{{"transaction_id":"{}","transaction_key":"{}","old_value":["{}"]}}"#,
+                        coordinator, trans_key, old
+                    ),
+                ))
+                .await?;
+            storage
+                .set(&KeyValue::new(
+                    &format!("TXN-PREPARE::{}::{}", coordinator, trans_key),
+                    &format!(
+                        r#"{{"transaction_id":"{}","transaction_key":"{}","keys":["STR::k"]}}"#,
+                        coordinator, trans_key
+                    ),
+                ))
+                .await?;
+            TribResult::Ok(())
+        }
+    };
+
+    // both transactions were indexed as open when they started
+    let index = bin_storage.bin("TXN-INDEX").await?;
+    for open in [
+        r#"{"transaction_id":"carol","transaction_key":"1","write_keys":{"alice":["STR::k"],"bob":["STR::k"]}}"#,
+        r#"{"transaction_id":"dave","transaction_key":"2","write_keys":{"bobby":["STR::k"]}}"#,
+    ] {
+        index.list_append(&KeyValue::new("OPEN-TXNS", open)).await?;
+    }
+    // carol decided to commit before crashing
+    prepared("carol", "1", "alice", "a0", "a1").await?;
+    prepared("carol", "1", "bob", "b0", "b1").await?;
+    bin_storage
+        .bin("carol")
+        .await?
+        .list_append(&KeyValue::new("TXN-DECISION::1", "COMMIT"))
+        .await?;
+    // dave crashed while bobby was prepared but before deciding
+    prepared("dave", "2", "bobby", "c0", "c1").await?;
+
+    let restarted = new_txn_client(
+        Arc::new(RwLock::new(HashMap::new())),
+        bin_storage.clone(),
+        Arc::new(new_lock_client(vec![lock_addr.to_string()])),
+    );
+    restarted.recover().await?;
+
+    for (bin, value, prepare_key) in [
+        ("alice", "a1", "TXN-PREPARE::carol::1"),
+        ("bob", "b1", "TXN-PREPARE::carol::1"),
+        ("bobby", "c0", "TXN-PREPARE::dave::2"),
+    ] {
+        let storage = bin_storage.bin_with_locks(bin).await?;
+        assert_eq!(storage.get("k").await?, Some(value.to_string()));
+        assert_eq!(storage.get("TRANS-LOG-STR::k").await?, None);
+        assert_eq!(storage.get(prepare_key).await?, None);
+    }
+    assert_collected(bin_storage.as_ref(), &back_addr, &["alice", "bob", "bobby"]).await?;
+    // both decisions are deleted once their transaction is finished
+    for (coordinator, decision_key) in [("carol", "TXN-DECISION::1"), ("dave", "TXN-DECISION::2")] {
+        let decisions = bin_storage
+            .bin(coordinator)
+            .await?
+            .list_get(decision_key)
+            .await?
+            .0;
+        assert!(decisions.is_empty());
+    }
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_txn_commit_yields_to_a_recorded_abort() -> TribResult<()> {
+    let lock_addr = "127.0.0.1:2164".to_string();
+    let back_addr = "127.0.0.1:2165".to_string();
+    let shut_txs = test_helper::setup(&lock_addr, &[back_addr.to_string()]).await?;
+
+    let bin_storage = Arc::new(new_bin_client_for_txn(
+        vec![back_addr.to_string()],
+        vec![lock_addr.to_string()],
+    ));
+    let alice = bin_storage.bin_with_locks("alice").await?;
+    alice.set(&KeyValue::new("k", "before")).await?;
+    let mut slow = TxnClient::new(
+        Arc::new(new_lock_client(vec![lock_addr.to_string()])),
+        "erin".to_string(),
+        Arc::new(RwLock::new(HashMap::new())),
+        bin_storage.clone(),
+    );
+    let write_keys_map = HashMap::from([("alice".to_string(), vec!["STR::k".to_string()])]);
+    let trans_key = slow
+        .transaction_start(HashMap::new(), write_keys_map.clone())
+        .await?;
+    alice.set(&KeyValue::new("k", "during")).await?;
+    // the coordinator stalls past its lease and recovery presumes an abort
+    bin_storage
+        .bin("erin")
+        .await?
+        .list_append(&KeyValue::new(
+            &format!("TXN-DECISION::{}", trans_key),
+            "ABORT",
+        ))
+        .await?;
+
+    match slow
+        .transaction_end(trans_key.to_string(), HashMap::new(), write_keys_map)
+        .await
+    {
+        Err(err) => match err.downcast_ref::<TribblerError>() {
+            Some(TribblerError::TxnAbortedByRecovery(_)) => (),
+            _ => panic!("expected an abort by recovery, got {}", err),
+        },
+        Ok(_) => panic!("committed over a recorded abort"),
+    }
+    assert_eq!(slow.state().await, TxnState::Aborted(trans_key.to_string()));
+    assert_eq!(alice.get("k").await?, Some("before".to_string()));
+    // the abort is finished, so its decision is deleted
+    let decisions = bin_storage
+        .bin("erin")
+        .await?
+        .list_get(&format!("TXN-DECISION::{}", trans_key))
+        .await?
+        .0;
+    assert!(decisions.is_empty());
+
+    test_helper::shutdown(shut_txs).await;
+    Ok(())
+}
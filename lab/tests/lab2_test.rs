@@ -9,7 +9,7 @@ use std::{
 use rand::Rng;
 use lab::{self, lab2, lab1};
 use tokio::{sync::mpsc::Sender as MpscSender};
-use tribbler::{config::KeeperConfig};
+use tribbler::config::{KeeperConfig, DEFAULT_MIGRATION_CONCURRENCY, DEFAULT_REPLICAS, DEFAULT_VNODES};
 #[allow(unused_imports)]
 use tribbler::{
     self,
@@ -73,6 +73,11 @@ async fn setup(backs: Vec<String>, keeper_addr: Vec<String>) -> TribResult<(Mpsc
     let kfg1 = KeeperConfig {
         backs: backs.clone(),
         addrs: keeper_addr.clone(),
+        locks: vec![],
+        replicas: DEFAULT_REPLICAS,
+        vnodes: DEFAULT_VNODES,
+        migration_rate: 0,
+        migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
         this: 0,
         id: 1,
         ready: None,
@@ -81,6 +86,11 @@ async fn setup(backs: Vec<String>, keeper_addr: Vec<String>) -> TribResult<(Mpsc
     let kfg2 = KeeperConfig {
         backs: backs.clone(),
         addrs: keeper_addr.clone(),
+        locks: vec![],
+        replicas: DEFAULT_REPLICAS,
+        vnodes: DEFAULT_VNODES,
+        migration_rate: 0,
+        migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
         this: 1,
         id: 2,
         ready: None,
@@ -691,6 +701,11 @@ async fn test_massive_broadcast_test() -> TribResult<()> {
     let kfg = KeeperConfig {
         backs: backs.clone(),
         addrs: vec![keeper_addr.to_string()],
+        locks: vec![],
+        replicas: DEFAULT_REPLICAS,
+        vnodes: DEFAULT_VNODES,
+        migration_rate: 0,
+        migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
         this: 0,
         id: 0,
         ready: None,
@@ -28,7 +28,7 @@ async fn test_single_list_append_one_node_dead() -> TribResult<()> {
     // Time to return the data back to Rhine River pal pal.
     let mut bft = BigFuckingTester::new(10, 5, vec![0, 1, 2, 3, 4], 1, vec![0]).await;
     println!("The story begins, backends and keepers rise up!");
-    let bin_client = lab3::new_bin_client(bft.back_addresses.clone()).await?;
+    let bin_client = lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone()).await?;
     let target_bin = bin_client.bin("alice").await?;
     let _ = target_bin.list_append(&KeyValue { key: "key1".to_string(), value: "val1".to_string() }).await?;
     println!("And the elf wrecked one part of Rhine riverbacks and snatched the treasures");
@@ -49,7 +49,7 @@ async fn test_single_list_append_one_node_dead() -> TribResult<()> {
 async fn test_single_keeper_successor_node_join() -> TribResult<()> {
     // Adventure time:
     let mut bft = BigFuckingTester::new(2, 5, vec![0, 2, 4], 1, vec![0]).await;
-    let bin_client = lab3::new_bin_client(bft.back_addresses.clone()).await?;
+    let bin_client = lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone()).await?;
     let target_bin = bin_client.bin("alice").await?;
     let _ = target_bin.list_append(&KeyValue { key: "key1".to_string(), value: "val1".to_string() }).await?;
     println!("An adventurer has joined the party!");
@@ -83,7 +83,7 @@ async fn test_single_keeper_two_backs_dead() -> TribResult<()> {
 
     let STRING_LEN = 30;
     let NUM_KEYS = 12;
-    let bin_client = lab3::new_bin_client(bft.back_addresses.clone()).await?;
+    let bin_client = lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone()).await?;
 
     for i in 0..NUM_KEYS {
         let bin_name = generate_random_username(STRING_LEN);
@@ -121,7 +121,7 @@ async fn test_single_keeper_two_backs_dead() -> TribResult<()> {
 async fn test_keeper_leave() -> TribResult<()> {
     let mut bft = BigFuckingTester::new(11, 5, vec![0, 1, 2, 3, 4], 3, vec![0, 1, 2]).await;
     
-    let bin_client = lab3::new_bin_client(bft.back_addresses.clone()).await?;
+    let bin_client = lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone()).await?;
     let target_bin = bin_client.bin("alice").await?;
     let _ = target_bin.list_append(&KeyValue { key: "key1".to_string(), value: "val1".to_string() }).await?;
 
@@ -148,7 +148,7 @@ async fn test_keeper_leave() -> TribResult<()> {
 async fn test_back_join() -> TribResult<()> {
     let mut bft = BigFuckingTester::new(12, 5, vec![0, 2, 4], 1, vec![0]).await;
     
-    let bin_client = lab3::new_bin_client(bft.back_addresses.clone()).await?;
+    let bin_client = lab3::new_bin_client_with_locks(bft.back_addresses.clone(), bft.lock_addresses.clone()).await?;
     let mut target_bin = bin_client.bin("alice").await?;
     let _ = target_bin.list_append(&KeyValue { key: "key1".to_string(), value: "val1".to_string() }).await?;
 
@@ -178,4 +178,65 @@ async fn test_back_join() -> TribResult<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_three_replicas_two_backs_dead_at_once() -> TribResult<()> {
+    let mut bft = BigFuckingTester::new_with_replicas(20, 5, vec![0, 1, 2, 3, 4], 1, vec![0], 3).await;
+    let mut key_val_map = HashMap::new();
+    let mut key_bin_map = HashMap::new();
+
+    let string_len = 30;
+    let num_keys = 12;
+    let bin_client = lab3::new_bin_client_with_replicas(bft.back_addresses.clone(), bft.lock_addresses.clone(), 3).await?;
+
+    for _ in 0..num_keys {
+        let bin_name = generate_random_username(string_len);
+        let key = generate_random_username(string_len);
+        let value = generate_random_username(string_len);
+        key_val_map.insert(key.to_string(), value.to_string());
+        key_bin_map.insert(key.to_string(), bin_name.to_string());
+        let client = bin_client.bin(bin_name.as_str()).await?;
+        client.set(&KeyValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        }).await?;
+    }
+    // both die before the keeper can migrate anything, every bin still has a copy left
+    bft.back_node_leave(0).await;
+    bft.back_node_leave(1).await;
+    for key in key_val_map.keys() {
+        let bin_name = key_bin_map.get(key).unwrap();
+        let expected_value = key_val_map.get(key).unwrap();
+        let client = bin_client.bin(bin_name.as_str()).await?;
+        assert_eq!(client.get(key).await?, Some(expected_value.to_string()));
+    }
+    bft.cleanup().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_three_replicas_join_then_two_backs_dead() -> TribResult<()> {
+    let mut bft = BigFuckingTester::new_with_replicas(21, 5, vec![0, 2, 4], 1, vec![0], 3).await;
+    let bin_client = lab3::new_bin_client_with_replicas(bft.back_addresses.clone(), bft.lock_addresses.clone(), 3).await?;
+    let mut target_bin = bin_client.bin("alice").await?;
+    let _ = target_bin.list_append(&KeyValue { key: "key1".to_string(), value: "val1".to_string() }).await?;
+
+    bft.back_join(1).await;
+    tokio::time::sleep(Duration::from_secs(15)).await;
+    bft.back_join(3).await;
+    tokio::time::sleep(Duration::from_secs(15)).await;
+
+    target_bin = bin_client.bin("alice").await?;
+    let _ = target_bin.list_append(&KeyValue { key: "key1".to_string(), value: "val2".to_string() }).await?;
+
+    // the joined backends took over their share, any two of the five may go
+    bft.back_node_leave(0).await;
+    bft.back_node_leave(2).await;
+
+    target_bin = bin_client.bin("alice").await?;
+    assert_eq!(target_bin.list_get("key1").await?.0, vec!["val1", "val2"]);
+    bft.cleanup().await;
+
+    Ok(())
+}
+
 // cargo test --package lab --test lab3_test -- test_simple_keeper_kill --exact --nocapture
\ No newline at end of file
@@ -15,6 +15,17 @@ use crate::storage::Storage;
 
 pub const DEFAULT_CONFIG_LOCATION: &str = "bins.json";
 
+/// the number of backends each bin is kept on when a config does not say
+pub const DEFAULT_REPLICAS: usize = 2;
+
+/// the number of points each backend takes on the bin placement ring when a
+/// config does not say
+pub const DEFAULT_VNODES: usize = 64;
+
+/// the number of batches of keys a migration copies at once when a config
+/// does not say
+pub const DEFAULT_MIGRATION_CONCURRENCY: usize = 4;
+
 /// a struct which represents the configuration for a particular storage backend
 pub struct BackConfig {
     /// the address `<host>:<port>` combination to serve on
@@ -52,6 +63,16 @@ pub struct KeeperConfig {
     pub backs: Vec<String>,
     /// The addresses of keepers
     pub addrs: Vec<String>,
+    /// The addresses of lock servers
+    pub locks: Vec<String>,
+    /// The number of backends each bin is kept on
+    pub replicas: usize,
+    /// The number of points each backend takes on the bin placement ring
+    pub vnodes: usize,
+    /// The number of keys per second a migration copies, 0 for no limit
+    pub migration_rate: u64,
+    /// The number of batches of keys a migration copies at once
+    pub migration_concurrency: usize,
     /// The index of this back-end
     pub this: usize,
     /// Non zero incarnation identifier
@@ -77,6 +98,38 @@ impl KeeperConfig {
 pub struct Config {
     pub backs: Vec<String>,
     pub keepers: Vec<String>,
+    /// lock server addresses, absent from configs written before lock
+    /// servers were part of the cluster
+    #[serde(default)]
+    pub locks: Vec<String>,
+    /// the number of backends each bin is kept on, [DEFAULT_REPLICAS] for
+    /// configs that do not set it
+    #[serde(default = "default_replicas")]
+    pub replicas: usize,
+    /// the number of points each backend takes on the bin placement ring,
+    /// [DEFAULT_VNODES] for configs that do not set it
+    #[serde(default = "default_vnodes")]
+    pub vnodes: usize,
+    /// the number of keys per second a migration copies, 0 for no limit as
+    /// in configs that do not set it
+    #[serde(default)]
+    pub migration_rate: u64,
+    /// the number of batches of keys a migration copies at once,
+    /// [DEFAULT_MIGRATION_CONCURRENCY] for configs that do not set it
+    #[serde(default = "default_migration_concurrency")]
+    pub migration_concurrency: usize,
+}
+
+fn default_replicas() -> usize {
+    DEFAULT_REPLICAS
+}
+
+fn default_vnodes() -> usize {
+    DEFAULT_VNODES
+}
+
+fn default_migration_concurrency() -> usize {
+    DEFAULT_MIGRATION_CONCURRENCY
 }
 
 impl Config {
@@ -126,6 +179,11 @@ impl Config {
         self.keepers.len()
     }
 
+    /// gets the total number of lock servers in the config.
+    pub fn lock_count(&self) -> usize {
+        self.locks.len()
+    }
+
     /// build a [BackConfig] for the given index `i` in the list of backend
     /// addresses. `i` must be a valid index in the list of backends.
     ///
@@ -162,6 +220,11 @@ impl Config {
         Ok(KeeperConfig {
             backs: self.backs.clone(),
             addrs: self.keepers.clone(),
+            locks: self.locks.clone(),
+            replicas: self.replicas,
+            vnodes: self.vnodes,
+            migration_rate: self.migration_rate,
+            migration_concurrency: self.migration_concurrency,
             this: i,
             id: SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)?
@@ -171,3 +234,67 @@ impl Config {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Config, DEFAULT_MIGRATION_CONCURRENCY, DEFAULT_REPLICAS, DEFAULT_VNODES};
+
+    #[test]
+    fn config_without_locks() {
+        let cfg: Config =
+            serde_json::from_str(r#"{"backs": ["127.0.0.1:3000"], "keepers": ["127.0.0.1:3001"]}"#)
+                .unwrap();
+        assert!(cfg.locks.is_empty());
+        assert_eq!(cfg.replicas, DEFAULT_REPLICAS);
+        assert_eq!(cfg.vnodes, DEFAULT_VNODES);
+        assert_eq!(cfg.migration_rate, 0);
+        assert_eq!(cfg.migration_concurrency, DEFAULT_MIGRATION_CONCURRENCY);
+    }
+
+    #[test]
+    fn keeper_config_carries_locks() {
+        let cfg = Config {
+            backs: vec!["127.0.0.1:3000".to_string()],
+            keepers: vec!["127.0.0.1:3001".to_string()],
+            locks: vec!["127.0.0.1:3002".to_string()],
+            replicas: DEFAULT_REPLICAS,
+            vnodes: DEFAULT_VNODES,
+            migration_rate: 0,
+            migration_concurrency: DEFAULT_MIGRATION_CONCURRENCY,
+        };
+        let kc = cfg.keeper_config(0, None, None).unwrap();
+        assert_eq!(kc.locks, cfg.locks);
+    }
+
+    #[test]
+    fn keeper_config_carries_replicas() {
+        let cfg: Config = serde_json::from_str(
+            r#"{"backs": ["127.0.0.1:3000"], "keepers": ["127.0.0.1:3001"], "replicas": 3}"#,
+        )
+        .unwrap();
+        let kc = cfg.keeper_config(0, None, None).unwrap();
+        assert_eq!(kc.replicas, 3);
+    }
+
+    #[test]
+    fn keeper_config_carries_vnodes() {
+        let cfg: Config = serde_json::from_str(
+            r#"{"backs": ["127.0.0.1:3000"], "keepers": ["127.0.0.1:3001"], "vnodes": 8}"#,
+        )
+        .unwrap();
+        let kc = cfg.keeper_config(0, None, None).unwrap();
+        assert_eq!(kc.vnodes, 8);
+    }
+
+    #[test]
+    fn keeper_config_carries_migration_limits() {
+        let cfg: Config = serde_json::from_str(
+            r#"{"backs": ["127.0.0.1:3000"], "keepers": ["127.0.0.1:3001"],
+                "migration_rate": 500, "migration_concurrency": 2}"#,
+        )
+        .unwrap();
+        let kc = cfg.keeper_config(0, None, None).unwrap();
+        assert_eq!(kc.migration_rate, 500);
+        assert_eq!(kc.migration_concurrency, 2);
+    }
+}
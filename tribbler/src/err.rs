@@ -27,6 +27,40 @@ pub enum TribblerError {
     WhoWhom(String),
     /// when there are no more seq numbers to give out
     MaxedSeq,
+    /// raised when a client releases locks after the lock server reclaimed
+    /// its lease
+    LeaseExpired(String),
+    /// raised when a lock request conflicts with an older transaction and
+    /// has to be retried
+    LockConflict(String),
+    /// raised when the lock server at the given address cannot be reached
+    LockUnavailable(String),
+    /// raised when acquiring locks from the given lock server takes longer
+    /// than the acquire timeout
+    LockTimeout(String),
+    /// raised when a transaction accesses a key it did not declare
+    UndeclaredTxnKey(String),
+    /// raised when a transaction call does not fit the state of the client,
+    /// like committing a transaction that was already aborted
+    InvalidTxnState(String),
+    /// raised when an optimistic transaction read a key that changed before
+    /// it could commit, and has to be retried
+    TxnValidationFailed(String),
+    /// raised when a bin written by a transaction cannot be prepared for
+    /// commit, so the transaction is aborted instead
+    TxnPrepareFailed(String),
+    /// raised when recovery recorded an abort for a transaction before its
+    /// coordinator could record the commit
+    TxnAbortedByRecovery(String),
+    /// raised when a snapshot is read at a clock whose history was already
+    /// compacted
+    SnapshotCompacted(u64),
+    /// raised when fewer replicas answered a read or acknowledged a write
+    /// than the consistency level requires, as (required, answered)
+    QuorumNotReached(usize, usize),
+    /// raised when a keeper that does not lead is asked for what only the
+    /// leader knows or does, with the leader it knows of
+    NotLeader(Option<usize>),
     /// catch-all error for other issues
     Unknown(String),
 }
@@ -45,6 +79,43 @@ impl Display for TribblerError {
             TribblerError::NotFollowing(who, whom) => format!("{} doesn't follow {}", who, whom),
             TribblerError::TribTooLong => "tribbler post exceed character limit".to_string(),
             TribblerError::WhoWhom(x) => format!("user {} can't follow themself", x),
+            TribblerError::LeaseExpired(x) => format!("lock lease of \"{}\" expired", x),
+            TribblerError::LockConflict(x) => {
+                format!("lock on \"{}\" held by an older transaction, retry", x)
+            }
+            TribblerError::LockUnavailable(x) => format!("lock server {} unavailable", x),
+            TribblerError::LockTimeout(x) => format!("timed out acquiring locks from {}", x),
+            TribblerError::UndeclaredTxnKey(x) => {
+                format!("key \"{}\" is not declared by the transaction", x)
+            }
+            TribblerError::InvalidTxnState(x) => format!("invalid transaction state: {}", x),
+            TribblerError::TxnValidationFailed(x) => {
+                format!("\"{}\" changed since the transaction read it, retry", x)
+            }
+            TribblerError::TxnPrepareFailed(x) => {
+                format!("transaction aborted, \"{}\" could not be prepared", x)
+            }
+            TribblerError::TxnAbortedByRecovery(x) => {
+                format!(
+                    "transaction {} was aborted by recovery before it committed",
+                    x
+                )
+            }
+            TribblerError::SnapshotCompacted(x) => {
+                format!("history at clock {} was compacted", x)
+            }
+            TribblerError::QuorumNotReached(required, answered) => {
+                format!(
+                    "{} of the {} required replicas answered",
+                    answered, required
+                )
+            }
+            TribblerError::NotLeader(Some(x)) => {
+                format!("keeper is not the leader, keeper {} leads", x)
+            }
+            TribblerError::NotLeader(None) => {
+                "keeper is not the leader, no keeper leads".to_string()
+            }
             TribblerError::Unknown(x) => format!("unknown error: {}", x),
             x => format!("{:?}", x),
         };